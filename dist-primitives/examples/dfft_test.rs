@@ -3,19 +3,25 @@ use dist_primitives::utils::domain_utils::EvaluationDomainExt;
 use dist_primitives::{
     channel::channel::MpcSerNet,
     dfft::dfft::{d_fft, fft_in_place_rearrange},
+    preprocessing::{KingDealer, Preprocessing},
     utils::pack::transpose,
     Opt,
 };
 use ff::{PrimeField, WithSmallOrderMulGroup};
 use halo2_proofs::{halo2curves::bn256::Fr, poly::EvaluationDomain};
-use mpc_net::{MpcMultiNet as Net, MpcNet};
+use mpc_net::{MpcNetwork, NetError};
 use secret_sharing::pss::PackedSharingParams;
 use serde::{Deserialize, Serialize};
 use structopt::StructOpt;
 
-pub fn d_fft_test<F>(pp: &PackedSharingParams<F>, dom: &EvaluationDomain<F>)
+pub fn d_fft_test<F, N>(
+    pp: &PackedSharingParams<F>,
+    dom: &EvaluationDomain<F>,
+    net: &N,
+) -> Result<(), NetError>
 where
     F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    N: MpcNetwork,
 {
     let mbyl: usize = dom.size() / pp.l;
     // We apply FFT on this vector
@@ -38,37 +44,38 @@ where
 
     let pcoeff_share = pcoeff
         .iter()
-        .map(|x| x[Net::party_id()])
+        .map(|x| x[net.party_id()])
         .collect::<Vec<_>>();
 
     // Rearranging x
     let myfft_timer = start_timer!(|| "Distributed FFT");
 
-    let peval_share = d_fft(pcoeff_share, false, 1, false, dom, pp);
+    let masks = KingDealer::new(net).fft_masks(false, false, 1, false, dom, pp)?;
+    let peval_share = d_fft(pcoeff_share, false, 1, false, masks, dom, net, pp)?;
     end_timer!(myfft_timer);
 
     // Send to king who reconstructs and checks the answer
-    Net::send_to_king(&peval_share).map(|peval_shares| {
+    net.send_to_king(&peval_share)?.map(|peval_shares| {
         let peval_shares = transpose(peval_shares);
 
-        let mut pevals: Vec<F> = peval_shares
+        let pevals: Vec<F> = peval_shares
             .into_iter()
             .flat_map(|x| pp.unpack(&x))
             .collect();
-        pevals.reverse(); // todo: implement such that we avoid this reverse
 
-        if Net::am_king() {
+        if net.am_king() {
             assert_eq!(should_be_output, pevals);
         }
     });
+    Ok(())
 }
 
-pub fn main() {
+pub fn main() -> Result<(), NetError> {
     env_logger::builder().format_timestamp(None).init();
 
     let opt = Opt::from_args();
 
-    Net::init_from_file(opt.input.to_str().unwrap(), opt.id);
+    let net = opt.network()?;
     let pp = PackedSharingParams::<Fr>::new(opt.l);
     let dom = EvaluationDomain::<Fr>::new(1, (opt.m as f64).log2() as u32);
     debug_assert_eq!(
@@ -77,7 +84,7 @@ pub fn main() {
         "Failed to obtain domain of size {}",
         opt.m
     );
-    d_fft_test::<Fr>(&pp, &dom);
-
-    Net::deinit();
+    d_fft_test::<Fr, _>(&pp, &dom, &net.with_kings(opt.kings()))?;
+    opt.write_stats(&net.stats());
+    Ok(())
 }
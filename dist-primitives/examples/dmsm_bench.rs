@@ -1,5 +1,6 @@
 use ark_std::{end_timer, start_timer, UniformRand, Zero};
 use dist_primitives::utils::domain_utils::EvaluationDomainExt;
+use dist_primitives::preprocessing::KingDealer;
 use dist_primitives::{dmsm::dmsm::d_msm, Opt};
 use ff::Field;
 use ff::{PrimeField, WithSmallOrderMulGroup};
@@ -9,15 +10,20 @@ use halo2_proofs::{
     poly::EvaluationDomain,
 };
 use halo2curves::pairing::Engine;
-use mpc_net::{MpcMultiNet as Net, MpcNet};
+use mpc_net::{MpcNetwork, NetError};
 use secret_sharing::pss::PackedSharingParams;
+use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 use structopt::StructOpt;
 
-pub fn d_msm_test<E: Engine>(pp: &PackedSharingParams<E::Scalar>, dom: &EvaluationDomain<E::Scalar>)
+pub fn d_msm_test<E: Engine, N: MpcNetwork>(
+    pp: &PackedSharingParams<E::Scalar>,
+    dom: &EvaluationDomain<E::Scalar>,
+    net: &N,
+) -> Result<(), NetError>
 where
     E: Engine + Debug,
-    E::Scalar: PrimeField + WithSmallOrderMulGroup<3>,
+    E::Scalar: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
 {
     // let m = pp.l*4;
     // let case_timer = start_timer!(||"affinemsm_test");
@@ -35,23 +41,24 @@ where
     }
 
     let dmsm = start_timer!(|| "Distributed msm");
-    d_msm::<E>(&x_share, &y_share, pp);
+    d_msm::<E, _, _>(&x_share, &y_share, &mut KingDealer::new(net), net, pp)?;
     end_timer!(dmsm);
+    Ok(())
 }
 
-fn main() {
+fn main() -> Result<(), NetError> {
     env_logger::builder().format_timestamp(None).init();
 
     let opt = Opt::from_args();
 
-    Net::init_from_file(opt.input.to_str().unwrap(), opt.id);
+    let net = opt.network()?;
 
     let pp = PackedSharingParams::<Fr>::new(opt.l);
     for i in 10..20 {
         let dom = EvaluationDomain::<Fr>::new(1, i);
         println!("domain size: {}", dom.size());
-        d_msm_test::<Bn256>(&pp, &dom);
+        d_msm_test::<Bn256, _>(&pp, &dom, &net)?;
     }
-
-    Net::deinit();
+    opt.write_stats(&net.stats());
+    Ok(())
 }
@@ -1,6 +1,7 @@
 use ark_std::{end_timer, start_timer};
 use dist_primitives::dmsm::dmsm::packexp_from_public;
 use dist_primitives::utils::domain_utils::EvaluationDomainExt;
+use dist_primitives::preprocessing::KingDealer;
 use dist_primitives::{dmsm::dmsm::d_msm, Opt};
 use ff::Field;
 use ff::{PrimeField, WithSmallOrderMulGroup};
@@ -12,15 +13,20 @@ use halo2_proofs::{
     poly::EvaluationDomain,
 };
 use halo2curves::pairing::Engine;
-use mpc_net::{MpcMultiNet as Net, MpcNet};
+use mpc_net::{MpcNetwork, NetError};
 use secret_sharing::pss::PackedSharingParams;
+use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 use structopt::StructOpt;
 
-pub fn d_msm_test<E: Engine>(pp: &PackedSharingParams<E::Scalar>, dom: &EvaluationDomain<E::Scalar>)
+pub fn d_msm_test<E: Engine, N: MpcNetwork>(
+    pp: &PackedSharingParams<E::Scalar>,
+    dom: &EvaluationDomain<E::Scalar>,
+    net: &N,
+) -> Result<(), NetError>
 where
     E: Engine + Debug,
-    E::Scalar: PrimeField + WithSmallOrderMulGroup<3>,
+    E::Scalar: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
 {
     // let m = pp.l*4;
     // let case_timer = start_timer!(||"affinemsm_test");
@@ -39,12 +45,12 @@ where
 
     let x_share: Vec<E::G1> = x_pub
         .chunks(pp.l)
-        .map(|s| packexp_from_public::<E>(&s.to_vec(), &pp)[Net::party_id()])
+        .map(|s| packexp_from_public::<E>(&s.to_vec(), &pp)[net.party_id()])
         .collect();
 
     let y_share: Vec<E::Scalar> = y_pub
         .chunks(pp.l)
-        .map(|s| pp.pack_from_public(&s.to_vec())[Net::party_id()])
+        .map(|s| pp.pack_from_public(&s.to_vec())[net.party_id()])
         .collect();
 
     // Will be comparing against this in the end
@@ -59,24 +65,23 @@ where
     end_timer!(nmsm);
 
     let dmsm = start_timer!(|| "Distributed msm");
-    let output = d_msm::<E>(&x_share, &y_share, pp);
+    let output = d_msm::<E, _, _>(&x_share, &y_share, &mut KingDealer::new(net), net, pp)?;
     end_timer!(dmsm);
 
-    if Net::am_king() {
+    if net.am_king() {
         assert_eq!(should_be_output, output);
     }
+    Ok(())
 }
 
-fn main() {
+fn main() -> Result<(), NetError> {
     env_logger::builder().format_timestamp(None).init();
 
     let opt = Opt::from_args();
 
-    Net::init_from_file(opt.input.to_str().unwrap(), opt.id);
+    let net = opt.network()?;
 
     let pp = PackedSharingParams::<Fr>::new(opt.l);
     let dom = EvaluationDomain::<Fr>::new(1, (opt.m as f64).log2() as u32);
-    d_msm_test::<Bn256>(&pp, &dom);
-
-    Net::deinit();
+    d_msm_test::<Bn256, _>(&pp, &dom, &net)
 }
@@ -2,19 +2,25 @@ use dist_primitives::utils::domain_utils::EvaluationDomainExt;
 use dist_primitives::{
     channel::channel::MpcSerNet,
     dpp::dpp::d_pp,
+    preprocessing::{KingDealer, Preprocessing},
     utils::pack::{pack_vec, transpose},
     Opt,
 };
 use ff::{PrimeField, WithSmallOrderMulGroup};
 use halo2_proofs::{halo2curves::bn256::Fr, poly::EvaluationDomain};
-use mpc_net::{MpcMultiNet as Net, MpcNet};
+use mpc_net::{MpcNetwork, NetError};
 use secret_sharing::pss::PackedSharingParams;
 use serde::{Deserialize, Serialize};
 use structopt::StructOpt;
 
-pub fn d_pp_test<F>(pp: &PackedSharingParams<F>, dom: &EvaluationDomain<F>)
+pub fn d_pp_test<F, N>(
+    pp: &PackedSharingParams<F>,
+    dom: &EvaluationDomain<F>,
+    net: &N,
+) -> Result<(), NetError>
 where
     F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    N: MpcNetwork,
 {
     // We apply FFT on this vector
     // let mut x = vec![F::ONE; cd.m];
@@ -29,11 +35,12 @@ where
     // pack x
     let px = transpose(pack_vec(&x, pp));
 
-    let px_share = px[Net::party_id()].clone();
-    let pp_px_share = d_pp(px_share.clone(), px_share.clone(), pp);
+    let px_share = px[net.party_id()].clone();
+    let masks = KingDealer::new(net).dpp_masks(dom.size(), pp)?;
+    let pp_px_share = d_pp(px_share.clone(), px_share.clone(), masks, net, pp)?;
 
     // Send to king who reconstructs and checks the answer
-    Net::send_to_king(&pp_px_share).map(|pp_px_shares| {
+    net.send_to_king(&pp_px_share)?.map(|pp_px_shares| {
         let pp_px_shares = transpose(pp_px_shares);
 
         let pp_px: Vec<F> = pp_px_shares
@@ -41,21 +48,20 @@ where
             .flat_map(|x| pp.unpack(&x))
             .collect();
 
-        if Net::am_king() {
+        if net.am_king() {
             debug_assert_eq!(should_be_output, pp_px);
         }
     });
+    Ok(())
 }
 
-pub fn main() {
+pub fn main() -> Result<(), NetError> {
     env_logger::builder().format_timestamp(None).init();
 
     let opt = Opt::from_args();
 
-    Net::init_from_file(opt.input.to_str().unwrap(), opt.id);
+    let net = opt.network()?;
     let pp = PackedSharingParams::<Fr>::new(opt.l);
     let cd = EvaluationDomain::<Fr>::new(1, (opt.m as f64).log2() as u32);
-    d_pp_test::<Fr>(&pp, &cd);
-
-    Net::deinit();
+    d_pp_test::<Fr, _>(&pp, &cd, &net)
 }
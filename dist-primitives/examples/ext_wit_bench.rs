@@ -0,0 +1,87 @@
+use ark_std::{end_timer, start_timer};
+use dist_primitives::{
+    channel::channel::MpcSerNet,
+    dwit::dwit::{d_eval, eval_shape, Circuit, Gate},
+    preprocessing::{dealer, store::Store},
+    utils::pack::{pack_vec, transpose},
+    Opt,
+};
+use ff::{Field, PrimeField, WithSmallOrderMulGroup};
+use halo2_proofs::halo2curves::bn256::Fr;
+use mpc_net::{MpcNetwork, NetError};
+use secret_sharing::pss::PackedSharingParams;
+use serde::{Deserialize, Serialize};
+use structopt::StructOpt;
+
+/// Rounds of the MiMC style permutation
+const ROUNDS: usize = 16;
+
+/// x <- (x + k + c_i)^3 for every round on every row, then every row moves to the next one
+fn mimc<F: PrimeField>(m: usize) -> Circuit<F> {
+    let mut circuit = Circuit::new(m, 2);
+    let (mut x, k) = (0, 1);
+    for round in 0..ROUNDS {
+        let constants = (0..m).map(|row| F::from((round * m + row) as u64)).collect();
+        let sum = circuit.gate(Gate::Add(x, k));
+        let sum = circuit.gate(Gate::AddPublic(sum, constants));
+        let square = circuit.gate(Gate::Mul(sum, sum));
+        x = circuit.gate(Gate::Mul(square, sum));
+    }
+    circuit.gate(Gate::Route((0..m).map(|row| (x, (row + 1) % m)).collect()));
+    circuit
+}
+
+pub fn d_ext_wit_test<F, N>(m: usize, pp: &PackedSharingParams<F>, net: &N) -> Result<(), NetError>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    N: MpcNetwork,
+{
+    let rng = &mut ark_std::test_rng();
+    let circuit = mimc::<F>(m);
+    let inputs: Vec<Vec<F>> = (0..2)
+        .map(|_| (0..m).map(|_| F::random(&mut *rng)).collect())
+        .collect();
+    let input_shares = inputs
+        .iter()
+        .map(|input| transpose(pack_vec(input, pp))[net.party_id()].clone())
+        .collect();
+
+    // Offline: king records the shape of the circuit and plays the trusted dealer
+    let stores = if net.am_king() {
+        let shape = eval_shape(&circuit, pp)?;
+        Some(dealer::deal(&shape, pp, &mut ark_std::test_rng()))
+    } else {
+        None
+    };
+    let mut store: Store<F> = net.recv_from_king(stores)?;
+
+    // Online: the wires are the advice columns of the extended witness, plonk's d_share_bundle
+    // turns them into the share bundle of d_create_proof_from_shares
+    let ext_wit = start_timer!(|| "Distributed extended witness");
+    let wires = d_eval(&circuit, input_shares, &mut store, net, pp)?;
+    end_timer!(ext_wit);
+    assert!(store.is_empty(), "the shape of the circuit changed");
+
+    // Send the last wire to king who reconstructs and checks it
+    net.send_to_king(wires.last().unwrap())?.map(|shares| {
+        let output: Vec<F> = transpose(shares).iter().flat_map(|x| pp.unpack(x)).collect();
+        assert_eq!(&output, circuit.eval(inputs).last().unwrap());
+    });
+    Ok(())
+}
+
+fn main() -> Result<(), NetError> {
+    env_logger::builder().format_timestamp(None).init();
+
+    let opt = Opt::from_args();
+
+    let net = opt.network()?;
+
+    let pp = PackedSharingParams::<Fr>::new(opt.l);
+    for i in 10..16 {
+        println!("rows: {}", 1 << i);
+        d_ext_wit_test::<Fr, _>(1 << i, &pp, &net)?;
+    }
+    opt.write_stats(&net.stats());
+    Ok(())
+}
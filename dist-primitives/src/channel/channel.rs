@@ -1,40 +1,153 @@
 use bincode;
-use mpc_net::MpcNet;
+use mpc_net::{Kings, MpcNetwork, NetError};
 use serde::{Deserialize, Serialize};
 
-pub trait MpcSerNet: MpcNet {
+use crate::utils::pack::transpose;
+
+/// Decodes the message of party peer
+fn deserialize<T: for<'de> Deserialize<'de>>(peer: usize, bytes: &[u8]) -> Result<T, NetError> {
+    bincode::deserialize(bytes).map_err(|e| NetError::Deserialization {
+        peer,
+        reason: e.to_string(),
+    })
+}
+
+pub trait MpcSerNet: MpcNetwork {
+    #[inline]
+    fn broadcast<T: for<'de> Deserialize<'de> + Serialize>(
+        &self,
+        out: &T,
+    ) -> Result<Vec<T>, NetError> {
+        let bytes_out = bincode::serialize(out).unwrap();
+        let bytes_in = self.broadcast_bytes(&bytes_out)?;
+        bytes_in
+            .iter()
+            .enumerate()
+            .map(|(peer, b)| deserialize(peer, b))
+            .collect()
+    }
+
+    /// All parties send out to king, who gets them in the order of parties
     #[inline]
-    fn broadcast<T: for<'de> Deserialize<'de> + Serialize>(out: &T) -> Vec<T> {
+    fn gather<T: for<'de> Deserialize<'de> + Serialize>(
+        &self,
+        king: usize,
+        out: &T,
+    ) -> Result<Option<Vec<T>>, NetError> {
         let bytes_out = bincode::serialize(out).unwrap();
-        let bytes_in = Self::broadcast_bytes(&bytes_out);
+        self.gather_bytes(king, &bytes_out)?
+            .map(|bytes_in| {
+                bytes_in
+                    .iter()
+                    .enumerate()
+                    .map(|(peer, b)| deserialize(peer, b))
+                    .collect()
+            })
+            .transpose()
+    }
+
+    /// King sends outs[i] to party i, provide outs iff you're king
+    #[inline]
+    fn scatter<T: for<'de> Deserialize<'de> + Serialize>(
+        &self,
+        king: usize,
+        outs: Option<Vec<T>>,
+    ) -> Result<T, NetError> {
+        let bytes_in = self.scatter_bytes(
+            king,
+            outs.map(|outs| {
+                outs.iter()
+                    .map(|out| bincode::serialize(out).unwrap())
+                    .collect()
+            }),
+        )?;
+        deserialize(king, &bytes_in)
+    }
+
+    /// All parties send out to the leading king
+    #[inline]
+    fn send_to_king<T: for<'de> Deserialize<'de> + Serialize>(
+        &self,
+        out: &T,
+    ) -> Result<Option<Vec<T>>, NetError> {
+        self.gather(self.kings().leader(), out)
+    }
+
+    /// All parties recv from the leading king, provide out iff you're the king
+    #[inline]
+    fn recv_from_king<T: for<'de> Deserialize<'de> + Serialize>(
+        &self,
+        out: Option<Vec<T>>,
+    ) -> Result<T, NetError> {
+        self.scatter(self.kings().leader(), out)
+    }
+
+    /// Every party sends its share of every column to the king of the column, see Kings.
+    /// The king maps the n shares of a column to the n shares of the output column with f,
+    /// so the columns of every king are handled in parallel.
+    fn king_columns<T, U, G>(&self, shares: Vec<T>, f: G) -> Result<Vec<U>, NetError>
+    where
+        T: for<'de> Deserialize<'de> + Serialize + Clone,
+        U: for<'de> Deserialize<'de> + Serialize,
+        G: Fn(Vec<T>) -> Vec<U>,
+    {
+        let open = |columns: Vec<Vec<T>>| -> Vec<Vec<U>> {
+            transpose(transpose(columns).into_iter().map(&f).collect())
+        };
+        let kings = self.kings();
+        if let Kings::One(king) = kings {
+            let king_answer = self.gather(king, &shares)?.map(open);
+            return self.scatter(king, king_answer);
+        }
+
+        let n = self.n_parties();
+        let ranges = kings.ranges(n, shares.len());
+        let outs: Vec<Vec<T>> = ranges.iter().map(|r| shares[r.clone()].to_vec()).collect();
+        let columns = self.all_to_all(&outs)?;
+        let answers = if ranges[self.party_id()].is_empty() {
+            (0..n).map(|_| Vec::new()).collect()
+        } else {
+            open(columns)
+        };
+        Ok(self.all_to_all(&answers)?.into_iter().flatten().collect())
+    }
+
+    /// Sends outs[i] privately to party i and returns the messages of every party to us
+    #[inline]
+    fn all_to_all<T: for<'de> Deserialize<'de> + Serialize>(
+        &self,
+        outs: &[T],
+    ) -> Result<Vec<T>, NetError> {
+        let bytes_out = outs
+            .iter()
+            .map(|out| bincode::serialize(out).unwrap())
+            .collect();
+        let bytes_in = self.all_to_all_bytes(bytes_out)?;
         bytes_in
-            .into_iter()
-            .map(|b| bincode::deserialize(&b[..]).unwrap())
+            .iter()
+            .enumerate()
+            .map(|(peer, b)| deserialize(peer, b))
             .collect()
     }
 
     #[inline]
-    fn send_to_king<T: for<'de> Deserialize<'de> + Serialize>(out: &T) -> Option<Vec<T>> {
+    fn send_to<T: for<'de> Deserialize<'de> + Serialize>(
+        &self,
+        party: usize,
+        out: &T,
+    ) -> Result<(), NetError> {
         let bytes_out = bincode::serialize(out).unwrap();
-        Self::send_bytes_to_king(&bytes_out).map(|bytes_in| {
-            bytes_in
-                .into_iter()
-                .map(|b| bincode::deserialize(&b[..]).unwrap())
-                .collect()
-        })
+        self.send_bytes_to(party, &bytes_out)
     }
 
     #[inline]
-    fn recv_from_king<T: for<'de> Deserialize<'de> + Serialize>(out: Option<Vec<T>>) -> T {
-        let bytes_in = Self::recv_bytes_from_king(out.map(|outs| {
-            outs.iter()
-                .map(|out| {
-                    bincode::serialize(out).unwrap()
-                })
-                .collect()
-        }));
-        bincode::deserialize(&bytes_in[..]).unwrap()
+    fn recv_from<T: for<'de> Deserialize<'de> + Serialize>(
+        &self,
+        party: usize,
+    ) -> Result<T, NetError> {
+        let bytes_in = self.recv_bytes_from(party)?;
+        deserialize(party, &bytes_in)
     }
 }
 
-impl<N: MpcNet> MpcSerNet for N {}
+impl<N: MpcNetwork> MpcSerNet for N {}
@@ -1,13 +1,15 @@
 use crate::{
     channel::channel::MpcSerNet,
+    preprocessing::dfft::FftMasks,
     utils::domain_utils::EvaluationDomainExt,
-    utils::pack::{pack_vec, transpose},
+    utils::pack::transpose,
+    utils::reshare::d_reshare,
 };
 use ark_std::{end_timer, log2, start_timer};
 use ff::{PrimeField, WithSmallOrderMulGroup};
 use halo2_proofs::poly::EvaluationDomain;
-use log::debug;
-use mpc_net::{MpcMultiNet as Net, MpcNet};
+use log::{debug, warn};
+use mpc_net::{label, Kings, MpcNetwork, NetError};
 use secret_sharing::pss::PackedSharingParams;
 use serde::{Deserialize, Serialize};
 use std::mem;
@@ -16,81 +18,169 @@ use std::mem;
 /// rearrange: whether or not to rearrange output shares
 /// pad: whether or not to pad output shares with zeros
 /// degree2: whether or not to do degree reduction n the input shares
-pub fn d_fft<F>(
-    mut pcoeff_share: Vec<F>,
+/// masks: preprocessed shares of (r, FFT(r)) for the same rearrange, pad, degree2 and domain, so the king only sees masked values
+pub fn d_fft<F, N: MpcNetwork>(
+    pcoeff_share: Vec<F>,
     rearrange: bool,
     pad: usize,
     degree2: bool,
+    masks: FftMasks<F>,
     dom: &EvaluationDomain<F>,
+    net: &N,
     pp: &PackedSharingParams<F>,
-) -> Vec<F>
+) -> Result<Vec<F>, NetError>
 where
     F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
 {
-    debug_assert_eq!(
-        pcoeff_share.len() * pp.l,
-        dom.size(),
-        "Mismatch of size in FFT, {}, {}.",
-        pcoeff_share.len() * pp.l,
-        dom.size()
-    );
+    let pevals = vec![pcoeff_share];
+    let mut pevals = d_fft_batch(pevals, rearrange, pad, degree2, vec![masks], dom, net, pp)?;
+    Ok(pevals.pop().unwrap())
+}
+
+pub fn d_ifft<F, N: MpcNetwork>(
+    peval_share: Vec<F>,
+    rearrange: bool,
+    pad: usize,
+    degree2: bool,
+    masks: FftMasks<F>,
+    dom: &EvaluationDomain<F>,
+    net: &N,
+    pp: &PackedSharingParams<F>,
+) -> Result<Vec<F>, NetError>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+{
+    let pcoeffs = vec![peval_share];
+    let mut pcoeffs = d_ifft_batch(pcoeffs, rearrange, pad, degree2, vec![masks], dom, net, pp)?;
+    Ok(pcoeffs.pop().unwrap())
+}
+
+/// d_fft of several polynomials over the same domain, with a single round to and from the
+/// kings for all of them. Every polynomial has its own masks.
+pub fn d_fft_batch<F, N: MpcNetwork>(
+    mut pcoeff_shares: Vec<Vec<F>>,
+    rearrange: bool,
+    pad: usize,
+    degree2: bool,
+    masks: Vec<FftMasks<F>>,
+    dom: &EvaluationDomain<F>,
+    net: &N,
+    pp: &PackedSharingParams<F>,
+) -> Result<Vec<Vec<F>>, NetError>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+{
+    check_packing(dom, pp);
+    for pcoeff_share in pcoeff_shares.iter() {
+        debug_assert_eq!(
+            pcoeff_share.len() * pp.l,
+            dom.size(),
+            "Mismatch of size in FFT, {}, {}.",
+            pcoeff_share.len() * pp.l,
+            dom.size()
+        );
+    }
+    let _label = label("dfft");
 
     // Parties apply FFT1 locally
-    fft1_in_place(&mut pcoeff_share, dom, pp);
+    for pcoeff_share in pcoeff_shares.iter_mut() {
+        fft1_in_place(pcoeff_share, dom.get_omega(), dom, pp);
+    }
     // King applies FFT2 and parties receive shares of evals
-    fft2_with_rearrange_pad(pcoeff_share, rearrange, pad, degree2, dom, pp)
+    fft2_with_rearrange_pad(
+        pcoeff_shares,
+        dom.get_omega(),
+        rearrange,
+        pad,
+        degree2,
+        masks,
+        dom,
+        net,
+        pp,
+    )
 }
 
-pub fn d_ifft<F>(
-    mut peval_share: Vec<F>,
+/// d_ifft of several polynomials over the same domain, see d_fft_batch
+pub fn d_ifft_batch<F, N: MpcNetwork>(
+    mut peval_shares: Vec<Vec<F>>,
     rearrange: bool,
     pad: usize,
     degree2: bool,
+    masks: Vec<FftMasks<F>>,
     dom: &EvaluationDomain<F>,
+    net: &N,
     pp: &PackedSharingParams<F>,
-) -> Vec<F>
+) -> Result<Vec<Vec<F>>, NetError>
 where
     F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
 {
-    debug_assert_eq!(
-        peval_share.len() * pp.l,
-        dom.size(),
-        "Mismatch of size in IFFT, {}, {}.",
-        peval_share.len() * pp.l,
-        dom.size()
-    );
+    check_packing(dom, pp);
+    for peval_share in peval_shares.iter() {
+        debug_assert_eq!(
+            peval_share.len() * pp.l,
+            dom.size(),
+            "Mismatch of size in IFFT, {}, {}.",
+            peval_share.len() * pp.l,
+            dom.size()
+        );
+    }
 
-    let sizeinv = F::from(dom.size() as u64).invert().unwrap();
-    peval_share.iter_mut().for_each(|x| *x = *x * sizeinv);
+    let _label = label("difft");
 
-    // Parties apply FFT1 locally
-    fft1_in_place(&mut peval_share, dom, pp);
+    let sizeinv = F::from(dom.size() as u64).invert().unwrap();
+    for peval_share in peval_shares.iter_mut() {
+        peval_share.iter_mut().for_each(|x| *x = *x * sizeinv);
+        // Parties apply FFT1 locally
+        fft1_in_place(peval_share, dom.get_omega_inv(), dom, pp);
+    }
     // King applies FFT2 and parties receive shares of evals
-    fft2_with_rearrange_pad(peval_share, rearrange, pad, degree2, dom, pp)
+    fft2_with_rearrange_pad(
+        peval_shares,
+        dom.get_omega_inv(),
+        rearrange,
+        pad,
+        degree2,
+        masks,
+        dom,
+        net,
+        pp,
+    )
+}
+
+/// FFT1 and FFT2 split the radix 2 butterflies at l, PackedSharingParams accepts any l but the
+/// FFTs only work when l is a power of two that divides the domain size
+pub(crate) fn check_packing<F: PrimeField>(
+    dom: &EvaluationDomain<F>,
+    pp: &PackedSharingParams<F>,
+) {
+    assert!(
+        pp.l.is_power_of_two() && dom.size() % pp.l == 0,
+        "d_fft needs l to be a power of two dividing the domain size, got l = {} for size {}",
+        pp.l,
+        dom.size()
+    );
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
-fn fft1_in_place<F>(px: &mut Vec<F>, dom: &EvaluationDomain<F>, pp: &PackedSharingParams<F>)
+fn fft1_in_place<F>(px: &mut Vec<F>, omega: F, dom: &EvaluationDomain<F>, pp: &PackedSharingParams<F>)
 where
     F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
 {
     // FFT1 computation done locally on a vector of shares
     debug_assert_eq!(
-        dom.get_omega_inv().pow(&[(px.len() * pp.l) as u64]),
+        omega.pow(&[(px.len() * pp.l) as u64]),
         F::ONE,
         "Mismatch of size in FFT1, input:{}",
         px.len()
     );
 
     let now = start_timer!(|| "FFT1");
-    if Net::am_king() {
-        debug!("Applying fft1");
-    }
+    debug!("Applying fft1");
 
     // fft1
     for i in (log2(pp.l) + 1..=log2(dom.size())).rev() {
         let poly_size = dom.size() / 2usize.pow(i);
-        let factor_stride = dom.get_omega_inv().pow(&[2usize.pow(i - 1) as u64]);
+        let factor_stride = omega.pow(&[2usize.pow(i - 1) as u64]);
         let mut factor = factor_stride;
         for k in 0..poly_size {
             for j in 0..2usize.pow(i - 1) / pp.l {
@@ -105,13 +195,12 @@ where
 
     end_timer!(now);
 
-    if Net::am_king() {
-        debug!("Finished fft1");
-    }
+    debug!("Finished fft1");
 }
 
 fn fft2_in_place<F: PrimeField>(
     s1: &mut Vec<F>,
+    omega: F,
     dom: &EvaluationDomain<F>,
     pp: &PackedSharingParams<F>,
 ) where
@@ -122,14 +211,12 @@ fn fft2_in_place<F: PrimeField>(
     let now = start_timer!(|| "FFT2");
     let mut s2 = vec![F::ZERO; s1.len()]; //Remove this time permitting
 
-    if Net::am_king() {
-        debug!("Applying fft2");
-    }
+    debug!("Applying fft2");
 
     // fft2
     for i in (1..=log2(pp.l)).rev() {
         let poly_size = dom.size() / 2usize.pow(i);
-        let factor_stride = dom.get_omega_inv().pow(&[2usize.pow(i - 1) as u64]);
+        let factor_stride = omega.pow(&[2usize.pow(i - 1) as u64]);
         let mut factor = factor_stride;
         for k in 0..poly_size {
             for j in 0..2usize.pow(i - 1) {
@@ -143,96 +230,267 @@ fn fft2_in_place<F: PrimeField>(
         mem::swap(s1, &mut s2);
     }
 
-    // s1.rotate_right(1);
+    // the butterflies above start from omega instead of one, which shifts the output by one
+    s1.rotate_right(1);
 
     end_timer!(now);
 
-    if Net::am_king() {
-        debug!("Finished fft2");
+    debug!("Finished fft2");
+}
+
+/// FFT2 followed by the optional padding and rearranging, all of which are linear.
+/// Returns the values in the order they are packed, l consecutive values per share.
+pub(crate) fn fft2_rearrange_pad<F>(
+    mut s1: Vec<F>,
+    omega: F,
+    rearrange: bool,
+    pad: usize,
+    dom: &EvaluationDomain<F>,
+    pp: &PackedSharingParams<F>,
+) -> Vec<F>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+{
+    fft2_in_place(&mut s1, omega, dom, pp); // s1 constains final output now
+
+    // Optionally double length by padding zeros here
+    if pad > 1 {
+        s1.resize(pad * s1.len(), F::ZERO);
+    }
+
+    // Optionally rearrange to get ready for next FFT/IFFT
+    if rearrange == true {
+        fft_in_place_rearrange(&mut s1);
+        let mbyl = s1.len() / pp.l;
+        s1 = (0..mbyl)
+            .flat_map(|i| s1.iter().skip(i).step_by(mbyl).cloned().collect::<Vec<_>>())
+            .collect();
     }
+
+    s1
 }
 
-/// Send shares after fft1 to king who finishes the protocol and returns packed shares
-fn fft2_with_rearrange_pad<F: PrimeField>(
-    px: Vec<F>,
+/// Send masked shares after fft1 to the kings who finish the protocol and return packed shares.
+/// All the polynomials go in a single message.
+fn fft2_with_rearrange_pad<F: PrimeField, N: MpcNetwork>(
+    px: Vec<Vec<F>>,
+    omega: F,
     rearrange: bool,
     pad: usize,
     degree2: bool,
+    masks: Vec<FftMasks<F>>,
     dom: &EvaluationDomain<F>,
+    net: &N,
     pp: &PackedSharingParams<F>,
-) -> Vec<F>
+) -> Result<Vec<Vec<F>>, NetError>
 where
     F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
 {
     // King applies FFT2 with rearrange
 
-    let mbyl = px.len();
-    println!("mbyl: {}", mbyl);
-
-    let communication_timer = start_timer!(|| "ComToKing");
-    let received_shares = Net::send_to_king(&px);
-    end_timer!(communication_timer);
+    let mbyl = dom.size() / pp.l;
+    debug_assert_eq!(masks.len(), px.len(), "Mismatch of size in FFT masks");
+    for masks in masks.iter() {
+        debug_assert_eq!(masks.mask.len(), mbyl, "Mismatch of size in FFT masks");
+        debug_assert_eq!(
+            masks.transformed.len(),
+            pad * mbyl,
+            "Mismatch of size in FFT masks"
+        );
+    }
+    if px.is_empty() {
+        return Ok(Vec::new());
+    }
 
-    let king_answer = received_shares.map(|all_shares| {
-        let all_shares = transpose(all_shares);
-        let mut s1: Vec<F> = vec![F::ZERO; px.len() * pp.l];
-
-        let open_shares_timer = start_timer!(|| "Opening shares");
-        for i in 0..mbyl {
-            let tmp: Vec<F>;
-            if degree2 == true {
-                tmp = pp.unpack2(&all_shares[i]);
-            } else {
-                tmp = pp.unpack(&all_shares[i]);
-            }
+    let px: Vec<F> = px
+        .iter()
+        .zip(masks.iter())
+        .flat_map(|(px, masks)| px.iter().zip(masks.mask.iter()).map(|(x, r)| *x + r))
+        .collect();
 
-            for j in 0..pp.l {
-                s1[i * pp.l + j] = tmp[j];
-            }
+    let _label = label("fft2");
+    if let Kings::Spread(k) = net.kings() {
+        if mbyl % pp.l != 0 {
+            warn!(
+                "FFT2 of size {} does not split into groups of {} for {} kings, the leader runs it",
+                dom.size(),
+                pp.l,
+                k
+            );
+        }
+    }
+    let got_from_king = match net.kings() {
+        // The kings transform disjoint blocks, nobody holds all the values
+        Kings::Spread(_) if mbyl % pp.l == 0 => {
+            let count = masks.len();
+            fft2_transposed(px, count, omega, rearrange, pad, degree2, dom, net, pp)?
         }
-        end_timer!(open_shares_timer);
+        _ => d_reshare(px, degree2, net, pp, |s1| {
+            s1.chunks(dom.size())
+                .flat_map(|s1| fft2_rearrange_pad(s1.to_vec(), omega, rearrange, pad, dom, pp))
+                .collect()
+        })?,
+    };
 
-        fft2_in_place(&mut s1, dom, pp); // s1 constains final output now
+    // Parties remove the transformed masks locally
+    Ok(got_from_king
+        .chunks(pad * mbyl)
+        .zip(masks.iter())
+        .map(|(x, masks)| {
+            x.iter()
+                .zip(masks.transformed.iter())
+                .map(|(x, r)| *x - r)
+                .collect()
+        })
+        .collect())
+}
 
-        // Optionally double length by padding zeros here
-        if pad > 1 {
-            s1.resize(pad * s1.len(), F::ZERO);
-        }
+/// FFT2 of the l values packed at position k, the butterflies that fft2_in_place applies to
+/// them and to nothing else. Value a of the result is output (k + 1 + a * m/l) % m, the shift
+/// being the rotation at the end of fft2_in_place.
+fn fft2_block<F>(
+    values: Vec<F>,
+    k: usize,
+    omega: F,
+    dom: &EvaluationDomain<F>,
+    pp: &PackedSharingParams<F>,
+) -> Vec<F>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+{
+    let mbyl = dom.size() / pp.l;
+    // Every group has the offset of its outputs from k and its values
+    let mut groups = vec![(0, values)];
+    for i in (1..=log2(pp.l)).rev() {
+        let poly_size = dom.size() / 2usize.pow(i);
+        let half = 2usize.pow(i - 1);
+        groups = groups
+            .into_iter()
+            .flat_map(|(offset, values)| {
+                let factor = omega.pow(&[(half * (k + offset + 1)) as u64]);
+                let (sum, diff): (Vec<F>, Vec<F>) = (0..half)
+                    .map(|j| {
+                        let x = values[2 * j];
+                        let y = values[2 * j + 1] * factor;
+                        (x + y, x - y)
+                    })
+                    .unzip();
+                [(offset, sum), (offset + poly_size, diff)]
+            })
+            .collect();
+    }
+
+    let mut out = vec![F::ZERO; pp.l];
+    for (offset, values) in groups {
+        out[offset / mbyl] = values[0];
+    }
+    out
+}
 
-        // Optionally rearrange to get ready for next FFT/IFFT
-        if rearrange == true {
-            fft_in_place_rearrange(&mut s1);
-            let mut out_shares: Vec<Vec<F>> = Vec::new();
-            let pack_shares_timer = start_timer!(|| "Packing shares");
-            for i in 0..s1.len() / pp.l {
-                out_shares.push(
-                    // This will cause issues with memory benchmarking since it assumes everyone creates this instead of receiving it from dealer
-                    pp.pack_from_public(
-                        &s1.iter()
-                            .skip(i)
-                            .step_by(s1.len() / pp.l)
-                            .cloned()
-                            .collect::<Vec<_>>(),
-                    ),
-                );
+/// Reverses the lowest bits of x
+fn bit_reverse(x: usize, bits: u32) -> usize {
+    (0..bits).fold(0, |acc, bit| (acc << 1) | ((x >> bit) & 1))
+}
+
+/// FFT2 of count polynomials split over the spread kings instead of done by one king.
+/// Output share q packs values q*l..q*l + l, which come from the shares at positions
+/// (q*l + r - 1) % (m/l), so when m/l is a multiple of l a king that opens the shares of a
+/// group of l positions can pack l whole output shares on its own. The shares go to the kings
+/// and the outputs come back in one all to all exchange each, and every king only holds its
+/// part of the m values.
+fn fft2_transposed<F, N>(
+    px: Vec<F>,
+    count: usize,
+    omega: F,
+    rearrange: bool,
+    pad: usize,
+    degree2: bool,
+    dom: &EvaluationDomain<F>,
+    net: &N,
+    pp: &PackedSharingParams<F>,
+) -> Result<Vec<F>, NetError>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    N: MpcNetwork,
+{
+    let (n, id, l) = (net.n_parties(), net.party_id(), pp.l);
+    let mbyl = dom.size() / l;
+    let out_len = pad * mbyl;
+    debug_assert_eq!(mbyl % l, 0, "Mismatch of size in FFT2");
+    debug_assert!(out_len.is_power_of_two(), "Mismatch of size in FFT2");
+
+    // Every king gets groups of l output positions, c in its blocks needs the share at c - 1
+    let ranges = net.kings().ranges(n, mbyl / l);
+    let blocks = |king: usize| ranges[king].start * l..ranges[king].end * l;
+    let input = |c: usize| (c + mbyl - 1) % mbyl;
+    // Where output share q goes, with the order of its values
+    let position = |q: usize| if rearrange { bit_reverse(q, log2(out_len)) } else { q };
+    let slot = |r: usize| if rearrange { bit_reverse(r, log2(l)) } else { r };
+    // The output shares a king packs, in the order it sends them
+    let outputs = |king: usize| {
+        ranges[king].clone().flat_map(move |group| (0..l).map(move |a| a * mbyl / l + group))
+    };
+
+    let communication_timer = start_timer!(|| "ComToKings");
+    let outs: Vec<Vec<F>> = (0..n)
+        .map(|king| {
+            px.chunks(mbyl)
+                .flat_map(|px| blocks(king).map(|c| px[input(c)]).collect::<Vec<_>>())
+                .collect()
+        })
+        .collect();
+    let columns = net.all_to_all(&outs)?;
+    end_timer!(communication_timer);
+
+    let fft2_timer = start_timer!(|| "FFT2");
+    let unpack = |x: &Vec<F>| if degree2 { pp.unpack2(x) } else { pp.unpack(x) };
+    let secrets: Vec<F> = transpose(columns).iter().flat_map(unpack).collect();
+    let (start, width) = (blocks(id).start, blocks(id).len());
+    let mut shares: Vec<Vec<F>> = vec![Vec::new(); n];
+    for poly in 0..count {
+        let secrets = &secrets[poly * width * l..][..width * l];
+        // Output c + a*m/l of the polynomial at a * width + c - start
+        let mut values = vec![F::ZERO; width * l];
+        for (c, secrets) in blocks(id).zip(secrets.chunks(l)) {
+            let k = input(c);
+            let transformed = fft2_block(secrets.to_vec(), k, omega, dom, pp);
+            for (a, value) in transformed.into_iter().enumerate() {
+                let t = (k + 1 + a * mbyl) % dom.size();
+                values[(t / mbyl) * width + t % mbyl - start] = value;
             }
-            end_timer!(pack_shares_timer);
-            transpose(out_shares)
-        } else {
-            transpose(pack_vec(&s1, pp))
         }
-    });
-
-    drop(px);
+        for q in outputs(id) {
+            let (a, c) = (q * l / mbyl, q * l % mbyl);
+            let natural = &values[a * width + c - start..][..l];
+            let packed: Vec<F> = (0..l).map(|r| natural[slot(r)]).collect();
+            for (party, share) in pp.pack_from_public(&packed).into_iter().enumerate() {
+                shares[party].push(share);
+            }
+        }
+    }
+    end_timer!(fft2_timer);
 
-    let communication_timer = start_timer!(|| "ComFromKing");
-    let got_from_king = Net::recv_from_king(king_answer);
+    let communication_timer = start_timer!(|| "ComFromKings");
+    let got_from_kings = net.all_to_all(&shares)?;
     end_timer!(communication_timer);
 
-    got_from_king
+    // The positions past the polynomial are zeros from the padding
+    let mut out = vec![F::ZERO; count * out_len];
+    for (king, got) in got_from_kings.iter().enumerate() {
+        let positions: Vec<usize> = outputs(king).map(position).collect();
+        if positions.is_empty() {
+            continue;
+        }
+        for (poly, got) in got.chunks(positions.len()).enumerate() {
+            for (pos, share) in positions.iter().zip(got.iter()) {
+                out[poly * out_len + pos] = *share;
+            }
+        }
+    }
+    Ok(out)
 }
 
-pub fn fft_in_place_rearrange<F: PrimeField>(data: &mut Vec<F>) {
+pub fn fft_in_place_rearrange<T>(data: &mut Vec<T>) {
     let mut target = 0;
     for pos in 0..data.len() {
         if target > pos {
@@ -246,3 +504,198 @@ pub fn fft_in_place_rearrange<F: PrimeField>(data: &mut Vec<F>) {
         target |= mask;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preprocessing::{KingDealer, Preprocessing};
+    use crate::utils::pack::transpose;
+    use ff::Field;
+    use halo2_proofs::halo2curves::bn256::Fr as F;
+    use mpc_net::{local::simulate, Kings};
+
+    const L: usize = 2;
+    const K: u32 = 5;
+
+    /// Shares of party_id in the input layout of d_fft, see the dfft_test example
+    fn fft_input_share(
+        mut values: Vec<F>,
+        party_id: usize,
+        pp: &PackedSharingParams<F>,
+    ) -> Vec<F> {
+        fft_in_place_rearrange(&mut values);
+        let mbyl = values.len() / pp.l;
+        (0..mbyl)
+            .map(|i| {
+                let chunk: Vec<F> = values.iter().skip(i).step_by(mbyl).cloned().collect();
+                pp.pack_from_public(&chunk)[party_id]
+            })
+            .collect()
+    }
+
+    fn open(shares: Vec<Vec<F>>, pp: &PackedSharingParams<F>) -> Vec<F> {
+        transpose(shares).iter().flat_map(|x| pp.unpack(x)).collect()
+    }
+
+    #[test]
+    fn d_fft_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let dom = EvaluationDomain::<F>::new(1, K);
+        let rng = &mut ark_std::test_rng();
+        let coeffs: Vec<F> = (0..dom.size()).map(|_| F::random(&mut *rng)).collect();
+
+        let shares = simulate(pp.n, |net| {
+            let pcoeff_share = fft_input_share(coeffs.clone(), net.party_id(), &pp);
+            let dealer = &mut KingDealer::new(net);
+            let masks = dealer.fft_masks(false, false, 1, false, &dom, &pp).unwrap();
+            d_fft(pcoeff_share, false, 1, false, masks, &dom, net, &pp).unwrap()
+        });
+
+        assert_eq!(open(shares, &pp), PackedSharingParams::fft(&mut coeffs.clone(), &dom));
+    }
+
+    #[test]
+    fn d_fft_spread_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let dom = EvaluationDomain::<F>::new(1, K);
+        let rng = &mut ark_std::test_rng();
+        let coeffs: Vec<F> = (0..dom.size()).map(|_| F::random(&mut *rng)).collect();
+
+        // Three kings each run FFT2 on their blocks, nobody holds all the values
+        let shares = simulate(pp.n, |net| {
+            let net = &net.with_kings(Kings::Spread(3));
+            let pcoeff_share = fft_input_share(coeffs.clone(), net.party_id(), &pp);
+            let dealer = &mut KingDealer::new(net);
+            let masks = dealer.fft_masks(false, false, 1, false, &dom, &pp).unwrap();
+            d_fft(pcoeff_share, false, 1, false, masks, &dom, net, &pp).unwrap()
+        });
+
+        assert_eq!(open(shares, &pp), PackedSharingParams::fft(&mut coeffs.clone(), &dom));
+    }
+
+    #[test]
+    fn d_ifft_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let dom = EvaluationDomain::<F>::new(1, K);
+        let rng = &mut ark_std::test_rng();
+        let evals: Vec<F> = (0..dom.size()).map(|_| F::random(&mut *rng)).collect();
+
+        let shares = simulate(pp.n, |net| {
+            let peval_share = fft_input_share(evals.clone(), net.party_id(), &pp);
+            let dealer = &mut KingDealer::new(net);
+            let masks = dealer.fft_masks(true, false, 1, false, &dom, &pp).unwrap();
+            d_ifft(peval_share, false, 1, false, masks, &dom, net, &pp).unwrap()
+        });
+
+        assert_eq!(open(shares, &pp), PackedSharingParams::ifft(&mut evals.clone(), &dom));
+    }
+
+    #[test]
+    fn d_ifft_degree2_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let dom = EvaluationDomain::<F>::new(1, K);
+        let rng = &mut ark_std::test_rng();
+        let mut evals: Vec<F> = (0..dom.size()).map(|_| F::random(&mut *rng)).collect();
+
+        // Shares of degree 2d, like products of shares
+        let mut layout = evals.clone();
+        fft_in_place_rearrange(&mut layout);
+        let mbyl = dom.size() / pp.l;
+        let shares = transpose(
+            (0..mbyl)
+                .map(|i| {
+                    let chunk: Vec<F> = layout.iter().skip(i).step_by(mbyl).cloned().collect();
+                    pp.pack2(&chunk, rng)
+                })
+                .collect(),
+        );
+
+        for kings in [Kings::One(0), Kings::Spread(pp.n)] {
+            let outputs = simulate(pp.n, |net| {
+                let net = &net.with_kings(kings);
+                let dealer = &mut KingDealer::new(net);
+                let masks = dealer.fft_masks(true, false, 1, true, &dom, &pp).unwrap();
+                let peval_share = shares[net.party_id()].clone();
+                d_ifft(peval_share, false, 1, true, masks, &dom, net, &pp).unwrap()
+            });
+            assert_eq!(open(outputs, &pp), PackedSharingParams::ifft(&mut evals, &dom));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "power of two")]
+    fn check_packing_test() {
+        // n = 9 parties can reconstruct products for l = 3 and t = 1, the FFTs cannot split
+        let pp = PackedSharingParams::<F>::new_with(9, 1, 3);
+        check_packing(&EvaluationDomain::<F>::new(1, K), &pp);
+    }
+
+    #[test]
+    fn d_fft_batch_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let dom = EvaluationDomain::<F>::new(1, K);
+        let rng = &mut ark_std::test_rng();
+        let polys: Vec<Vec<F>> = (0..3)
+            .map(|_| (0..dom.size()).map(|_| F::random(&mut *rng)).collect())
+            .collect();
+
+        let outputs = simulate(pp.n, |net| {
+            let mut dealer = KingDealer::new(net);
+            let shares: Vec<Vec<F>> = polys
+                .iter()
+                .map(|poly| fft_input_share(poly.clone(), net.party_id(), &pp))
+                .collect();
+            let fft_masks = (0..3)
+                .map(|_| dealer.fft_masks(false, false, 1, false, &dom, &pp).unwrap())
+                .collect();
+            let ifft_masks = (0..3)
+                .map(|_| dealer.fft_masks(true, false, 1, false, &dom, &pp).unwrap())
+                .collect();
+
+            // One round to and from the king for the whole batch
+            net.reset_stats();
+            let evals = d_fft_batch(shares.clone(), false, 1, false, fft_masks, &dom, net, &pp);
+            let stats = net.stats();
+            let coeffs = d_ifft_batch(shares, false, 1, false, ifft_masks, &dom, net, &pp);
+            (evals.unwrap(), coeffs.unwrap(), (stats.to_king, stats.from_king))
+        });
+
+        for (i, poly) in polys.iter().enumerate() {
+            let evals = open(outputs.iter().map(|o| o.0[i].clone()).collect(), &pp);
+            let coeffs = open(outputs.iter().map(|o| o.1[i].clone()).collect(), &pp);
+            assert_eq!(evals, PackedSharingParams::fft(&mut poly.clone(), &dom));
+            assert_eq!(coeffs, PackedSharingParams::ifft(&mut poly.clone(), &dom));
+        }
+        assert!(outputs.iter().all(|o| o.2 == (1, 1)));
+    }
+
+    #[test]
+    fn d_fft_transposed_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let dom = EvaluationDomain::<F>::new(1, K);
+        let rng = &mut ark_std::test_rng();
+        let evals: Vec<F> = (0..dom.size()).map(|_| F::random(&mut *rng)).collect();
+
+        let run = |kings: Kings| {
+            simulate(pp.n, |net| {
+                let net = &net.with_kings(kings);
+                let peval_share = fft_input_share(evals.clone(), net.party_id(), &pp);
+                let dealer = &mut KingDealer::new(net);
+                let masks = dealer.fft_masks(true, true, 8, false, &dom, &pp).unwrap();
+                net.reset_stats();
+                let share = d_ifft(peval_share, true, 8, false, masks, &dom, net, &pp).unwrap();
+                let stats = net.stats();
+                (share, (stats.to_king, stats.from_king, stats.all_to_all))
+            })
+        };
+        let one = run(Kings::One(0));
+        // Every party is a king and FFT2 takes two all to all exchanges without a king
+        let spread = run(Kings::Spread(pp.n));
+
+        let open_all = |outputs: Vec<(Vec<F>, _)>| {
+            open(outputs.into_iter().map(|(share, _)| share).collect(), &pp)
+        };
+        assert!(spread.iter().all(|(_, rounds)| *rounds == (0, 0, 2)));
+        assert_eq!(open_all(spread), open_all(one));
+    }
+}
@@ -0,0 +1,350 @@
+// Distributed counterparts of the halo2 EvaluationDomain conversions.
+// They take the same domain as halo2, j-fold extension included, and run the FFTs over the
+// extended domain on the coset ZETA * <extended_omega> exactly as EvaluationDomain does.
+// All inputs and outputs are packed in natural order, l consecutive values per share.
+// Moving values into the layout of the FFTs goes through masked routes and the powers of ZETA
+// are multiplied into the shares locally, so the king never opens unmasked values.
+
+use crate::{
+    dfft::dfft::{d_fft_batch, d_ifft_batch, fft_in_place_rearrange},
+    preprocessing::Preprocessing,
+    utils::{deg_red::deg_red, domain_utils::EvaluationDomainExt, reshare::d_route},
+};
+use ff::{PrimeField, WithSmallOrderMulGroup};
+use halo2_proofs::poly::EvaluationDomain;
+use mpc_net::{MpcNetwork, NetError};
+use secret_sharing::pss::PackedSharingParams;
+use serde::{Deserialize, Serialize};
+
+/// Rearranges a vector into the input layout of d_fft:
+/// bit reversed and strided so that share i packs values i, i + m/l, i + 2m/l, ...
+pub fn fft_layout<T: Copy>(mut values: Vec<T>, l: usize) -> Vec<T> {
+    fft_in_place_rearrange(&mut values);
+    let mbyl = values.len() / l;
+    (0..mbyl)
+        .flat_map(|i| values.iter().skip(i).step_by(mbyl).copied().collect::<Vec<_>>())
+        .collect()
+}
+
+/// Multiplies coefficient i by ZETA^i, or by ZETA^-i to move back from the coset.
+/// ZETA has order 3, so the powers repeat as in EvaluationDomain::distribute_powers_zeta.
+fn distribute_powers_zeta<F: PrimeField + WithSmallOrderMulGroup<3>>(
+    values: &mut [F],
+    into_coset: bool,
+) {
+    let (g_coset, g_coset_inv) = (F::ZETA, F::ZETA.square());
+    let powers = if into_coset {
+        [F::ONE, g_coset, g_coset_inv]
+    } else {
+        [F::ONE, g_coset_inv, g_coset]
+    };
+    for (i, value) in values.iter_mut().enumerate() {
+        *value *= powers[i % 3];
+    }
+}
+
+/// Routes count vectors of len values into the layout of an FFT over size values, padding them
+/// with zeros
+fn layout_map(count: usize, len: usize, size: usize, l: usize) -> Vec<Option<usize>> {
+    let layout = fft_layout((0..size).collect(), l);
+    (0..count)
+        .flat_map(|p| layout.iter().map(move |&i| (i < len).then_some(p * len + i)))
+        .collect()
+}
+
+/// Multiplies packed shares of count vectors of coefficients by the powers of ZETA of
+/// distribute_powers_zeta. The powers are packed from public, so the output has degree 2d.
+fn distribute_powers_zeta_shares<F>(
+    shares: &[F],
+    count: usize,
+    into_coset: bool,
+    party_id: usize,
+    pp: &PackedSharingParams<F>,
+) -> Vec<F>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3>,
+{
+    let mut powers = vec![F::ONE; shares.len() / count * pp.l];
+    distribute_powers_zeta(&mut powers, into_coset);
+    let powers: Vec<F> = powers
+        .chunks(pp.l)
+        .map(|chunk| pp.pack_from_public(&chunk.to_vec())[party_id])
+        .collect();
+    shares
+        .iter()
+        .zip(powers.iter().cycle())
+        .map(|(x, z)| *x * z)
+        .collect()
+}
+
+/// Interpolates packed shares of evaluations over the domain into packed shares of coefficients
+pub fn d_lagrange_to_coeff<F, P, N>(
+    peval_share: Vec<F>,
+    degree2: bool,
+    domain: &EvaluationDomain<F>,
+    preprocessing: &mut P,
+    net: &N,
+    pp: &PackedSharingParams<F>,
+) -> Result<Vec<F>, NetError>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    P: Preprocessing<F>,
+    N: MpcNetwork,
+{
+    let peval_shares = vec![peval_share];
+    let mut pcoeff_shares =
+        d_lagrange_to_coeff_batch(peval_shares, degree2, domain, preprocessing, net, pp)?;
+    Ok(pcoeff_shares.pop().unwrap())
+}
+
+/// d_lagrange_to_coeff of several polynomials, the rearranging and the IFFT each take a single
+/// round for all of them
+pub fn d_lagrange_to_coeff_batch<F, P, N>(
+    peval_shares: Vec<Vec<F>>,
+    degree2: bool,
+    domain: &EvaluationDomain<F>,
+    preprocessing: &mut P,
+    net: &N,
+    pp: &PackedSharingParams<F>,
+) -> Result<Vec<Vec<F>>, NetError>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    P: Preprocessing<F>,
+    N: MpcNetwork,
+{
+    if peval_shares.is_empty() {
+        return Ok(Vec::new());
+    }
+    for peval_share in peval_shares.iter() {
+        assert_eq!(peval_share.len() * pp.l, domain.size());
+    }
+    let n = domain.size();
+    let map = layout_map(peval_shares.len(), n, n, pp.l);
+    let peval_shares = d_route(peval_shares.concat(), &map, degree2, preprocessing, net, pp)?;
+    let peval_shares: Vec<Vec<F>> = peval_shares
+        .chunks(n / pp.l)
+        .map(|share| share.to_vec())
+        .collect();
+    let masks = (0..peval_shares.len())
+        .map(|_| preprocessing.fft_masks(true, false, 1, false, domain, pp))
+        .collect::<Result<_, _>>()?;
+    d_ifft_batch(peval_shares, false, 1, false, masks, domain, net, pp)
+}
+
+/// Evaluates packed shares of the n coefficients of a polynomial over the extended coset
+/// ZETA * <extended_omega> of the domain
+pub fn d_coeff_to_extended<F, P, N>(
+    pcoeff_share: Vec<F>,
+    domain: &EvaluationDomain<F>,
+    preprocessing: &mut P,
+    net: &N,
+    pp: &PackedSharingParams<F>,
+) -> Result<Vec<F>, NetError>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    P: Preprocessing<F>,
+    N: MpcNetwork,
+{
+    let pcoeff_shares = vec![pcoeff_share];
+    let mut peval_shares =
+        d_coeff_to_extended_batch(pcoeff_shares, domain, preprocessing, net, pp)?;
+    Ok(peval_shares.pop().unwrap())
+}
+
+/// d_coeff_to_extended of several polynomials, the moving into the coset and the FFT each take
+/// a single round for all of them
+pub fn d_coeff_to_extended_batch<F, P, N>(
+    pcoeff_shares: Vec<Vec<F>>,
+    domain: &EvaluationDomain<F>,
+    preprocessing: &mut P,
+    net: &N,
+    pp: &PackedSharingParams<F>,
+) -> Result<Vec<Vec<F>>, NetError>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    P: Preprocessing<F>,
+    N: MpcNetwork,
+{
+    if pcoeff_shares.is_empty() {
+        return Ok(Vec::new());
+    }
+    for pcoeff_share in pcoeff_shares.iter() {
+        assert_eq!(pcoeff_share.len() * pp.l, domain.size());
+    }
+    let ext_dom = domain.extended_domain();
+    let count = pcoeff_shares.len();
+    let party_id = net.party_id();
+    let pcoeff_shares =
+        distribute_powers_zeta_shares(&pcoeff_shares.concat(), count, true, party_id, pp);
+    let map = layout_map(count, domain.size(), ext_dom.size(), pp.l);
+    let pcoeff_shares = d_route(pcoeff_shares, &map, true, preprocessing, net, pp)?;
+    let pcoeff_shares: Vec<Vec<F>> = pcoeff_shares
+        .chunks(ext_dom.size() / pp.l)
+        .map(|share| share.to_vec())
+        .collect();
+    let masks = (0..pcoeff_shares.len())
+        .map(|_| preprocessing.fft_masks(false, false, 1, false, &ext_dom, pp))
+        .collect::<Result<_, _>>()?;
+    d_fft_batch(pcoeff_shares, false, 1, false, masks, &ext_dom, net, pp)
+}
+
+/// Interpolates packed shares of evaluations over the extended coset back into coefficients.
+/// As in EvaluationDomain::extended_to_coeff the result is truncated to the size of the
+/// quotient polynomial, n * (j - 1) coefficients.
+pub fn d_extended_to_coeff<F, P, N>(
+    peval_share: Vec<F>,
+    degree2: bool,
+    domain: &EvaluationDomain<F>,
+    preprocessing: &mut P,
+    net: &N,
+    pp: &PackedSharingParams<F>,
+) -> Result<Vec<F>, NetError>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    P: Preprocessing<F>,
+    N: MpcNetwork,
+{
+    let peval_shares = vec![peval_share];
+    let mut pcoeff_shares =
+        d_extended_to_coeff_batch(peval_shares, degree2, domain, preprocessing, net, pp)?;
+    Ok(pcoeff_shares.pop().unwrap())
+}
+
+/// d_extended_to_coeff of several polynomials, the rearranging, the IFFT and the moving back
+/// from the coset each take a single round for all of them
+pub fn d_extended_to_coeff_batch<F, P, N>(
+    peval_shares: Vec<Vec<F>>,
+    degree2: bool,
+    domain: &EvaluationDomain<F>,
+    preprocessing: &mut P,
+    net: &N,
+    pp: &PackedSharingParams<F>,
+) -> Result<Vec<Vec<F>>, NetError>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    P: Preprocessing<F>,
+    N: MpcNetwork,
+{
+    if peval_shares.is_empty() {
+        return Ok(Vec::new());
+    }
+    for peval_share in peval_shares.iter() {
+        assert_eq!(peval_share.len() * pp.l, domain.extended_len());
+    }
+    let ext_dom = domain.extended_domain();
+    let count = peval_shares.len();
+    let len = domain.size() * domain.get_quotient_poly_degree();
+
+    let map = layout_map(count, ext_dom.size(), ext_dom.size(), pp.l);
+    let peval_shares = d_route(peval_shares.concat(), &map, degree2, preprocessing, net, pp)?;
+    let peval_shares: Vec<Vec<F>> = peval_shares
+        .chunks(ext_dom.size() / pp.l)
+        .map(|share| share.to_vec())
+        .collect();
+    let masks = (0..count)
+        .map(|_| preprocessing.fft_masks(true, false, 1, false, &ext_dom, pp))
+        .collect::<Result<_, _>>()?;
+    let pcoeff_shares = d_ifft_batch(peval_shares, false, 1, false, masks, &ext_dom, net, pp)?;
+
+    // Truncating drops the last shares, moving from the coset doubles the degree
+    let truncated: Vec<F> = pcoeff_shares
+        .iter()
+        .flat_map(|share| share[..len / pp.l].iter().copied())
+        .collect();
+    let pcoeff_shares =
+        distribute_powers_zeta_shares(&truncated, count, false, net.party_id(), pp);
+    let sharings = preprocessing.double_sharings(pcoeff_shares.len(), pp)?;
+    let pcoeff_shares = deg_red(pcoeff_shares, sharings, net, pp)?;
+    Ok(pcoeff_shares
+        .chunks(len / pp.l)
+        .map(|share| share.to_vec())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preprocessing::KingDealer;
+    use crate::utils::pack::{pack_vec, transpose};
+    use ff::Field;
+    use halo2_proofs::halo2curves::bn256::Fr as F;
+    use mpc_net::local::simulate;
+
+    const L: usize = 2;
+    const K: u32 = 4;
+    /// The quotient polynomial has degree 3n, so the extended domain is 4n
+    const J: u32 = 4;
+
+    fn open(shares: Vec<Vec<F>>, pp: &PackedSharingParams<F>) -> Vec<F> {
+        transpose(shares).iter().flat_map(|x| pp.unpack(x)).collect()
+    }
+
+    #[test]
+    fn d_domain_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let domain = EvaluationDomain::<F>::new(J, K);
+        let rng = &mut ark_std::test_rng();
+        let evals: Vec<F> = (0..domain.size()).map(|_| F::random(&mut *rng)).collect();
+        let h_evals: Vec<F> = (0..domain.extended_len()).map(|_| F::random(&mut *rng)).collect();
+
+        // h has degree 2d like the products it is made of
+        let h_shares = transpose(
+            h_evals
+                .chunks(L)
+                .map(|chunk| pp.pack2(&chunk.to_vec(), &mut *rng))
+                .collect(),
+        );
+
+        let outputs = simulate(pp.n, |net| {
+            let dealer = &mut KingDealer::new(net);
+            let eval_share = transpose(pack_vec(&evals, &pp))[net.party_id()].clone();
+            let h_share = h_shares[net.party_id()].clone();
+
+            let coeffs = d_lagrange_to_coeff(eval_share, false, &domain, dealer, net, &pp);
+            let coeffs = coeffs.unwrap();
+            let coset = d_coeff_to_extended(coeffs.clone(), &domain, dealer, net, &pp).unwrap();
+            let h = d_extended_to_coeff(h_share, true, &domain, dealer, net, &pp).unwrap();
+            (coeffs, coset, h)
+        });
+
+        let coeffs = domain.lagrange_to_coeff(domain.lagrange_from_vec(evals));
+        let coset = domain.coeff_to_extended(coeffs.clone());
+        let mut h = domain.empty_extended();
+        h.iter_mut().zip(h_evals).for_each(|(x, y)| *x = y);
+        let h = domain.extended_to_coeff(h);
+
+        assert_eq!(open(outputs.iter().map(|o| o.0.clone()).collect(), &pp), coeffs.to_vec());
+        assert_eq!(open(outputs.iter().map(|o| o.1.clone()).collect(), &pp), coset.to_vec());
+        assert_eq!(open(outputs.iter().map(|o| o.2.clone()).collect(), &pp), h);
+    }
+
+    #[test]
+    fn d_extended_to_coeff_batch_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let domain = EvaluationDomain::<F>::new(J, K);
+        let rng = &mut ark_std::test_rng();
+        let h_evals: Vec<Vec<F>> = (0..3)
+            .map(|_| (0..domain.extended_len()).map(|_| F::random(&mut *rng)).collect())
+            .collect();
+
+        let outputs = simulate(pp.n, |net| {
+            let dealer = &mut KingDealer::new(net);
+            let h_shares: Vec<Vec<F>> = h_evals
+                .iter()
+                .map(|h| transpose(pack_vec(h, &pp))[net.party_id()].clone())
+                .collect();
+            net.reset_stats();
+            let h = d_extended_to_coeff_batch(h_shares, false, &domain, dealer, net, &pp);
+            (h.unwrap(), net.stats().to_king)
+        });
+
+        // The route, the IFFT and the degree reduction
+        assert!(outputs.iter().all(|(_, to_king)| *to_king == 3));
+        for (i, h_evals) in h_evals.into_iter().enumerate() {
+            let mut h = domain.empty_extended();
+            h.iter_mut().zip(h_evals).for_each(|(x, y)| *x = y);
+            let shares = outputs.iter().map(|(h, _)| h[i].clone()).collect();
+            assert_eq!(open(shares, &pp), domain.extended_to_coeff(h));
+        }
+    }
+}
@@ -1 +1,2 @@
 pub mod dfft;
+pub mod domain;
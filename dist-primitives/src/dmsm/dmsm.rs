@@ -1,7 +1,9 @@
 use std::fmt::Debug;
 
 use crate::utils::g1_serialization::G1Wrapper;
-use crate::{channel::channel::MpcSerNet, utils::domain_utils};
+use crate::channel::channel::MpcSerNet;
+use crate::preprocessing::Preprocessing;
+use crate::utils::pack::transpose;
 use ark_std::{end_timer, start_timer};
 use ff::{PrimeField, WithSmallOrderMulGroup};
 use group::Group;
@@ -9,7 +11,8 @@ use halo2_proofs::{
     halo2curves::pairing::Engine,
     poly::{commitment::MSM, kzg::msm::MSMKZG},
 };
-use mpc_net::{MpcMultiNet as Net, MpcNet};
+use log::debug;
+use mpc_net::{label, MpcNetwork, NetError};
 use secret_sharing::pss::PackedSharingParams;
 
 pub fn unpackexp<E>(
@@ -21,41 +24,12 @@ where
     E: Engine + Debug,
     E::Scalar: PrimeField + WithSmallOrderMulGroup<3>,
 {
-    let mut result = shares.to_vec();
+    debug_assert!(
+        pp.is_consistent(shares, degree2),
+        "Polynomial has degree > degree bound"
+    );
 
-    // interpolate shares
-    domain_utils::ifft_on_group_elements::<E>(&mut result, &pp.share);
-
-    // Simplified this assertion using a zero check in the last n - d - 1 entries
-
-    #[cfg(debug_assertions)]
-    {
-        let n = Net::n_parties();
-        let d: usize;
-        if degree2 {
-            d = 2 * (pp.t + pp.l)
-        } else {
-            d = pp.t + pp.l
-        }
-
-        for i in d + 1..n {
-            let is_identity: bool = result[i].is_identity().into();
-            debug_assert!(is_identity, "Polynomial has degree > degree bound {})", d);
-        }
-    }
-
-    // Evalaute the polynomial on the coset to recover secrets
-    if degree2 {
-        domain_utils::fft_on_group_elements::<E>(&mut result, &pp.secret2);
-        result[0..pp.l * 2]
-            .iter()
-            .step_by(2)
-            .copied()
-            .collect::<Vec<_>>()
-    } else {
-        domain_utils::fft_on_group_elements::<E>(&mut result, &pp.secret);
-        result[0..pp.l].to_vec()
-    }
+    pp.unpackexp(shares, degree2)
 }
 
 pub fn packexp_from_public<E>(
@@ -68,61 +42,212 @@ where
 {
     debug_assert_eq!(secrets.len(), pp.l);
 
-    let mut result = secrets.to_vec();
-    // interpolate secrets
-    domain_utils::ifft_on_group_elements::<E>(&mut result, &pp.secret);
-
-    // evaluate polynomial to get shares
-    domain_utils::fft_on_group_elements::<E>(&mut result, &pp.share);
-
-    result
+    pp.packexp_from_public(secrets)
 }
 
-pub fn d_msm<E>(
+pub fn d_msm<E, P, N>(
     bases: &[E::G1],
     scalars: &[E::Scalar],
+    preprocessing: &mut P,
+    net: &N,
     pp: &PackedSharingParams<E::Scalar>,
-) -> E::G1
+) -> Result<E::G1, NetError>
 where
     E: Engine + Debug,
     E::Scalar: PrimeField + WithSmallOrderMulGroup<3>,
+    P: Preprocessing<E::Scalar>,
+    N: MpcNetwork,
 {
-    // Using affine is important because we don't want to create an extra vector for converting Projective to Affine.
-    // Eventually we do have to convert to Projective but this will be pp.l group elements instead of m()
+    debug!("bases: {}, scalars: {}", bases.len(), scalars.len());
+    Ok(d_msm_batch::<E, _, _>(&[bases], &[scalars], preprocessing, net, pp)?[0])
+}
 
-    // Ensure bases and scalars have the same length
+/// d_msm of every pair of bases and scalars with a single round to and from the king.
+/// The king only learns the outputs, the partial sums in the slots are hidden by sharings times
+/// the generator whose slots sum to zero.
+pub fn d_msm_batch<E, P, N>(
+    bases: &[&[E::G1]],
+    scalars: &[&[E::Scalar]],
+    preprocessing: &mut P,
+    net: &N,
+    pp: &PackedSharingParams<E::Scalar>,
+) -> Result<Vec<E::G1>, NetError>
+where
+    E: Engine + Debug,
+    E::Scalar: PrimeField + WithSmallOrderMulGroup<3>,
+    P: Preprocessing<E::Scalar>,
+    N: MpcNetwork,
+{
     assert_eq!(bases.len(), scalars.len());
+    let _label = label("dmsm");
 
     // First round of local computation done by parties
-    println!("bases: {}, scalars: {}", bases.len(), scalars.len());
-    let basemsm_timer = start_timer!(|| "Base MSM");
+    let c_shares = local_msms::<E>(bases, scalars);
+    let zeros = preprocessing.sum_zero_sharings(c_shares.len(), pp)?;
+    let c_shares = rerandomize::<E>(c_shares, &zeros);
 
-    let mut msm = MSMKZG::<E>::new();
+    // Now we do degree reduction -- psstoss
+    // Send to king who reduces and sends the outputs to everyone.
+    let _reduce = label("reduce");
 
-    for (base, scalar) in bases.iter().zip(scalars.iter()) {
-        msm.append_term(*scalar, *base)
-    }
+    let king_answer: Option<Vec<Vec<G1Wrapper<E>>>> =
+        net.send_to_king(&c_shares)?.map(|wrapped_shares: Vec<Vec<G1Wrapper<E>>>| {
+            let outputs: Vec<G1Wrapper<E>> = sum_slots::<E>(wrapped_shares, pp)
+                .into_iter()
+                .map(G1Wrapper)
+                .collect();
+            vec![outputs; net.n_parties()]
+        });
 
-    let c_share = msm.eval();
+    let received_answer: Vec<G1Wrapper<E>> = net.recv_from_king(king_answer)?;
 
-    end_timer!(basemsm_timer);
-    // Now we do degree reduction -- psstoss
-    // Send to king who reduces and sends shamir shares (not packed).
-    // Should be randomized. First convert to projective share.
+    Ok(received_answer.into_iter().map(|wrapper| wrapper.0).collect())
+}
 
-    let king_answer: Option<Vec<G1Wrapper<E>>> =
-        Net::send_to_king(&G1Wrapper(c_share)).map(|wrapped_shares: Vec<G1Wrapper<E>>| {
-            let shares: Vec<E::G1> = wrapped_shares
-                .into_iter()
-                .map(|wrapper| wrapper.0)
+/// d_msm that returns a packed share of the output in the first slot instead of opening it
+pub fn d_msm_packed<E, P, N>(
+    bases: &[E::G1],
+    scalars: &[E::Scalar],
+    preprocessing: &mut P,
+    net: &N,
+    pp: &PackedSharingParams<E::Scalar>,
+) -> Result<E::G1, NetError>
+where
+    E: Engine + Debug,
+    E::Scalar: PrimeField + WithSmallOrderMulGroup<3>,
+    P: Preprocessing<E::Scalar>,
+    N: MpcNetwork,
+{
+    Ok(d_msm_packed_batch::<E, _, _>(&[bases], &[scalars], preprocessing, net, pp)?[0])
+}
+
+/// d_msm of every pair of bases and scalars with a single round to and from the king.
+/// Returns packed shares of the outputs, l outputs per share in order and the missing ones zero.
+/// The king only sees the outputs masked with r·G for field masks r, and the shares of the
+/// partial sums rerandomized with sharings of zero like in d_msm_batch.
+pub fn d_msm_packed_batch<E, P, N>(
+    bases: &[&[E::G1]],
+    scalars: &[&[E::Scalar]],
+    preprocessing: &mut P,
+    net: &N,
+    pp: &PackedSharingParams<E::Scalar>,
+) -> Result<Vec<E::G1>, NetError>
+where
+    E: Engine + Debug,
+    E::Scalar: PrimeField + WithSmallOrderMulGroup<3>,
+    P: Preprocessing<E::Scalar>,
+    N: MpcNetwork,
+{
+    assert_eq!(bases.len(), scalars.len());
+    let _label = label("dmsm");
+
+    // Pad with empty msms to whole output shares
+    let len = bases.len().div_ceil(pp.l) * pp.l;
+    let mut c_shares = local_msms::<E>(bases, scalars);
+    c_shares.resize(len, G1Wrapper(E::G1::identity()));
+    let zeros = preprocessing.zero_sharings(len, pp)?;
+    let c_shares = rerandomize::<E>(c_shares, &zeros);
+
+    // Msm i is masked with r_il..r_il+l in its slots and the king opens their sum. Routing slot s
+    // of every msm to block s and adding up the blocks gives packed shares of the sums.
+    let map: Vec<usize> = (0..pp.l)
+        .flat_map(|s| (0..len).map(move |i| i * pp.l + s))
+        .collect();
+    let masks = preprocessing.route_masks(len * pp.l, &map, false, pp)?;
+    let sums: Vec<E::Scalar> = (0..len / pp.l)
+        .map(|j| (0..pp.l).map(|s| masks.routed[s * len / pp.l + j]).sum())
+        .collect();
+    let generator = E::G1::generator();
+    let c_shares: Vec<G1Wrapper<E>> = c_shares
+        .into_iter()
+        .zip(masks.mask.iter())
+        .map(|(c, r)| G1Wrapper(c.0 + generator * *r))
+        .collect();
+
+    let _reduce = label("reduce");
+
+    let king_answer: Option<Vec<Vec<G1Wrapper<E>>>> =
+        net.send_to_king(&c_shares)?.map(|wrapped_shares: Vec<Vec<G1Wrapper<E>>>| {
+            let outputs = sum_slots::<E>(wrapped_shares, pp);
+            let shares: Vec<Vec<G1Wrapper<E>>> = outputs
+                .chunks(pp.l)
+                .map(|chunk| {
+                    packexp_from_public::<E>(&chunk.to_vec(), pp)
+                        .into_iter()
+                        .map(G1Wrapper)
+                        .collect()
+                })
                 .collect();
-            let output: E::G1 = unpackexp::<E>(&shares, true, pp).iter().sum();
-            vec![G1Wrapper(output); Net::n_parties()]
+            transpose(shares)
         });
 
-    let received_answer: G1Wrapper<E> = Net::recv_from_king(king_answer);
+    let received_answer: Vec<G1Wrapper<E>> = net.recv_from_king(king_answer)?;
 
-    received_answer.0
+    // Parties remove the masks locally
+    Ok(received_answer
+        .into_iter()
+        .zip(sums.iter())
+        .map(|(wrapper, sum)| wrapper.0 - generator * *sum)
+        .collect())
+}
+
+/// Local msms of the parties, shares of degree 2 of the l partial sums of each output
+fn local_msms<E>(bases: &[&[E::G1]], scalars: &[&[E::Scalar]]) -> Vec<G1Wrapper<E>>
+where
+    E: Engine + Debug,
+    E::Scalar: PrimeField + WithSmallOrderMulGroup<3>,
+{
+    let basemsm_timer = start_timer!(|| "Base MSM");
+    let c_shares = bases
+        .iter()
+        .zip(scalars.iter())
+        .map(|(bases, scalars)| {
+            // Ensure bases and scalars have the same length
+            assert_eq!(bases.len(), scalars.len());
+            let mut msm = MSMKZG::<E>::new();
+            for (base, scalar) in bases.iter().zip(scalars.iter()) {
+                msm.append_term(*scalar, *base)
+            }
+            G1Wrapper(msm.eval())
+        })
+        .collect();
+    end_timer!(basemsm_timer);
+    c_shares
+}
+
+/// Adds sharings of degree 2d times the generator to shares of degree 2d. With sharings of zero
+/// the secrets stay the same, with sharings that sum to zero only the sum of the slots does, and
+/// the rest of the shares become uniformly random
+fn rerandomize<E>(c_shares: Vec<G1Wrapper<E>>, zeros: &[E::Scalar]) -> Vec<G1Wrapper<E>>
+where
+    E: Engine + Debug,
+    E::Scalar: PrimeField + WithSmallOrderMulGroup<3>,
+{
+    debug_assert_eq!(c_shares.len(), zeros.len(), "Mismatch of size in rerandomize");
+    let generator = E::G1::generator();
+    c_shares
+        .into_iter()
+        .zip(zeros.iter())
+        .map(|(c, z)| G1Wrapper(c.0 + generator * *z))
+        .collect()
+}
+
+/// King opens the shares of every output and adds up the partial sums in the slots
+fn sum_slots<E>(
+    wrapped_shares: Vec<Vec<G1Wrapper<E>>>,
+    pp: &PackedSharingParams<E::Scalar>,
+) -> Vec<E::G1>
+where
+    E: Engine + Debug,
+    E::Scalar: PrimeField + WithSmallOrderMulGroup<3>,
+{
+    transpose(wrapped_shares)
+        .into_iter()
+        .map(|shares| {
+            let shares: Vec<E::G1> = shares.into_iter().map(|wrapper| wrapper.0).collect();
+            unpackexp::<E>(&shares, true, pp).iter().sum()
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -136,9 +261,18 @@ mod tests {
     type E = Bn256;
     type F = Fr;
 
+    use crate::dmsm::dmsm::d_msm;
+    use crate::dmsm::dmsm::{d_msm_batch, d_msm_packed_batch};
     use crate::dmsm::dmsm::packexp_from_public;
     use crate::dmsm::dmsm::unpackexp;
+    use crate::preprocessing::{
+        dealer::deal,
+        shape::{Request, Shape},
+        KingDealer,
+    };
     use crate::utils::pack::transpose;
+    use group::Group;
+    use mpc_net::{local::simulate, MpcNetwork};
 
     const L: usize = 2;
     const N: usize = L * 4;
@@ -222,4 +356,121 @@ mod tests {
         let result: G1P = unpackexp::<E>(&result, true, &pp).iter().sum();
         assert_eq!(expected, result);
     }
+
+    #[test]
+    fn d_msm_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let rng = &mut ark_std::test_rng();
+
+        let bases: Vec<G1P> = (0..M).map(|_| G1P::generator() * random_fr(rng)).collect();
+        let scalars: Vec<F> = (0..M).map(|_| random_fr(rng)).collect();
+
+        let mut msm = MSMKZG::<E>::new();
+        for (base, scalar) in bases.iter().zip(scalars.iter()) {
+            msm.append_term(*scalar, *base)
+        }
+        let expected = msm.eval();
+
+        let base_shares = transpose(
+            bases
+                .chunks(L)
+                .map(|s| packexp_from_public::<E>(&s.to_vec(), &pp))
+                .collect(),
+        );
+        let scalar_shares = transpose(
+            scalars
+                .chunks(L)
+                .map(|s| pp.pack_from_public(&s.to_vec()))
+                .collect(),
+        );
+
+        let outputs = simulate(pp.n, |net| {
+            let id = net.party_id();
+            let dealer = &mut KingDealer::new(net);
+            d_msm::<E, _, _>(&base_shares[id], &scalar_shares[id], dealer, net, &pp).unwrap()
+        });
+        assert!(outputs.iter().all(|output| *output == expected));
+    }
+
+    /// Packed shares of random bases and scalars for three msms of different lengths, and the
+    /// expected outputs
+    fn batch_input(
+        pp: &PackedSharingParams<F>,
+    ) -> (Vec<Vec<Vec<G1P>>>, Vec<Vec<Vec<F>>>, Vec<G1P>) {
+        let rng = &mut ark_std::test_rng();
+        let mut base_shares = Vec::new();
+        let mut scalar_shares = Vec::new();
+        let mut expected = Vec::new();
+        for m in [M, M / 2, M] {
+            let bases: Vec<G1P> = (0..m).map(|_| G1P::generator() * random_fr(rng)).collect();
+            let scalars: Vec<F> = (0..m).map(|_| random_fr(rng)).collect();
+
+            let mut msm = MSMKZG::<E>::new();
+            for (base, scalar) in bases.iter().zip(scalars.iter()) {
+                msm.append_term(*scalar, *base)
+            }
+            expected.push(msm.eval());
+
+            base_shares.push(transpose(
+                bases
+                    .chunks(L)
+                    .map(|s| packexp_from_public::<E>(&s.to_vec(), pp))
+                    .collect(),
+            ));
+            scalar_shares.push(transpose(
+                scalars
+                    .chunks(L)
+                    .map(|s| pp.pack_from_public(&s.to_vec()))
+                    .collect(),
+            ));
+        }
+        // Every party gets its shares of all the msms
+        (transpose(base_shares), transpose(scalar_shares), expected)
+    }
+
+    #[test]
+    fn d_msm_batch_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let (base_shares, scalar_shares, expected) = batch_input(&pp);
+        // Dealt ahead so that only the msms count towards the rounds
+        let shape = Shape {
+            requests: vec![Request::SumZeros { len: 3 }],
+        };
+        let stores = deal(&shape, &pp, &mut ark_std::test_rng());
+
+        let outputs = simulate(pp.n, |net| {
+            let id = net.party_id();
+            let bases: Vec<&[G1P]> = base_shares[id].iter().map(|b| &b[..]).collect();
+            let scalars: Vec<&[F]> = scalar_shares[id].iter().map(|s| &s[..]).collect();
+            let store = &mut stores[id].clone();
+            net.reset_stats();
+            let outputs = d_msm_batch::<E, _, _>(&bases, &scalars, store, net, &pp).unwrap();
+            let stats = net.stats();
+            (outputs, (stats.to_king, stats.from_king))
+        });
+        // All the msms take a single round
+        assert!(outputs.iter().all(|output| *output == (expected.clone(), (1, 1))));
+    }
+
+    #[test]
+    fn d_msm_packed_batch_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let (base_shares, scalar_shares, expected) = batch_input(&pp);
+
+        let shares = simulate(pp.n, |net| {
+            let id = net.party_id();
+            let bases: Vec<&[G1P]> = base_shares[id].iter().map(|b| &b[..]).collect();
+            let scalars: Vec<&[F]> = scalar_shares[id].iter().map(|s| &s[..]).collect();
+            let dealer = &mut KingDealer::new(net);
+            d_msm_packed_batch::<E, _, _>(&bases, &scalars, dealer, net, &pp).unwrap()
+        });
+
+        // Three outputs in two shares, the last slot is zero
+        let outputs: Vec<G1P> = transpose(shares)
+            .iter()
+            .flat_map(|shares| unpackexp::<E>(shares, false, &pp))
+            .collect();
+        assert_eq!(outputs[..3], expected[..]);
+        assert_eq!(outputs[3], G1P::identity());
+    }
 }
@@ -3,6 +3,7 @@
 
 use crate::{
     channel::channel::MpcSerNet,
+    preprocessing::dpp::DppMasks,
     utils::{
         deg_red::deg_red,
         pack::{pack_vec, transpose},
@@ -10,32 +11,53 @@ use crate::{
 };
 use ark_std::{end_timer, start_timer};
 use ff::{Field, PrimeField, WithSmallOrderMulGroup};
-use mpc_net::MpcMultiNet as Net;
+use mpc_net::{label, MpcNetwork, NetError};
 use secret_sharing::pss::PackedSharingParams;
 use serde::{Deserialize, Serialize};
 
-// Given pre-processed randomness [s], [s^-1]
-pub fn d_pp<F>(num: Vec<F>, den: Vec<F>, pp: &PackedSharingParams<F>) -> Vec<F>
+/// Partial products of num / den given preprocessed masks, see preprocessing::dpp.
+/// num and den are shares of degree 1 and the king only sees masked values.
+pub fn d_pp<F, N: MpcNetwork>(
+    num: Vec<F>,
+    den: Vec<F>,
+    masks: DppMasks<F>,
+    net: &N,
+    pp: &PackedSharingParams<F>,
+) -> Result<Vec<F>, NetError>
 where
     F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
 {
-    // using some dummy randomness
-    let s = F::from(1 as u64);
-    let sinv = s.invert().unwrap();
+    debug_assert_eq!(num.len(), masks.num_mask.len(), "Mismatch of size in d_pp");
+    debug_assert_eq!(den.len(), masks.den_mask.len(), "Mismatch of size in d_pp");
+    debug_assert_eq!(2 * num.len(), masks.zero.len(), "Mismatch of size in d_pp");
+    let _label = label("dpp");
 
-    // multiply all entries of px by of s
+    // multiply all entries by the masks
     let dpp_rand_timer = start_timer!(|| "DppRand");
-    let num_rand = num.iter().map(|&x| x * s).collect::<Vec<_>>();
-    let mut den_rand = den.iter().map(|&x| x * s).collect::<Vec<_>>();
+    let num_rand = num
+        .iter()
+        .zip(masks.num_mask.iter())
+        .map(|(x, r)| *x * r)
+        .collect::<Vec<_>>();
+    let mut den_rand = den
+        .iter()
+        .zip(masks.den_mask.iter())
+        .map(|(x, r)| *x * r)
+        .collect::<Vec<_>>();
     end_timer!(dpp_rand_timer);
 
     let mut numden_rand = num_rand;
     numden_rand.append(&mut den_rand);
+    // The products have degree 2d, rerandomize them so the king only learns the secrets
+    numden_rand
+        .iter_mut()
+        .zip(masks.zero.iter())
+        .for_each(|(x, z)| *x += z);
 
     // Along with degree reduction
-    // King recovers secrets, computes partial products and repacks
+    // King recovers masked secrets, computes partial products and repacks
     let communication_timer = start_timer!(|| "ComToKing");
-    let received_shares = Net::send_to_king(&numden_rand);
+    let received_shares = net.send_to_king(&numden_rand)?;
     end_timer!(communication_timer);
 
     let king_answer: Option<Vec<Vec<F>>> = received_shares.map(|numden_shares: Vec<Vec<F>>| {
@@ -76,16 +98,52 @@ where
     });
 
     let communication_timer = start_timer!(|| "ComFromKing");
-    let mut pp_numden_rand = Net::recv_from_king(king_answer);
+    let pp_numden_rand: Vec<F> = net.recv_from_king(king_answer)?;
     end_timer!(communication_timer);
 
-    // Finally, remove the ranomness in the partial products
-    // multiply all entries of pp_pxss by of s
-    // do degree reduction
+    // Finally, remove the masks from the partial products
+    // and do degree reduction
     let dpp_rand_timer = start_timer!(|| "DppRand");
-    pp_numden_rand.iter_mut().for_each(|x| *x *= sinv);
+    let pp_numden: Vec<F> = pp_numden_rand
+        .iter()
+        .zip(masks.unmask.iter())
+        .map(|(x, r)| *x * r)
+        .collect();
     end_timer!(dpp_rand_timer);
-    let pp_numden = deg_red(pp_numden_rand, pp); //packed shares of partial products
 
-    pp_numden
+    deg_red(pp_numden, masks.reduce, net, pp) //packed shares of partial products
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preprocessing::{KingDealer, Preprocessing};
+    use halo2_proofs::halo2curves::bn256::Fr as F;
+    use mpc_net::{local::simulate, MpcNetwork};
+
+    const L: usize = 2;
+    const M: usize = 32;
+
+    #[test]
+    fn d_pp_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let rng = &mut ark_std::test_rng();
+        let num: Vec<F> = (0..M).map(|_| F::random(&mut *rng)).collect();
+        let den: Vec<F> = (0..M).map(|_| F::random(&mut *rng)).collect();
+        let num_shares = transpose(pack_vec(&num, &pp));
+        let den_shares = transpose(pack_vec(&den, &pp));
+
+        let shares = simulate(pp.n, |net| {
+            let masks = KingDealer::new(net).dpp_masks(M, &pp).unwrap();
+            let id = net.party_id();
+            d_pp(num_shares[id].clone(), den_shares[id].clone(), masks, net, &pp).unwrap()
+        });
+        let products: Vec<F> = transpose(shares).iter().flat_map(|x| pp.unpack(x)).collect();
+
+        let mut expected = F::ONE;
+        for i in 0..M {
+            expected *= num[i] * den[i].invert().unwrap();
+            assert_eq!(products[i], expected);
+        }
+    }
 }
@@ -0,0 +1,366 @@
+// Distributed generation of the extended witness.
+// The client only shares the primary inputs and the parties evaluate the circuit computing the
+// other advice cells on packed shares. Every wire is a column of m values packed l to a share,
+// so a gate acts on all the rows at once. Linear gates are local, products are reduced with
+// deg_red, and routes, which move values to other rows or columns like copy constraints and
+// rotations do, are opened masked by the king and packed again in their new places.
+// Gates run by level, the number of rounds before their inputs are ready, and all the products
+// and all the routes of a level share a round.
+
+use ark_std::{end_timer, start_timer};
+use ff::{PrimeField, WithSmallOrderMulGroup};
+use mpc_net::{label, local::simulate, MpcNetwork, NetError};
+use secret_sharing::pss::PackedSharingParams;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    preprocessing::{
+        shape::{Recorder, Shape},
+        KingDealer, Preprocessing,
+    },
+    utils::{deg_red::deg_red, pack::pack_vec, reshare::d_route},
+};
+
+/// A gate of a circuit, its inputs are wires defined before it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Gate<F> {
+    Add(usize, usize),
+    Sub(usize, usize),
+    /// Multiplication by a public constant
+    Scale(usize, F),
+    /// Addition of a public column of m values
+    AddPublic(usize, Vec<F>),
+    Mul(usize, usize),
+    /// Row i of the output is row route[i].1 of wire route[i].0
+    Route(Vec<(usize, usize)>),
+}
+
+impl<F> Gate<F> {
+    pub fn inputs(&self) -> Vec<usize> {
+        match self {
+            Gate::Add(a, b) | Gate::Sub(a, b) | Gate::Mul(a, b) => vec![*a, *b],
+            Gate::Scale(a, _) | Gate::AddPublic(a, _) => vec![*a],
+            Gate::Route(route) => route.iter().map(|(wire, _)| *wire).collect(),
+        }
+    }
+
+    /// Can the parties compute it without talking to each other?
+    pub fn is_local(&self) -> bool {
+        !matches!(self, Gate::Mul(..) | Gate::Route(_))
+    }
+}
+
+/// An arithmetic circuit over columns of m values.
+/// Wires 0..inputs are the primary inputs and wire inputs + i is the output of gate i.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Circuit<F> {
+    pub m: usize,
+    pub inputs: usize,
+    pub gates: Vec<Gate<F>>,
+}
+
+impl<F: PrimeField> Circuit<F> {
+    pub fn new(m: usize, inputs: usize) -> Self {
+        Circuit {
+            m,
+            inputs,
+            gates: Vec::new(),
+        }
+    }
+
+    pub fn n_wires(&self) -> usize {
+        self.inputs + self.gates.len()
+    }
+
+    /// Appends a gate and returns its output wire
+    pub fn gate(&mut self, gate: Gate<F>) -> usize {
+        let wire = self.n_wires();
+        assert!(
+            gate.inputs().iter().all(|&input| input < wire),
+            "gate {:?} reads a wire that is not defined yet",
+            gate
+        );
+        match &gate {
+            Gate::AddPublic(_, values) => assert_eq!(values.len(), self.m),
+            Gate::Route(route) => {
+                assert_eq!(route.len(), self.m);
+                assert!(route.iter().all(|&(_, row)| row < self.m));
+            }
+            _ => {}
+        }
+        self.gates.push(gate);
+        wire
+    }
+
+    /// Number of rounds of communication before each wire is ready
+    pub fn levels(&self) -> Vec<usize> {
+        let mut levels = vec![0; self.inputs];
+        for gate in self.gates.iter() {
+            let ready = gate.inputs().iter().map(|&w| levels[w]).max().unwrap_or(0);
+            levels.push(if gate.is_local() { ready } else { ready + 1 });
+        }
+        levels
+    }
+
+    /// Evaluates the circuit in the clear, returns the values of every wire
+    pub fn eval(&self, inputs: Vec<Vec<F>>) -> Vec<Vec<F>> {
+        assert_eq!(inputs.len(), self.inputs);
+        let mut wires = inputs;
+        for gate in self.gates.iter() {
+            let out = match gate {
+                Gate::Mul(a, b) => zip_with(&wires[*a], &wires[*b], |a, b| a * b),
+                Gate::Route(route) => route.iter().map(|&(wire, row)| wires[wire][row]).collect(),
+                Gate::AddPublic(a, values) => zip_with(&wires[*a], values, |a, b| a + b),
+                gate => eval_linear(gate, &wires),
+            };
+            wires.push(out);
+        }
+        wires
+    }
+}
+
+fn zip_with<F: Copy>(a: &[F], b: &[F], f: impl Fn(F, F) -> F) -> Vec<F> {
+    a.iter().zip(b.iter()).map(|(a, b)| f(*a, *b)).collect()
+}
+
+/// Gates that are the same on values and on shares
+fn eval_linear<F: PrimeField>(gate: &Gate<F>, wires: &[Vec<F>]) -> Vec<F> {
+    match gate {
+        Gate::Add(a, b) => zip_with(&wires[*a], &wires[*b], |a, b| a + b),
+        Gate::Sub(a, b) => zip_with(&wires[*a], &wires[*b], |a, b| a - b),
+        Gate::Scale(a, c) => wires[*a].iter().map(|a| *a * c).collect(),
+        _ => unreachable!(),
+    }
+}
+
+/// Evaluates circuit on packed shares of its primary inputs and returns packed shares of every
+/// wire, the advice cells of the extended witness.
+/// Consumes one set of double sharings and one set of route masks per level that has products
+/// or routes.
+pub fn d_eval<F, P, N>(
+    circuit: &Circuit<F>,
+    inputs: Vec<Vec<F>>,
+    preprocessing: &mut P,
+    net: &N,
+    pp: &PackedSharingParams<F>,
+) -> Result<Vec<Vec<F>>, NetError>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    P: Preprocessing<F>,
+    N: MpcNetwork,
+{
+    assert_eq!(inputs.len(), circuit.inputs);
+    debug_assert_eq!(circuit.m % pp.l, 0, "Mismatch of size in d_eval");
+    debug_assert!(
+        inputs.iter().all(|input| input.len() == circuit.m / pp.l),
+        "Mismatch of size in d_eval"
+    );
+    let _label = label("dwit");
+    let levels = circuit.levels();
+    let depth = levels.iter().copied().max().unwrap_or(0);
+
+    let mut wires = inputs;
+    wires.resize(circuit.n_wires(), Vec::new());
+    for level in 0..=depth {
+        let gates: Vec<(usize, &Gate<F>)> = circuit
+            .gates
+            .iter()
+            .enumerate()
+            .map(|(i, gate)| (circuit.inputs + i, gate))
+            .filter(|(wire, _)| levels[*wire] == level)
+            .collect();
+
+        // All the products of the level in one degree reduction
+        let muls: Vec<(usize, usize, usize)> = gates
+            .iter()
+            .filter_map(|(wire, gate)| match gate {
+                Gate::Mul(a, b) => Some((*wire, *a, *b)),
+                _ => None,
+            })
+            .collect();
+        if !muls.is_empty() {
+            let mul_timer = start_timer!(|| "Products");
+            let products: Vec<F> = muls
+                .iter()
+                .flat_map(|&(_, a, b)| zip_with(&wires[a], &wires[b], |a, b| a * b))
+                .collect();
+            let masks = preprocessing.double_sharings(products.len(), pp)?;
+            let reduced = deg_red(products, masks, net, pp)?;
+            for (&(wire, _, _), shares) in muls.iter().zip(reduced.chunks(circuit.m / pp.l)) {
+                wires[wire] = shares.to_vec();
+            }
+            end_timer!(mul_timer);
+        }
+
+        // All the routes of the level in one reshare
+        let routes: Vec<(usize, &Vec<(usize, usize)>)> = gates
+            .iter()
+            .filter_map(|(wire, gate)| match gate {
+                Gate::Route(route) => Some((*wire, route)),
+                _ => None,
+            })
+            .collect();
+        if !routes.is_empty() {
+            let route_timer = start_timer!(|| "Routes");
+            let outputs: Vec<usize> = routes.iter().map(|(wire, _)| *wire).collect();
+            let routes: Vec<&Vec<(usize, usize)>> = routes.iter().map(|(_, r)| *r).collect();
+            let routed = route_wires(&routes, &wires, circuit.m, preprocessing, net, pp)?;
+            for (wire, shares) in outputs.into_iter().zip(routed.chunks(circuit.m / pp.l)) {
+                wires[wire] = shares.to_vec();
+            }
+            end_timer!(route_timer);
+        }
+
+        // The local gates of the level only read wires of this level or lower
+        for (wire, gate) in gates.iter() {
+            match gate {
+                Gate::Mul(..) | Gate::Route(_) => {}
+                Gate::AddPublic(a, values) => {
+                    let public = pack_vec(values, pp)
+                        .into_iter()
+                        .map(|shares| shares[net.party_id()])
+                        .collect::<Vec<_>>();
+                    wires[*wire] = zip_with(&wires[*a], &public, |a, b| a + b);
+                }
+                gate => wires[*wire] = eval_linear(gate, &wires),
+            }
+        }
+    }
+    Ok(wires)
+}
+
+/// The shape of d_eval on circuit, so its preprocessing can be dealt before the inputs exist
+pub fn eval_shape<F>(circuit: &Circuit<F>, pp: &PackedSharingParams<F>) -> Result<Shape, NetError>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+{
+    let zeros = vec![vec![F::ZERO; circuit.m / pp.l]; circuit.inputs];
+    let mut shapes = simulate(pp.n, |net| {
+        let mut recorder = Recorder::new(KingDealer::new(net));
+        d_eval(circuit, zeros.clone(), &mut recorder, net, pp).map(|_| recorder.into_shape())
+    });
+    shapes.swap_remove(0)
+}
+
+/// Moves the values of wires to the outputs of routes, the king only opens masked values
+fn route_wires<F, P, N>(
+    routes: &[&Vec<(usize, usize)>],
+    wires: &[Vec<F>],
+    m: usize,
+    preprocessing: &mut P,
+    net: &N,
+    pp: &PackedSharingParams<F>,
+) -> Result<Vec<F>, NetError>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    P: Preprocessing<F>,
+    N: MpcNetwork,
+{
+    // Only the wires the routes read are opened, one after the other
+    let mut sources: Vec<usize> = routes.iter().flat_map(|r| r.iter().map(|(w, _)| *w)).collect();
+    sources.sort_unstable();
+    sources.dedup();
+    let map: Vec<Option<usize>> = routes
+        .iter()
+        .flat_map(|route| route.iter())
+        .map(|&(wire, row)| Some(sources.binary_search(&wire).unwrap() * m + row))
+        .collect();
+
+    let values: Vec<F> = sources
+        .iter()
+        .flat_map(|&wire| wires[wire].iter().copied())
+        .collect();
+    d_route(values, &map, false, preprocessing, net, pp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{preprocessing::dealer, utils::pack::transpose};
+    use ff::Field;
+    use halo2_proofs::halo2curves::bn256::Fr as F;
+    use mpc_net::Kings;
+
+    const L: usize = 2;
+    const M: usize = 8;
+
+    /// Products, routes across rows and columns, and a level with both
+    fn circuit() -> Circuit<F> {
+        let mut circuit = Circuit::new(M, 2);
+        let (x, k) = (0, 1);
+        let sum = circuit.gate(Gate::Add(x, k));
+        let square = circuit.gate(Gate::Mul(sum, sum));
+        let cube = circuit.gate(Gate::Mul(square, sum));
+        let constants = (0..M).map(|i| F::from(i as u64)).collect();
+        let shifted = circuit.gate(Gate::AddPublic(cube, constants));
+        let rotated = circuit.gate(Gate::Route((0..M).map(|i| (shifted, (i + 1) % M)).collect()));
+        let mixed = circuit.gate(Gate::Route(
+            (0..M)
+                .map(|i| if i % 2 == 0 { (rotated, M - 1 - i) } else { (x, i) })
+                .collect(),
+        ));
+        let product = circuit.gate(Gate::Mul(rotated, k));
+        let scaled = circuit.gate(Gate::Scale(product, F::from(3)));
+        circuit.gate(Gate::Sub(scaled, mixed));
+        circuit
+    }
+
+    fn check_eval(kings: Kings) {
+        let pp = PackedSharingParams::<F>::new(L);
+        let rng = &mut ark_std::test_rng();
+        let circuit = circuit();
+        let inputs: Vec<Vec<F>> = (0..2)
+            .map(|_| (0..M).map(|_| F::random(&mut *rng)).collect())
+            .collect();
+        let input_shares: Vec<Vec<Vec<F>>> =
+            inputs.iter().map(|input| transpose(pack_vec(input, &pp))).collect();
+
+        let shares = simulate(pp.n, |net| {
+            let net = &net.with_kings(kings);
+            let id = net.party_id();
+            let inputs = input_shares.iter().map(|shares| shares[id].clone()).collect();
+            d_eval(&circuit, inputs, &mut KingDealer::new(net), net, &pp).unwrap()
+        });
+
+        let expected = circuit.eval(inputs);
+        for (wire, expected) in expected.iter().enumerate() {
+            let shares = transpose(shares.iter().map(|wires| wires[wire].clone()).collect());
+            assert!(shares.iter().all(|x| pp.is_consistent(x, false)));
+            let values: Vec<F> = shares.iter().flat_map(|x| pp.unpack(x)).collect();
+            assert_eq!(&values, expected, "wire {}", wire);
+        }
+    }
+
+    #[test]
+    fn d_eval_test() {
+        check_eval(Kings::One(0));
+    }
+
+    #[test]
+    fn d_eval_spread_test() {
+        check_eval(Kings::Spread(3));
+    }
+
+    #[test]
+    fn levels_test() {
+        assert_eq!(circuit().levels(), vec![0, 0, 0, 1, 2, 2, 3, 4, 4, 4, 4]);
+    }
+
+    #[test]
+    fn shape_test() {
+        // One degree reduction per level with products and one route per level with routes
+        let pp = PackedSharingParams::<F>::new(L);
+        let circuit = circuit();
+        let shape = eval_shape(&circuit, &pp).unwrap();
+        assert_eq!(shape.requests.len(), 5);
+
+        // The dealer's preprocessing runs the same evaluation
+        let stores = dealer::deal(&shape, &pp, &mut ark_std::test_rng());
+        simulate(pp.n, |net| {
+            let mut store = stores[net.party_id()].clone();
+            let inputs = vec![vec![F::ZERO; M / L]; 2];
+            d_eval(&circuit, inputs, &mut store, net, &pp).unwrap();
+            assert!(store.is_empty());
+        });
+    }
+}
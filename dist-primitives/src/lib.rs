@@ -2,8 +2,11 @@ pub mod channel;
 pub mod dfft;
 pub mod dmsm;
 pub mod dpp;
+pub mod dwit;
+pub mod preprocessing;
 pub mod utils;
 
+use mpc_net::{noise, Kings, MultiNetwork, NetError, Stats};
 use std::path::PathBuf;
 use structopt::StructOpt;
 
@@ -25,4 +28,47 @@ pub struct Opt {
 
     /// FFT size
     pub m: usize,
+
+    /// Private key file, encrypts and authenticates the connections to the parties
+    #[structopt(long, parse(from_os_str))]
+    pub key: Option<PathBuf>,
+
+    /// Number of parties that split the work of king
+    #[structopt(long, default_value = "1")]
+    pub kings: usize,
+
+    /// File to write the network statistics to as JSON, {id} is replaced by the party id
+    #[structopt(long, parse(from_os_str))]
+    pub stats: Option<PathBuf>,
+}
+
+impl Opt {
+    /// Connects to the parties of the host configuration
+    pub fn network(&self) -> Result<MultiNetwork, NetError> {
+        let path = self.input.to_str().unwrap();
+        match &self.key {
+            Some(key) => {
+                MultiNetwork::from_file_with_key(path, self.id, noise::read_private_key(key)?)
+            }
+            None => MultiNetwork::from_file(path, self.id),
+        }
+    }
+
+    /// Writes stats to the file given with --stats, if any
+    pub fn write_stats(&self, stats: &Stats) {
+        if let Some(path) = &self.stats {
+            let path = path.to_str().unwrap().replace("{id}", &self.id.to_string());
+            std::fs::write(&path, stats.to_json())
+                .unwrap_or_else(|e| panic!("cannot write stats to {}: {}", path, e));
+        }
+    }
+
+    /// Party 0 alone or the first parties together
+    pub fn kings(&self) -> Kings {
+        if self.kings > 1 {
+            Kings::Spread(self.kings)
+        } else {
+            Kings::One(0)
+        }
+    }
 }
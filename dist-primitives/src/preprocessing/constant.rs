@@ -0,0 +1,48 @@
+// Sharings of random constants.
+// Every secret is packed into all l slots, so multiplying packed shares by it scales all the
+// values alike. The lookup argument masks the values the king sorts with such constants.
+
+use ff::{PrimeField, WithSmallOrderMulGroup};
+use rand::RngCore;
+use secret_sharing::pss::PackedSharingParams;
+
+use crate::utils::pack::transpose;
+
+/// Packs len random constants, each one into all l slots, and returns the shares of every party
+pub fn deal<F, R>(len: usize, pp: &PackedSharingParams<F>, rng: &mut R) -> Vec<Vec<F>>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3>,
+    R: RngCore,
+{
+    transpose(
+        (0..len)
+            .map(|_| {
+                let constant = F::random(&mut *rng);
+                pp.pack(&vec![constant; pp.l], rng)
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use halo2_proofs::halo2curves::bn256::Fr as F;
+
+    const L: usize = 4;
+    const LEN: usize = 3;
+
+    #[test]
+    fn constant_sharings_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let rng = &mut ark_std::test_rng();
+
+        let shares = deal(LEN, &pp, rng);
+        assert_eq!(shares.len(), pp.n);
+        let constants: Vec<Vec<F>> = transpose(shares).iter().map(|x| pp.unpack(x)).collect();
+        for constant in constants.iter() {
+            assert!(constant.iter().all(|x| *x == constant[0]));
+        }
+        assert_ne!(constants[0][0], constants[1][0]);
+    }
+}
@@ -0,0 +1,166 @@
+// A trusted dealer generates the preprocessing of every party for a shape.
+// The dealer learns all the masks, so it must not take part in the online phase.
+
+use ff::{PrimeField, WithSmallOrderMulGroup};
+use halo2_proofs::poly::EvaluationDomain;
+use rand::RngCore;
+use secret_sharing::pss::PackedSharingParams;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    constant,
+    deg_red::DoubleSharings,
+    dfft::FftMasks,
+    dpp::DppMasks,
+    powers,
+    route::RouteMasks,
+    shape::{Request, Shape},
+    store::{Item, Store},
+    sum_zero, zero,
+};
+
+/// Returns the items of every party answering a single request
+pub fn deal_request<F, R>(
+    request: &Request,
+    pp: &PackedSharingParams<F>,
+    rng: &mut R,
+) -> Vec<Item<F>>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    R: RngCore,
+{
+    match request {
+        Request::Dpp { m } => DppMasks::deal(*m, pp, rng).into_iter().map(Item::Dpp).collect(),
+        Request::DoubleSharings { len } => DoubleSharings::deal(*len, pp, rng)
+            .into_iter()
+            .map(Item::DoubleSharings)
+            .collect(),
+        Request::Fft {
+            inverse,
+            rearrange,
+            pad,
+            degree2,
+            k,
+        } => {
+            let dom = EvaluationDomain::<F>::new(1, *k);
+            FftMasks::deal(*inverse, *rearrange, *pad, *degree2, &dom, pp, rng)
+                .into_iter()
+                .map(Item::Fft)
+                .collect()
+        }
+        Request::PointPowers { len } => powers::deal(*len, pp, rng)
+            .into_iter()
+            .map(Item::PointPowers)
+            .collect(),
+        Request::Route { len, map, degree2 } => RouteMasks::deal(*len, map, *degree2, pp, rng)
+            .into_iter()
+            .map(Item::Route)
+            .collect(),
+        Request::Zeros { len } => zero::deal(*len, pp, rng)
+            .into_iter()
+            .map(Item::Zeros)
+            .collect(),
+        Request::Constants { len } => constant::deal(*len, pp, rng)
+            .into_iter()
+            .map(Item::Constants)
+            .collect(),
+        Request::SumZeros { len } => sum_zero::deal(*len, pp, rng)
+            .into_iter()
+            .map(Item::SumZeros)
+            .collect(),
+    }
+}
+
+/// Generates the preprocessing of every party for all the requests of the shape
+pub fn deal<F, R>(shape: &Shape, pp: &PackedSharingParams<F>, rng: &mut R) -> Vec<Store<F>>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    R: RngCore,
+{
+    let mut stores: Vec<Store<F>> = (0..pp.n).map(Store::new).collect();
+    for request in shape.requests.iter() {
+        for (store, item) in stores.iter_mut().zip(deal_request(request, pp, rng)) {
+            store.push(request.clone(), item);
+        }
+    }
+    stores
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{preprocessing::Preprocessing, utils::pack::transpose};
+    use ff::Field;
+    use halo2_proofs::halo2curves::bn256::Fr as F;
+    use mpc_net::NetError;
+
+    const L: usize = 4;
+    const K: u32 = 5;
+
+    #[test]
+    fn deal_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let dom = EvaluationDomain::<F>::new(1, K);
+        let rng = &mut ark_std::test_rng();
+
+        let point = F::random(&mut *rng);
+        let shape = Shape {
+            requests: vec![
+                Request::Dpp { m: 1 << K },
+                Request::Fft {
+                    inverse: false,
+                    rearrange: false,
+                    pad: 1,
+                    degree2: false,
+                    k: K,
+                },
+                Request::DoubleSharings { len: 2 },
+                Request::PointPowers { len: 2 * L },
+            ],
+        };
+        let mut stores = deal(&shape, &pp, rng);
+        assert_eq!(stores.len(), pp.n);
+
+        let dpp: Vec<_> = stores.iter_mut().map(|s| s.dpp_masks(1 << K, &pp).unwrap()).collect();
+        assert!(dpp.iter().all(|masks| masks.num_mask.len() == (1 << K) / L));
+        let fft: Vec<_> = stores
+            .iter_mut()
+            .map(|s| s.fft_masks(false, false, 1, false, &dom, &pp).unwrap())
+            .collect();
+        assert!(fft.iter().all(|masks| masks.mask.len() == (1 << K) / L));
+        let sharings: Vec<_> = stores
+            .iter_mut()
+            .map(|s| s.double_sharings(2, &pp).unwrap())
+            .collect();
+        assert!(sharings.iter().all(|sharings| sharings.low.len() == 2));
+
+        let powers = transpose(
+            stores
+                .iter_mut()
+                .map(|s| s.point_powers(point, 2 * L, &pp).unwrap())
+                .collect(),
+        );
+        let powers: Vec<F> = powers.iter().flat_map(|x| pp.unpack(x)).collect();
+        assert_eq!(powers[0], F::ONE);
+        assert_eq!(powers[2 * L - 1], point.pow_vartime([2 * L as u64 - 1]));
+
+        assert!(stores.iter().all(|s| s.is_empty()));
+    }
+
+    #[test]
+    fn out_of_order_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let rng = &mut ark_std::test_rng();
+
+        let shape = Shape {
+            requests: vec![Request::DoubleSharings { len: 2 }],
+        };
+        let mut stores = deal(&shape, &pp, rng);
+        let result = stores[0].double_sharings(3, &pp);
+        assert!(matches!(result, Err(NetError::Preprocessing { .. })));
+        // Running out is an error as well
+        stores[1].double_sharings(2, &pp).unwrap();
+        let result = stores[1].double_sharings(2, &pp);
+        assert!(matches!(result, Err(NetError::Preprocessing { .. })));
+    }
+}
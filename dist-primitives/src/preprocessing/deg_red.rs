@@ -0,0 +1,65 @@
+// Double sharings for degree reduction.
+// Parties open x + r from shares of degree 2d, king repacks it with degree d
+// and parties subtract [r]_d, so the king never sees the products.
+
+use ff::{PrimeField, WithSmallOrderMulGroup};
+use rand::RngCore;
+use secret_sharing::pss::PackedSharingParams;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::pack::transpose;
+
+/// One party's shares of the same random secrets packed with degree 2d and degree d
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DoubleSharings<F> {
+    /// Shares of r of degree 2d, added to the shares being reduced
+    pub high: Vec<F>,
+    /// Shares of r of degree d, subtracted from the reduced shares
+    pub low: Vec<F>,
+}
+
+impl<F> DoubleSharings<F>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3>,
+{
+    /// Samples double sharings for len packed shares and returns the shares of every party
+    pub fn deal<R: RngCore>(len: usize, pp: &PackedSharingParams<F>, rng: &mut R) -> Vec<Self> {
+        let mut high = Vec::with_capacity(len);
+        let mut low = Vec::with_capacity(len);
+        for _ in 0..len {
+            let r: Vec<F> = (0..pp.l).map(|_| F::random(&mut *rng)).collect();
+            high.push(pp.pack2(&r, rng));
+            low.push(pp.pack(&r, rng));
+        }
+
+        transpose(high)
+            .into_iter()
+            .zip(transpose(low))
+            .map(|(high, low)| DoubleSharings { high, low })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use halo2_proofs::halo2curves::bn256::Fr as F;
+
+    const L: usize = 4;
+    const LEN: usize = 8;
+
+    #[test]
+    fn double_sharings_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let rng = &mut ark_std::test_rng();
+
+        let sharings = DoubleSharings::deal(LEN, &pp, rng);
+        assert_eq!(sharings.len(), pp.n);
+
+        let high = transpose(sharings.iter().map(|s| s.high.clone()).collect());
+        let low = transpose(sharings.iter().map(|s| s.low.clone()).collect());
+        for (high, low) in high.iter().zip(low.iter()) {
+            assert_eq!(pp.unpack2(high), pp.unpack(low));
+        }
+    }
+}
@@ -0,0 +1,118 @@
+// Masks for the king round of d_fft and d_ifft.
+// Parties add shares of r to the output of FFT1 so that the king only opens masked values.
+// FFT2, padding and rearranging are linear, so the parties subtract shares of the transformed r.
+// When the input has degree 2d so does r, otherwise the king would open more than the masked
+// values.
+
+use ff::{PrimeField, WithSmallOrderMulGroup};
+use halo2_proofs::poly::EvaluationDomain;
+use rand::RngCore;
+use secret_sharing::pss::PackedSharingParams;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dfft::dfft::fft2_rearrange_pad,
+    utils::{domain_utils::EvaluationDomainExt, pack::transpose},
+};
+
+/// One party's packed shares of the masks for a single call to d_fft or d_ifft
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FftMasks<F> {
+    /// Shares of r, in the layout the king unpacks after FFT1
+    pub mask: Vec<F>,
+    /// Shares of FFT2(r) after padding and rearranging, in the output layout
+    pub transformed: Vec<F>,
+}
+
+impl<F> FftMasks<F>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+{
+    /// Samples the masks for an FFT over dom and returns the shares of every party.
+    /// inverse, rearrange, pad and degree2 must match the call they are used in.
+    pub fn deal<R: RngCore>(
+        inverse: bool,
+        rearrange: bool,
+        pad: usize,
+        degree2: bool,
+        dom: &EvaluationDomain<F>,
+        pp: &PackedSharingParams<F>,
+        rng: &mut R,
+    ) -> Vec<Self> {
+        let omega = if inverse {
+            dom.get_omega_inv()
+        } else {
+            dom.get_omega()
+        };
+
+        let r: Vec<F> = (0..dom.size()).map(|_| F::random(&mut *rng)).collect();
+        let transformed = fft2_rearrange_pad(r.clone(), omega, rearrange, pad, dom, pp);
+
+        let mut share = |values: Vec<F>, degree2: bool| -> Vec<Vec<F>> {
+            transpose(
+                values
+                    .chunks(pp.l)
+                    .map(|chunk| {
+                        if degree2 {
+                            pp.pack2(&chunk.to_vec(), rng)
+                        } else {
+                            pp.pack(&chunk.to_vec(), rng)
+                        }
+                    })
+                    .collect(),
+            )
+        };
+        // The king repacks with degree d whatever the input
+        let mask = share(r, degree2);
+        let transformed = share(transformed, false);
+
+        mask.into_iter()
+            .zip(transformed)
+            .map(|(mask, transformed)| FftMasks { mask, transformed })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use halo2_proofs::halo2curves::bn256::Fr as F;
+
+    const L: usize = 4;
+    const K: u32 = 5;
+
+    #[test]
+    fn masks_transform_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let dom = EvaluationDomain::<F>::new(1, K);
+        let rng = &mut ark_std::test_rng();
+
+        let open = |shares: Vec<Vec<F>>, degree2: bool| -> Vec<F> {
+            let shares = transpose(shares);
+            assert!(shares.iter().all(|x| pp.is_consistent(x, degree2)));
+            shares
+                .iter()
+                .flat_map(|x| if degree2 { pp.unpack2(x) } else { pp.unpack(x) })
+                .collect()
+        };
+
+        let cases = [(false, false, 1, false), (true, true, 2, true)];
+        for (inverse, rearrange, pad, degree2) in cases {
+            let masks = FftMasks::deal(inverse, rearrange, pad, degree2, &dom, &pp, rng);
+            assert_eq!(masks.len(), pp.n);
+
+            let mask = open(masks.iter().map(|m| m.mask.clone()).collect(), degree2);
+            let transformed = open(masks.iter().map(|m| m.transformed.clone()).collect(), false);
+
+            let omega = if inverse {
+                dom.get_omega_inv()
+            } else {
+                dom.get_omega()
+            };
+            assert_eq!(
+                transformed,
+                fft2_rearrange_pad(mask, omega, rearrange, pad, &dom, &pp)
+            );
+        }
+    }
+}
@@ -0,0 +1,118 @@
+// Masks for the partial products protocol.
+// For x_i = num_i / den_i and random non-zero r_0, .., r_m and u_1, .., u_m the king opens
+// a_i = num_i r_{i-1} u_i and b_i = den_i r_i u_i. The prefix products of a_i / b_i are
+// r_0 / r_i times the prefix products of x_i, which the parties fix by multiplying with r_i / r_0.
+// a_i and b_i are products of shares, so the parties add sharings of zero before opening them.
+
+use ff::{Field, PrimeField, WithSmallOrderMulGroup};
+use rand::RngCore;
+use secret_sharing::pss::PackedSharingParams;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::pack::transpose;
+
+use super::{deg_red::DoubleSharings, zero};
+
+/// One party's packed shares of the masks for a single call to d_pp over m values
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DppMasks<F> {
+    /// Shares of r_{i-1} u_i, multiplied into the numerators
+    pub num_mask: Vec<F>,
+    /// Shares of r_i u_i, multiplied into the denominators
+    pub den_mask: Vec<F>,
+    /// Shares of r_i / r_0, removes the masks from the partial products
+    pub unmask: Vec<F>,
+    /// Double sharings for reducing the degree of the unmasked partial products
+    pub reduce: DoubleSharings<F>,
+    /// Sharings of zero of degree 2d, added to the masked numerators and denominators
+    pub zero: Vec<F>,
+}
+
+impl<F> DppMasks<F>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3>,
+{
+    /// Samples the masks for m values and returns the shares of every party
+    pub fn deal<R: RngCore>(m: usize, pp: &PackedSharingParams<F>, rng: &mut R) -> Vec<Self> {
+        debug_assert_eq!(m % pp.l, 0, "Mismatch of size in DppMasks");
+        let non_zero = |rng: &mut R| loop {
+            let x = F::random(&mut *rng);
+            if x != F::ZERO {
+                return x;
+            }
+        };
+
+        let r: Vec<F> = (0..=m).map(|_| non_zero(rng)).collect();
+        let u: Vec<F> = (0..m).map(|_| non_zero(rng)).collect();
+        let r0_inv = r[0].invert().unwrap();
+
+        let num_mask: Vec<F> = (0..m).map(|i| r[i] * u[i]).collect();
+        let den_mask: Vec<F> = (0..m).map(|i| r[i + 1] * u[i]).collect();
+        let unmask: Vec<F> = (0..m).map(|i| r[i + 1] * r0_inv).collect();
+
+        let mut share = |values: Vec<F>| -> Vec<Vec<F>> {
+            transpose(
+                values
+                    .chunks(pp.l)
+                    .map(|chunk| pp.pack(&chunk.to_vec(), rng))
+                    .collect(),
+            )
+        };
+        let num_mask = share(num_mask);
+        let den_mask = share(den_mask);
+        let unmask = share(unmask);
+        let reduce = DoubleSharings::deal(m / pp.l, pp, rng);
+        let zero = zero::deal(2 * m / pp.l, pp, rng);
+
+        num_mask
+            .into_iter()
+            .zip(den_mask)
+            .zip(unmask)
+            .zip(reduce)
+            .zip(zero)
+            .map(|((((num_mask, den_mask), unmask), reduce), zero)| DppMasks {
+                num_mask,
+                den_mask,
+                unmask,
+                reduce,
+                zero,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use halo2_proofs::halo2curves::bn256::Fr as F;
+
+    const L: usize = 4;
+    const M: usize = 32;
+
+    #[test]
+    fn masks_cancel_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let rng = &mut ark_std::test_rng();
+
+        let masks = DppMasks::deal(M, &pp, rng);
+        assert_eq!(masks.len(), pp.n);
+
+        let open = |shares: Vec<Vec<F>>| -> Vec<F> {
+            transpose(shares).iter().flat_map(|x| pp.unpack(x)).collect()
+        };
+        let num_mask = open(masks.iter().map(|m| m.num_mask.clone()).collect());
+        let den_mask = open(masks.iter().map(|m| m.den_mask.clone()).collect());
+        let unmask = open(masks.iter().map(|m| m.unmask.clone()).collect());
+
+        // The prefix products of num_mask / den_mask are r_0 / r_i
+        let mut prefix = F::ONE;
+        for i in 0..M {
+            prefix *= num_mask[i] * den_mask[i].invert().unwrap();
+            assert_eq!(prefix * unmask[i], F::ONE);
+        }
+
+        let zero = transpose(masks.iter().map(|m| m.zero.clone()).collect());
+        assert_eq!(zero.len(), 2 * M / L);
+        assert!(zero.iter().all(|x| pp.unpack2(x).iter().all(|x| *x == F::ZERO)));
+    }
+}
@@ -0,0 +1,469 @@
+// Generation of the preprocessing by the parties themselves, without a trusted dealer.
+// Every party deals random items and the parties locally apply a hyper-invertible matrix with n
+// columns and n - t rows to the n items they received. Any n - t of the contributions map
+// bijectively onto the outputs, so all n - t outputs are uniformly random as long as at most t
+// parties are corrupted. This works for masks that are linear relations between shares, the
+// double sharings, the FFT masks, the route masks, the sharings of zero or of values that sum
+// to zero, the packings of zeros behind the point powers and the sharings of random constants,
+// a combination of constants is again a constant. The masks of d_pp are products of random
+// values instead, they are the product of the masks dealt by parties 0..=t and computed with
+// deg_red.
+
+use std::collections::VecDeque;
+
+use ff::{PrimeField, WithSmallOrderMulGroup};
+use mpc_net::{label, MpcNetwork, NetError};
+use rand::RngCore;
+use secret_sharing::pss::PackedSharingParams;
+use serde::{Deserialize, Serialize};
+
+use crate::{channel::channel::MpcSerNet, utils::deg_red::deg_red};
+
+use super::{
+    deg_red::DoubleSharings,
+    dealer::deal_request,
+    dfft::FftMasks,
+    dpp::DppMasks,
+    route::RouteMasks,
+    shape::{Request, Shape},
+    store::{Item, Store},
+};
+
+/// Layout of the contributions for a shape
+struct Plan {
+    /// Distinct linear requests along with the number of batches of n - t outputs they need
+    linear: Vec<(Request, usize)>,
+    /// Sizes of the d_pp requests
+    dpp: Vec<usize>,
+}
+
+impl Plan {
+    fn new<F: PrimeField>(shape: &Shape, pp: &PackedSharingParams<F>) -> Self {
+        let mut counts: Vec<(Request, usize)> = Vec::new();
+        let mut count = |request: Request| match counts.iter_mut().find(|(r, _)| *r == request) {
+            Some((_, c)) => *c += 1,
+            None => counts.push((request, 1)),
+        };
+
+        let mut dpp = Vec::new();
+        for request in shape.requests.iter() {
+            match *request {
+                Request::Dpp { m } => {
+                    // One degree reduction of num, den and unmask per product
+                    for _ in 0..pp.t {
+                        count(Request::DoubleSharings { len: 3 * m / pp.l });
+                    }
+                    dpp.push(m);
+                }
+                Request::DoubleSharings { .. }
+                | Request::Fft { .. }
+                | Request::Route { .. }
+                | Request::Zeros { .. }
+                | Request::Constants { .. }
+                | Request::SumZeros { .. }
+                | Request::PointPowers { .. } => count(request.clone()),
+            }
+        }
+
+        let outputs = pp.n - pp.t;
+        Plan {
+            linear: counts
+                .into_iter()
+                .map(|(request, c)| (request, (c + outputs - 1) / outputs))
+                .collect(),
+            dpp,
+        }
+    }
+}
+
+/// Vandermonde matrix over the points 1, .., n with n - t rows, any n - t columns are invertible
+fn him_matrix<F: PrimeField>(pp: &PackedSharingParams<F>) -> Vec<Vec<F>> {
+    let mut rows = vec![vec![F::ONE; pp.n]];
+    for _ in 1..pp.n - pp.t {
+        let prev = rows.last().unwrap();
+        let row = prev
+            .iter()
+            .enumerate()
+            .map(|(j, x)| *x * F::from(j as u64 + 1))
+            .collect();
+        rows.push(row);
+    }
+    rows
+}
+
+fn lincomb<'a, F: PrimeField>(vectors: impl Iterator<Item = &'a Vec<F>>, coeffs: &[F]) -> Vec<F> {
+    let mut out: Vec<F> = Vec::new();
+    for (vector, c) in vectors.zip(coeffs.iter()) {
+        out.resize(vector.len(), F::ZERO);
+        out.iter_mut().zip(vector.iter()).for_each(|(o, v)| *o += *v * c);
+    }
+    out
+}
+
+/// Linear combination of the same kind of linear items dealt by every party
+fn combine_linear<F: PrimeField>(items: &[&Item<F>], coeffs: &[F]) -> Item<F> {
+    match items[0] {
+        Item::DoubleSharings(_) => {
+            let sharings: Vec<&DoubleSharings<F>> = items
+                .iter()
+                .map(|item| match item {
+                    Item::DoubleSharings(sharings) => sharings,
+                    _ => panic!("contributions do not match the shape"),
+                })
+                .collect();
+            Item::DoubleSharings(DoubleSharings {
+                high: lincomb(sharings.iter().map(|s| &s.high), coeffs),
+                low: lincomb(sharings.iter().map(|s| &s.low), coeffs),
+            })
+        }
+        Item::Fft(_) => {
+            let masks: Vec<&FftMasks<F>> = items
+                .iter()
+                .map(|item| match item {
+                    Item::Fft(masks) => masks,
+                    _ => panic!("contributions do not match the shape"),
+                })
+                .collect();
+            Item::Fft(FftMasks {
+                mask: lincomb(masks.iter().map(|m| &m.mask), coeffs),
+                transformed: lincomb(masks.iter().map(|m| &m.transformed), coeffs),
+            })
+        }
+        Item::Route(_) => {
+            let masks: Vec<&RouteMasks<F>> = items
+                .iter()
+                .map(|item| match item {
+                    Item::Route(masks) => masks,
+                    _ => panic!("contributions do not match the shape"),
+                })
+                .collect();
+            Item::Route(RouteMasks {
+                mask: lincomb(masks.iter().map(|m| &m.mask), coeffs),
+                routed: lincomb(masks.iter().map(|m| &m.routed), coeffs),
+            })
+        }
+        Item::Zeros(_) => {
+            let zeros = items.iter().map(|item| match item {
+                Item::Zeros(zeros) => zeros,
+                _ => panic!("contributions do not match the shape"),
+            });
+            Item::Zeros(lincomb(zeros, coeffs))
+        }
+        Item::PointPowers(_) => {
+            let zeros = items.iter().map(|item| match item {
+                Item::PointPowers(zeros) => zeros,
+                _ => panic!("contributions do not match the shape"),
+            });
+            Item::PointPowers(lincomb(zeros, coeffs))
+        }
+        Item::Constants(_) => {
+            let constants = items.iter().map(|item| match item {
+                Item::Constants(constants) => constants,
+                _ => panic!("contributions do not match the shape"),
+            });
+            Item::Constants(lincomb(constants, coeffs))
+        }
+        Item::SumZeros(_) => {
+            let zeros = items.iter().map(|item| match item {
+                Item::SumZeros(zeros) => zeros,
+                _ => panic!("contributions do not match the shape"),
+            });
+            Item::SumZeros(lincomb(zeros, coeffs))
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Returns the items this party deals to every party, indexed by [receiver][item].
+/// Every party must receive its items privately.
+pub fn contribute<F, R>(
+    shape: &Shape,
+    party_id: usize,
+    pp: &PackedSharingParams<F>,
+    rng: &mut R,
+) -> Vec<Vec<Item<F>>>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    R: RngCore,
+{
+    let plan = Plan::new(shape, pp);
+    let mut out: Vec<Vec<Item<F>>> = vec![Vec::new(); pp.n];
+    let mut push = |items: Vec<Item<F>>| {
+        out.iter_mut().zip(items).for_each(|(out, item)| out.push(item));
+    };
+
+    for (request, batches) in plan.linear.iter() {
+        for _ in 0..*batches {
+            push(deal_request(request, pp, rng));
+        }
+    }
+    if party_id <= pp.t {
+        for &m in plan.dpp.iter() {
+            push(DppMasks::deal(m, pp, rng).into_iter().map(Item::Dpp).collect());
+        }
+    }
+    out
+}
+
+/// Combines the items received from every party, indexed by [sender][item], into the
+/// preprocessing of this party. Masks for d_pp need a few rounds of deg_red with king.
+pub fn combine<F, N>(
+    shape: &Shape,
+    received: Vec<Vec<Item<F>>>,
+    net: &N,
+    pp: &PackedSharingParams<F>,
+) -> Result<Store<F>, NetError>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    N: MpcNetwork,
+{
+    let plan = Plan::new(shape, pp);
+    let him = him_matrix(pp);
+    let num_linear: usize = plan.linear.iter().map(|(_, batches)| batches).sum();
+    debug_assert_eq!(received.len(), pp.n, "Mismatch of size in contributions");
+
+    // n - t outputs per batch of linear items
+    let mut outputs: Vec<VecDeque<Item<F>>> = Vec::with_capacity(plan.linear.len());
+    let mut offset = 0;
+    for (_, batches) in plan.linear.iter() {
+        let mut queue = VecDeque::new();
+        for batch in offset..offset + batches {
+            let items: Vec<&Item<F>> = received.iter().map(|items| &items[batch]).collect();
+            for row in him.iter() {
+                queue.push_back(combine_linear(&items, row));
+            }
+        }
+        outputs.push(queue);
+        offset += batches;
+    }
+    let mut next_linear = |request: &Request| -> Item<F> {
+        let index = plan
+            .linear
+            .iter()
+            .position(|(r, _)| r == request)
+            .unwrap();
+        outputs[index].pop_front().unwrap()
+    };
+
+    let mut store = Store::new(net.party_id());
+    let mut dpp_index = 0;
+    for request in shape.requests.iter() {
+        let item = match *request {
+            Request::DoubleSharings { .. }
+            | Request::Fft { .. }
+            | Request::Route { .. }
+            | Request::Zeros { .. }
+            | Request::Constants { .. }
+            | Request::SumZeros { .. }
+            | Request::PointPowers { .. } => next_linear(request),
+            Request::Dpp { m } => {
+                let masks: Vec<&DppMasks<F>> = received[..=pp.t]
+                    .iter()
+                    .map(|items| match &items[num_linear + dpp_index] {
+                        Item::Dpp(masks) => masks,
+                        _ => panic!("contributions do not match the shape"),
+                    })
+                    .collect();
+                dpp_index += 1;
+
+                let concat = |masks: &DppMasks<F>| -> Vec<F> {
+                    [&masks.num_mask[..], &masks.den_mask[..], &masks.unmask[..]].concat()
+                };
+                let mut product = concat(masks[0]);
+                for next in masks[1..].iter() {
+                    let product2: Vec<F> = product
+                        .iter()
+                        .zip(concat(next).iter())
+                        .map(|(a, b)| *a * b)
+                        .collect();
+                    let sharings = match next_linear(&Request::DoubleSharings {
+                        len: 3 * m / pp.l,
+                    }) {
+                        Item::DoubleSharings(sharings) => sharings,
+                        _ => unreachable!(),
+                    };
+                    product = deg_red(product2, sharings, net, pp)?;
+                }
+
+                // A sum of double sharings is a double sharing
+                let ones = vec![F::ONE; masks.len()];
+                let reduce = DoubleSharings {
+                    high: lincomb(masks.iter().map(|m| &m.reduce.high), &ones),
+                    low: lincomb(masks.iter().map(|m| &m.reduce.low), &ones),
+                };
+                // and so is a sum of sharings of zero
+                let zero = lincomb(masks.iter().map(|m| &m.zero), &ones);
+                let len = m / pp.l;
+                Item::Dpp(DppMasks {
+                    num_mask: product[..len].to_vec(),
+                    den_mask: product[len..2 * len].to_vec(),
+                    unmask: product[2 * len..].to_vec(),
+                    reduce,
+                    zero,
+                })
+            }
+        };
+        store.push(request.clone(), item);
+    }
+    Ok(store)
+}
+
+/// Generates the preprocessing for shape: every party sends its contributions privately to the
+/// other parties and combines what it received
+pub fn generate<F, R, N>(
+    shape: &Shape,
+    net: &N,
+    pp: &PackedSharingParams<F>,
+    rng: &mut R,
+) -> Result<Store<F>, NetError>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    R: RngCore,
+    N: MpcNetwork,
+{
+    let _label = label("him");
+    let contributions = contribute(shape, net.party_id(), pp, rng);
+    let received = net.all_to_all(&contributions)?;
+    combine(shape, received, net, pp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{preprocessing::Preprocessing, utils::pack::transpose};
+    use ff::Field;
+    use halo2_proofs::{halo2curves::bn256::Fr as F, poly::EvaluationDomain};
+    use mpc_net::local::simulate;
+
+    const L: usize = 4;
+    const K: u32 = 5;
+
+    /// Every party combines what it received, all parties run in this process
+    fn combine_all(shape: &Shape, pp: &PackedSharingParams<F>) -> Vec<Store<F>> {
+        let rng = &mut ark_std::test_rng();
+        // contributions[sender][receiver][item] -> received[receiver][sender][item]
+        let contributions: Vec<Vec<Vec<Item<F>>>> = (0..pp.n)
+            .map(|party_id| contribute(shape, party_id, pp, rng))
+            .collect();
+        let received = transpose(contributions);
+        simulate(pp.n, |net| {
+            combine(shape, received[net.party_id()].clone(), net, pp).unwrap()
+        })
+    }
+
+    #[test]
+    fn him_matrix_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let him = him_matrix(&pp);
+        assert_eq!(him.len(), pp.n - pp.t);
+        assert!(him.iter().all(|row| row.len() == pp.n));
+    }
+
+    #[test]
+    fn combine_linear_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let dom = EvaluationDomain::<F>::new(1, K);
+
+        // More requests than outputs per batch so that some need a second batch
+        let mut requests = vec![Request::DoubleSharings { len: 2 }; pp.n];
+        requests.push(Request::Fft {
+            inverse: true,
+            rearrange: true,
+            pad: 2,
+            degree2: false,
+            k: K,
+        });
+        let shape = Shape { requests };
+
+        let mut stores = combine_all(&shape, &pp);
+
+        for _ in 0..pp.n {
+            let sharings: Vec<_> = stores
+                .iter_mut()
+                .map(|s| s.double_sharings(2, &pp).unwrap())
+                .collect();
+            let high = transpose(sharings.iter().map(|s| s.high.clone()).collect());
+            let low = transpose(sharings.iter().map(|s| s.low.clone()).collect());
+            for (high, low) in high.iter().zip(low.iter()) {
+                assert_eq!(pp.unpack2(high), pp.unpack(low));
+            }
+        }
+
+        let masks: Vec<_> = stores
+            .iter_mut()
+            .map(|s| s.fft_masks(true, true, 2, false, &dom, &pp).unwrap())
+            .collect();
+        let open = |shares: Vec<Vec<F>>| -> Vec<F> {
+            transpose(shares).iter().flat_map(|x| pp.unpack(x)).collect()
+        };
+        let mask = open(masks.iter().map(|m| m.mask.clone()).collect());
+        let transformed = open(masks.iter().map(|m| m.transformed.clone()).collect());
+        assert_eq!(
+            transformed,
+            crate::dfft::dfft::fft2_rearrange_pad(mask, dom.get_omega_inv(), true, 2, &dom, &pp)
+        );
+    }
+
+    #[test]
+    fn generate_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let shape = Shape {
+            requests: vec![Request::DoubleSharings { len: 2 }; 2],
+        };
+        let mut stores = simulate(pp.n, |net| {
+            generate(&shape, net, &pp, &mut ark_std::test_rng()).unwrap()
+        });
+
+        for _ in 0..2 {
+            let sharings: Vec<_> = stores
+                .iter_mut()
+                .map(|s| s.double_sharings(2, &pp).unwrap())
+                .collect();
+            let high = transpose(sharings.iter().map(|s| s.high.clone()).collect());
+            let low = transpose(sharings.iter().map(|s| s.low.clone()).collect());
+            for (high, low) in high.iter().zip(low.iter()) {
+                assert_eq!(pp.unpack2(high), pp.unpack(low));
+            }
+        }
+    }
+
+    #[test]
+    fn combine_dpp_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let m = 2 * L;
+        let shape = Shape {
+            requests: vec![Request::Dpp { m }],
+        };
+        let mut stores = combine_all(&shape, &pp);
+        assert!(stores.iter().all(|s| s.len() == 1));
+
+        let masks: Vec<DppMasks<F>> = stores
+            .iter_mut()
+            .map(|s| s.dpp_masks(m, &pp).unwrap())
+            .collect();
+        let open = |shares: Vec<Vec<F>>| -> Vec<F> {
+            let shares = transpose(shares);
+            assert!(shares.iter().all(|x| pp.is_consistent(x, false)));
+            shares.iter().flat_map(|x| pp.unpack(x)).collect()
+        };
+        let num_mask = open(masks.iter().map(|m| m.num_mask.clone()).collect());
+        let den_mask = open(masks.iter().map(|m| m.den_mask.clone()).collect());
+        let unmask = open(masks.iter().map(|m| m.unmask.clone()).collect());
+
+        // The products of the contributions cancel just like dealt masks
+        let mut prefix = F::ONE;
+        for i in 0..m {
+            prefix *= num_mask[i] * den_mask[i].invert().unwrap();
+            assert_eq!(prefix * unmask[i], F::ONE);
+        }
+
+        let high = transpose(masks.iter().map(|m| m.reduce.high.clone()).collect());
+        let low = transpose(masks.iter().map(|m| m.reduce.low.clone()).collect());
+        for (high, low) in high.iter().zip(low.iter()) {
+            assert_eq!(pp.unpack2(high), pp.unpack(low));
+        }
+
+        let zero = transpose(masks.iter().map(|m| m.zero.clone()).collect());
+        assert!(zero.iter().all(|x| pp.unpack2(x).iter().all(|x| *x == F::ZERO)));
+    }
+}
@@ -0,0 +1,237 @@
+// Correlated randomness consumed by the online protocols.
+// Every protocol takes its masks explicitly, a Preprocessing hands them out in the order they are needed.
+// The offline phase records the shape of a run, generates a Store per party either with a trusted
+// dealer or with the parties themselves (see him), and the online phase consumes the stores.
+
+use ff::{PrimeField, WithSmallOrderMulGroup};
+use halo2_proofs::poly::EvaluationDomain;
+use mpc_net::{MpcNetwork, NetError};
+use rand::rngs::OsRng;
+use secret_sharing::pss::PackedSharingParams;
+use serde::{Deserialize, Serialize};
+
+use crate::channel::channel::MpcSerNet;
+
+use self::{deg_red::DoubleSharings, dfft::FftMasks, dpp::DppMasks, route::RouteMasks};
+
+pub mod constant;
+pub mod dealer;
+pub mod deg_red;
+pub mod dfft;
+pub mod dpp;
+pub mod him;
+pub mod powers;
+pub mod route;
+pub mod shape;
+pub mod store;
+pub mod sum_zero;
+pub mod zero;
+
+/// Source of correlated randomness for one party.
+/// Sources that talk to the other parties, like KingDealer, fail on the network with the peer
+/// at fault, a Store fails locally with NetError::Preprocessing, which names no peer, when it
+/// was generated for another shape.
+pub trait Preprocessing<F: PrimeField> {
+    /// Masks for a call to d_pp over m values
+    fn dpp_masks(
+        &mut self,
+        m: usize,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<DppMasks<F>, NetError>;
+
+    /// Double sharings for a call to deg_red over len packed shares
+    fn double_sharings(
+        &mut self,
+        len: usize,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<DoubleSharings<F>, NetError>;
+
+    /// Masks for a call to d_fft, or d_ifft if inverse is set, with the given rearrange, pad and
+    /// degree2
+    fn fft_masks(
+        &mut self,
+        inverse: bool,
+        rearrange: bool,
+        pad: usize,
+        degree2: bool,
+        dom: &EvaluationDomain<F>,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<FftMasks<F>, NetError>;
+
+    /// Shares of 1, point, .., point^(len - 1) packed in natural order, the point is public but
+    /// does not need to be known ahead of time
+    fn point_powers(
+        &mut self,
+        point: F,
+        len: usize,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<Vec<F>, NetError>;
+
+    /// Masks for moving len packed values, output i takes value map[i], of degree 2d if degree2
+    fn route_masks(
+        &mut self,
+        len: usize,
+        map: &[usize],
+        degree2: bool,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<RouteMasks<F>, NetError>;
+
+    /// Sharings of zero of degree 2d for len packed shares, added to shares of degree 2d before
+    /// the king opens them
+    fn zero_sharings(&mut self, len: usize, pp: &PackedSharingParams<F>)
+        -> Result<Vec<F>, NetError>;
+
+    /// Shares of len random constants, each one packed into all l slots
+    fn constant_sharings(
+        &mut self,
+        len: usize,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<Vec<F>, NetError>;
+
+    /// Sharings of degree 2d of l random values that sum to zero for len packed shares, added to
+    /// shares of degree 2d of which the king only adds up the slots
+    fn sum_zero_sharings(
+        &mut self,
+        len: usize,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<Vec<F>, NetError>;
+}
+
+/// King acts as the trusted dealer during the online phase.
+/// Only meant for tests and benchmarks, the king must not learn the masks in a deployment.
+#[derive(Clone, Copy, Debug)]
+pub struct KingDealer<'a, N> {
+    net: &'a N,
+}
+
+impl<'a, N: MpcNetwork> KingDealer<'a, N> {
+    pub fn new(net: &'a N) -> Self {
+        KingDealer { net }
+    }
+}
+
+impl<'a, F, N> Preprocessing<F> for KingDealer<'a, N>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    N: MpcNetwork,
+{
+    fn dpp_masks(
+        &mut self,
+        m: usize,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<DppMasks<F>, NetError> {
+        let king_answer = if self.net.am_king() {
+            Some(DppMasks::deal(m, pp, &mut OsRng))
+        } else {
+            None
+        };
+
+        self.net.recv_from_king(king_answer)
+    }
+
+    fn double_sharings(
+        &mut self,
+        len: usize,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<DoubleSharings<F>, NetError> {
+        let king_answer = if self.net.am_king() {
+            Some(DoubleSharings::deal(len, pp, &mut OsRng))
+        } else {
+            None
+        };
+
+        self.net.recv_from_king(king_answer)
+    }
+
+    fn fft_masks(
+        &mut self,
+        inverse: bool,
+        rearrange: bool,
+        pad: usize,
+        degree2: bool,
+        dom: &EvaluationDomain<F>,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<FftMasks<F>, NetError> {
+        let king_answer = if self.net.am_king() {
+            Some(FftMasks::deal(inverse, rearrange, pad, degree2, dom, pp, &mut OsRng))
+        } else {
+            None
+        };
+
+        self.net.recv_from_king(king_answer)
+    }
+
+    fn point_powers(
+        &mut self,
+        point: F,
+        len: usize,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<Vec<F>, NetError> {
+        let king_answer = if self.net.am_king() {
+            Some(powers::deal(len, pp, &mut OsRng))
+        } else {
+            None
+        };
+
+        let zeros = self.net.recv_from_king(king_answer)?;
+        Ok(powers::rerandomize(zeros, point, self.net.party_id(), pp))
+    }
+
+    fn route_masks(
+        &mut self,
+        len: usize,
+        map: &[usize],
+        degree2: bool,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<RouteMasks<F>, NetError> {
+        let king_answer = if self.net.am_king() {
+            Some(RouteMasks::deal(len, map, degree2, pp, &mut OsRng))
+        } else {
+            None
+        };
+
+        self.net.recv_from_king(king_answer)
+    }
+
+    fn zero_sharings(
+        &mut self,
+        len: usize,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<Vec<F>, NetError> {
+        let king_answer = if self.net.am_king() {
+            Some(zero::deal(len, pp, &mut OsRng))
+        } else {
+            None
+        };
+
+        self.net.recv_from_king(king_answer)
+    }
+
+    fn constant_sharings(
+        &mut self,
+        len: usize,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<Vec<F>, NetError> {
+        let king_answer = if self.net.am_king() {
+            Some(constant::deal(len, pp, &mut OsRng))
+        } else {
+            None
+        };
+
+        self.net.recv_from_king(king_answer)
+    }
+
+    fn sum_zero_sharings(
+        &mut self,
+        len: usize,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<Vec<F>, NetError> {
+        let king_answer = if self.net.am_king() {
+            Some(sum_zero::deal(len, pp, &mut OsRng))
+        } else {
+            None
+        };
+
+        self.net.recv_from_king(king_answer)
+    }
+}
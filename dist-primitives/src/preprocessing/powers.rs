@@ -0,0 +1,91 @@
+// Packed shares of the powers of a point, used to evaluate polynomials given packed shares of their
+// coefficients and as the entries of the toeplitz matrix for dividing by (X - point).
+// The point is public but usually only known online, it comes from the transcript. So the
+// preprocessing is a random packing of zeros and the parties add the powers packed from public,
+// which gives a fresh random packing of the powers.
+
+use ff::{PrimeField, WithSmallOrderMulGroup};
+use rand::RngCore;
+use secret_sharing::pss::PackedSharingParams;
+
+use crate::utils::pack::transpose;
+
+fn powers<F: PrimeField>(point: F, len: usize) -> Vec<F> {
+    let mut powers = Vec::with_capacity(len);
+    let mut power = F::ONE;
+    for _ in 0..len {
+        powers.push(power);
+        power *= point;
+    }
+    powers
+}
+
+/// Packs len zeros with fresh randomness and returns the shares of every party
+pub fn deal<F, R>(len: usize, pp: &PackedSharingParams<F>, rng: &mut R) -> Vec<Vec<F>>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3>,
+    R: RngCore,
+{
+    debug_assert_eq!(len % pp.l, 0, "Mismatch of size in point powers");
+    let zeros = vec![F::ZERO; pp.l];
+    transpose((0..len / pp.l).map(|_| pp.pack(&zeros, rng)).collect())
+}
+
+/// Shares of the same powers computed locally by a single party, the point is public anyway
+pub fn pack_public<F>(point: F, len: usize, party_id: usize, pp: &PackedSharingParams<F>) -> Vec<F>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3>,
+{
+    debug_assert_eq!(len % pp.l, 0, "Mismatch of size in point powers");
+    powers(point, len)
+        .chunks(pp.l)
+        .map(|chunk| pp.pack_from_public(&chunk.to_vec())[party_id])
+        .collect()
+}
+
+/// Adds the shares of 1, point, .., point^(len - 1) to a party's packing of len zeros from deal
+pub fn rerandomize<F>(
+    zeros: Vec<F>,
+    point: F,
+    party_id: usize,
+    pp: &PackedSharingParams<F>,
+) -> Vec<F>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3>,
+{
+    let len = zeros.len() * pp.l;
+    zeros
+        .iter()
+        .zip(pack_public(point, len, party_id, pp))
+        .map(|(zero, power)| *zero + power)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ff::Field;
+    use halo2_proofs::halo2curves::bn256::Fr as F;
+
+    const L: usize = 4;
+
+    #[test]
+    fn rerandomize_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let rng = &mut ark_std::test_rng();
+        let point = F::random(&mut *rng);
+
+        let shares: Vec<Vec<F>> = deal(2 * L, &pp, rng)
+            .into_iter()
+            .enumerate()
+            .map(|(party_id, zeros)| rerandomize(zeros, point, party_id, &pp))
+            .collect();
+        let shares = transpose(shares);
+        let powers: Vec<F> = shares.iter().flat_map(|x| pp.unpack(x)).collect();
+        assert_eq!(powers, super::powers(point, 2 * L));
+
+        // Not the deterministic packing from public
+        let public = transpose((0..pp.n).map(|i| pack_public(point, 2 * L, i, &pp)).collect());
+        assert_ne!(shares, public);
+    }
+}
@@ -0,0 +1,94 @@
+// Masks for moving packed values to other positions, see dwit.
+// Parties add shares of r to the values so that the king only opens masked values and moves
+// them to their places. Moving is linear, so the parties subtract shares of the moved r.
+// Values of degree 2d need r of degree 2d, the moved values always have degree d.
+
+use ff::{PrimeField, WithSmallOrderMulGroup};
+use rand::RngCore;
+use secret_sharing::pss::PackedSharingParams;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::pack::transpose;
+
+/// One party's packed shares of the masks for a single routing
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RouteMasks<F> {
+    /// Shares of r over the values being moved
+    pub mask: Vec<F>,
+    /// Shares of r[map[i]] for every output i
+    pub routed: Vec<F>,
+}
+
+impl<F> RouteMasks<F>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3>,
+{
+    /// Samples the masks for moving len values, output i takes value map[i], and returns the
+    /// shares of every party. degree2 must match the values being moved.
+    pub fn deal<R: RngCore>(
+        len: usize,
+        map: &[usize],
+        degree2: bool,
+        pp: &PackedSharingParams<F>,
+        rng: &mut R,
+    ) -> Vec<Self> {
+        debug_assert_eq!(len % pp.l, 0, "Mismatch of size in route masks");
+        debug_assert_eq!(map.len() % pp.l, 0, "Mismatch of size in route masks");
+        let r: Vec<F> = (0..len).map(|_| F::random(&mut *rng)).collect();
+        let routed: Vec<F> = map.iter().map(|&i| r[i]).collect();
+
+        let mut share = |values: Vec<F>, degree2: bool| -> Vec<Vec<F>> {
+            transpose(
+                values
+                    .chunks(pp.l)
+                    .map(|chunk| {
+                        if degree2 {
+                            pp.pack2(&chunk.to_vec(), rng)
+                        } else {
+                            pp.pack(&chunk.to_vec(), rng)
+                        }
+                    })
+                    .collect(),
+            )
+        };
+        let mask = share(r, degree2);
+        let routed = share(routed, false);
+
+        mask.into_iter()
+            .zip(routed)
+            .map(|(mask, routed)| RouteMasks { mask, routed })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use halo2_proofs::halo2curves::bn256::Fr as F;
+
+    const L: usize = 4;
+
+    #[test]
+    fn masks_route_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let rng = &mut ark_std::test_rng();
+        let map: Vec<usize> = (0..2 * L).map(|i| (3 * i + 1) % (3 * L)).collect();
+
+        let open = |shares: Vec<Vec<F>>, degree2: bool| -> Vec<F> {
+            let shares = transpose(shares);
+            assert!(shares.iter().all(|x| pp.is_consistent(x, degree2)));
+            shares
+                .iter()
+                .flat_map(|x| if degree2 { pp.unpack2(x) } else { pp.unpack(x) })
+                .collect()
+        };
+        for degree2 in [false, true] {
+            let masks = RouteMasks::deal(3 * L, &map, degree2, &pp, rng);
+            assert_eq!(masks.len(), pp.n);
+
+            let mask = open(masks.iter().map(|m| m.mask.clone()).collect(), degree2);
+            let routed = open(masks.iter().map(|m| m.routed.clone()).collect(), false);
+            assert_eq!(routed, map.iter().map(|&i| mask[i]).collect::<Vec<_>>());
+        }
+    }
+}
@@ -0,0 +1,179 @@
+// The shape of a protocol run is the list of masks it consumes, in order.
+// It only depends on the circuit and the sizes involved, never on the witness or the challenges
+// of the transcript, so it can be recorded once and reused to generate preprocessing for later
+// runs.
+
+use std::io::{Read, Write};
+
+use ff::{PrimeField, WithSmallOrderMulGroup};
+use halo2_proofs::poly::EvaluationDomain;
+use mpc_net::NetError;
+use secret_sharing::pss::PackedSharingParams;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    deg_red::DoubleSharings, dfft::FftMasks, dpp::DppMasks, route::RouteMasks, Preprocessing,
+};
+
+/// A single call of Preprocessing
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Request {
+    /// Masks for d_pp over m values
+    Dpp { m: usize },
+    /// Double sharings for deg_red over len packed shares
+    DoubleSharings { len: usize },
+    /// Masks for d_fft or d_ifft over the domain of size 2^k
+    Fft {
+        inverse: bool,
+        rearrange: bool,
+        pad: usize,
+        degree2: bool,
+        k: u32,
+    },
+    /// Shares of len powers of a public point, which is only known online
+    PointPowers { len: usize },
+    /// Masks for moving len values, output i takes value map[i]
+    Route {
+        len: usize,
+        map: Vec<usize>,
+        degree2: bool,
+    },
+    /// Sharings of zero of degree 2d for len packed shares
+    Zeros { len: usize },
+    /// Shares of len random constants
+    Constants { len: usize },
+    /// Sharings of degree 2d of values that sum to zero for len packed shares
+    SumZeros { len: usize },
+}
+
+/// All the requests of a protocol run in the order they are made
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Shape {
+    pub requests: Vec<Request>,
+}
+
+impl Shape {
+    pub fn write<W: Write>(&self, writer: W) -> bincode::Result<()> {
+        bincode::serialize_into(writer, self)
+    }
+
+    pub fn read<R: Read>(reader: R) -> bincode::Result<Self> {
+        bincode::deserialize_from(reader)
+    }
+}
+
+/// Records the shape of a run while handing out masks from another Preprocessing
+pub struct Recorder<P> {
+    inner: P,
+    pub shape: Shape,
+}
+
+impl<P> Recorder<P> {
+    pub fn new(inner: P) -> Self {
+        Recorder {
+            inner,
+            shape: Shape {
+                requests: Vec::new(),
+            },
+        }
+    }
+
+    pub fn into_shape(self) -> Shape {
+        self.shape
+    }
+}
+
+impl<P, F> Preprocessing<F> for Recorder<P>
+where
+    P: Preprocessing<F>,
+    F: PrimeField + WithSmallOrderMulGroup<3>,
+{
+    fn dpp_masks(
+        &mut self,
+        m: usize,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<DppMasks<F>, NetError> {
+        self.shape.requests.push(Request::Dpp { m });
+        self.inner.dpp_masks(m, pp)
+    }
+
+    fn double_sharings(
+        &mut self,
+        len: usize,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<DoubleSharings<F>, NetError> {
+        self.shape.requests.push(Request::DoubleSharings { len });
+        self.inner.double_sharings(len, pp)
+    }
+
+    fn fft_masks(
+        &mut self,
+        inverse: bool,
+        rearrange: bool,
+        pad: usize,
+        degree2: bool,
+        dom: &EvaluationDomain<F>,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<FftMasks<F>, NetError> {
+        self.shape.requests.push(Request::Fft {
+            inverse,
+            rearrange,
+            pad,
+            degree2,
+            k: dom.k(),
+        });
+        self.inner.fft_masks(inverse, rearrange, pad, degree2, dom, pp)
+    }
+
+    fn point_powers(
+        &mut self,
+        point: F,
+        len: usize,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<Vec<F>, NetError> {
+        self.shape.requests.push(Request::PointPowers { len });
+        self.inner.point_powers(point, len, pp)
+    }
+
+    fn route_masks(
+        &mut self,
+        len: usize,
+        map: &[usize],
+        degree2: bool,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<RouteMasks<F>, NetError> {
+        self.shape.requests.push(Request::Route {
+            len,
+            map: map.to_vec(),
+            degree2,
+        });
+        self.inner.route_masks(len, map, degree2, pp)
+    }
+
+    fn zero_sharings(
+        &mut self,
+        len: usize,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<Vec<F>, NetError> {
+        self.shape.requests.push(Request::Zeros { len });
+        self.inner.zero_sharings(len, pp)
+    }
+
+    fn constant_sharings(
+        &mut self,
+        len: usize,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<Vec<F>, NetError> {
+        self.shape.requests.push(Request::Constants { len });
+        self.inner.constant_sharings(len, pp)
+    }
+
+    fn sum_zero_sharings(
+        &mut self,
+        len: usize,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<Vec<F>, NetError> {
+        self.shape.requests.push(Request::SumZeros { len });
+        self.inner.sum_zero_sharings(len, pp)
+    }
+}
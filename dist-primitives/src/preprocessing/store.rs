@@ -0,0 +1,204 @@
+// Preprocessing generated ahead of time and kept by a party until the online phase consumes it.
+
+use std::{
+    collections::VecDeque,
+    io::{Read, Write},
+};
+
+use ff::{PrimeField, WithSmallOrderMulGroup};
+use halo2_proofs::poly::EvaluationDomain;
+use mpc_net::NetError;
+use secret_sharing::pss::PackedSharingParams;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    deg_red::DoubleSharings, dfft::FftMasks, dpp::DppMasks, powers, route::RouteMasks,
+    shape::Request, Preprocessing,
+};
+
+/// One party's shares answering a single request
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Item<F> {
+    Dpp(DppMasks<F>),
+    DoubleSharings(DoubleSharings<F>),
+    Fft(FftMasks<F>),
+    PointPowers(Vec<F>),
+    Route(RouteMasks<F>),
+    Zeros(Vec<F>),
+    Constants(Vec<F>),
+    SumZeros(Vec<F>),
+}
+
+/// The preprocessing of a single party for the requests of a shape, consumed in order
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Store<F> {
+    pub party_id: usize,
+    items: VecDeque<(Request, Item<F>)>,
+}
+
+impl<F> Store<F> {
+    pub fn new(party_id: usize) -> Self {
+        Store {
+            party_id,
+            items: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, request: Request, item: Item<F>) {
+        self.items.push_back((request, item));
+    }
+
+    /// Number of requests left
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+impl<F: Serialize + for<'de> Deserialize<'de>> Store<F> {
+    pub fn write<W: Write>(&self, writer: W) -> bincode::Result<()> {
+        bincode::serialize_into(writer, self)
+    }
+
+    pub fn read<R: Read>(reader: R) -> bincode::Result<Self> {
+        bincode::deserialize_from(reader)
+    }
+}
+
+impl<F: PrimeField> Store<F> {
+    /// Takes the next item, which must have been generated for exactly this request
+    fn next(&mut self, request: Request) -> Result<Item<F>, NetError> {
+        match self.items.pop_front() {
+            Some((expected, item)) if expected == request => Ok(item),
+            Some((expected, _)) => Err(self.error(format!(
+                "generated for {:?} but the protocol asked for {:?}",
+                expected, request
+            ))),
+            None => Err(self.error(format!("ran out at {:?}", request))),
+        }
+    }
+
+    fn error(&self, reason: String) -> NetError {
+        NetError::Preprocessing { reason }
+    }
+
+    /// The item answering a request is of another kind, the store was not dealt by dealer or him
+    fn wrong_item(&self) -> NetError {
+        self.error("an item does not match its request".to_string())
+    }
+}
+
+impl<F> Preprocessing<F> for Store<F>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3>,
+{
+    fn dpp_masks(
+        &mut self,
+        m: usize,
+        _pp: &PackedSharingParams<F>,
+    ) -> Result<DppMasks<F>, NetError> {
+        match self.next(Request::Dpp { m })? {
+            Item::Dpp(masks) => Ok(masks),
+            _ => Err(self.wrong_item()),
+        }
+    }
+
+    fn double_sharings(
+        &mut self,
+        len: usize,
+        _pp: &PackedSharingParams<F>,
+    ) -> Result<DoubleSharings<F>, NetError> {
+        match self.next(Request::DoubleSharings { len })? {
+            Item::DoubleSharings(sharings) => Ok(sharings),
+            _ => Err(self.wrong_item()),
+        }
+    }
+
+    fn fft_masks(
+        &mut self,
+        inverse: bool,
+        rearrange: bool,
+        pad: usize,
+        degree2: bool,
+        dom: &EvaluationDomain<F>,
+        _pp: &PackedSharingParams<F>,
+    ) -> Result<FftMasks<F>, NetError> {
+        let request = Request::Fft {
+            inverse,
+            rearrange,
+            pad,
+            degree2,
+            k: dom.k(),
+        };
+        match self.next(request)? {
+            Item::Fft(masks) => Ok(masks),
+            _ => Err(self.wrong_item()),
+        }
+    }
+
+    fn point_powers(
+        &mut self,
+        point: F,
+        len: usize,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<Vec<F>, NetError> {
+        match self.next(Request::PointPowers { len })? {
+            Item::PointPowers(zeros) => Ok(powers::rerandomize(zeros, point, self.party_id, pp)),
+            _ => Err(self.wrong_item()),
+        }
+    }
+
+    fn route_masks(
+        &mut self,
+        len: usize,
+        map: &[usize],
+        degree2: bool,
+        _pp: &PackedSharingParams<F>,
+    ) -> Result<RouteMasks<F>, NetError> {
+        let request = Request::Route {
+            len,
+            map: map.to_vec(),
+            degree2,
+        };
+        match self.next(request)? {
+            Item::Route(masks) => Ok(masks),
+            _ => Err(self.wrong_item()),
+        }
+    }
+
+    fn zero_sharings(
+        &mut self,
+        len: usize,
+        _pp: &PackedSharingParams<F>,
+    ) -> Result<Vec<F>, NetError> {
+        match self.next(Request::Zeros { len })? {
+            Item::Zeros(zeros) => Ok(zeros),
+            _ => Err(self.wrong_item()),
+        }
+    }
+
+    fn constant_sharings(
+        &mut self,
+        len: usize,
+        _pp: &PackedSharingParams<F>,
+    ) -> Result<Vec<F>, NetError> {
+        match self.next(Request::Constants { len })? {
+            Item::Constants(constants) => Ok(constants),
+            _ => Err(self.wrong_item()),
+        }
+    }
+
+    fn sum_zero_sharings(
+        &mut self,
+        len: usize,
+        _pp: &PackedSharingParams<F>,
+    ) -> Result<Vec<F>, NetError> {
+        match self.next(Request::SumZeros { len })? {
+            Item::SumZeros(zeros) => Ok(zeros),
+            _ => Err(self.wrong_item()),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+// Sharings of degree 2d of random values that sum to zero.
+// Some protocols only need the sum of the slots of a share of degree 2d, like the partial sums
+// of an evaluation or an msm. Opening the share would give the king every slot, parties add a
+// sharing whose slots are random but sum to zero, so the king learns the sum and nothing else.
+
+use ff::{PrimeField, WithSmallOrderMulGroup};
+use rand::RngCore;
+use secret_sharing::pss::PackedSharingParams;
+
+use crate::utils::pack::transpose;
+
+/// Packs l random values that sum to zero with degree 2d len times and returns the shares of
+/// every party
+pub fn deal<F, R>(len: usize, pp: &PackedSharingParams<F>, rng: &mut R) -> Vec<Vec<F>>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3>,
+    R: RngCore,
+{
+    transpose(
+        (0..len)
+            .map(|_| {
+                let mut values: Vec<F> = (1..pp.l).map(|_| F::random(&mut *rng)).collect();
+                values.push(-values.iter().copied().sum::<F>());
+                pp.pack2(&values, rng)
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ff::Field;
+    use halo2_proofs::halo2curves::bn256::Fr as F;
+
+    const L: usize = 4;
+    const LEN: usize = 8;
+
+    #[test]
+    fn sum_zero_sharings_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let rng = &mut ark_std::test_rng();
+
+        let shares = deal(LEN, &pp, rng);
+        assert_eq!(shares.len(), pp.n);
+        for x in transpose(shares).iter() {
+            let values = pp.unpack2(x);
+            assert_eq!(values.iter().copied().sum::<F>(), F::ZERO);
+            assert!(values.iter().all(|x| *x != F::ZERO));
+        }
+    }
+}
@@ -0,0 +1,44 @@
+// Sharings of zero of degree 2d.
+// Shares of degree 2d, like products of shares or shares times public values, are not uniformly
+// random apart from the secrets. Parties add a fresh sharing of zero before the king opens them,
+// so the king learns the secrets and nothing else.
+
+use ff::{PrimeField, WithSmallOrderMulGroup};
+use rand::RngCore;
+use secret_sharing::pss::PackedSharingParams;
+
+use crate::utils::pack::transpose;
+
+/// Packs zeros with degree 2d len times and returns the shares of every party
+pub fn deal<F, R>(len: usize, pp: &PackedSharingParams<F>, rng: &mut R) -> Vec<Vec<F>>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3>,
+    R: RngCore,
+{
+    let zeros = vec![F::ZERO; pp.l];
+    transpose((0..len).map(|_| pp.pack2(&zeros, rng)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ff::Field;
+    use halo2_proofs::halo2curves::bn256::Fr as F;
+
+    const L: usize = 4;
+    const LEN: usize = 8;
+
+    #[test]
+    fn zero_sharings_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let rng = &mut ark_std::test_rng();
+
+        let shares = deal(LEN, &pp, rng);
+        assert_eq!(shares.len(), pp.n);
+        for x in transpose(shares).iter() {
+            assert!(pp.unpack2(x).iter().all(|x| *x == F::ZERO));
+            // Full degree 2d, not the sharing of degree d of zero
+            assert!(!pp.is_consistent(x, false));
+        }
+    }
+}
@@ -1,35 +1,92 @@
 use ark_std::{end_timer, start_timer};
 use ff::{PrimeField, WithSmallOrderMulGroup};
-use mpc_net::MpcMultiNet as Net;
+use mpc_net::{label, MpcNetwork, NetError};
 use secret_sharing::pss::PackedSharingParams;
 use serde::{Deserialize, Serialize};
 
-use crate::channel::channel::MpcSerNet;
+use crate::{channel::channel::MpcSerNet, preprocessing::deg_red::DoubleSharings};
 
-use super::pack::transpose;
-
-/// Reduces the degree of a poylnomial with the help of king
-pub fn deg_red<F>(px: Vec<F>, pp: &PackedSharingParams<F>) -> Vec<F>
+/// Reduces the degree of a poylnomial with the help of the kings of net
+/// masks: preprocessed double sharings, the kings only see the shares masked with them
+pub fn deg_red<F, N: MpcNetwork>(
+    px: Vec<F>,
+    masks: DoubleSharings<F>,
+    net: &N,
+    pp: &PackedSharingParams<F>,
+) -> Result<Vec<F>, NetError>
 where
     F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
 {
-    let communication_timer = start_timer!(|| "ComToKing");
-    let received_shares = Net::send_to_king(&px);
-    end_timer!(communication_timer);
-    let king_answer: Option<Vec<Vec<F>>> = received_shares.map(|px_shares: Vec<Vec<F>>| {
-        let repack_shares_timer = start_timer!(|| "Unpack Pack shares");
-        let mut px_shares = transpose(px_shares);
-        for i in 0..px_shares.len() {
-            pp.unpack2_in_place(&mut px_shares[i]);
-            pp.pack_from_public_in_place(&mut px_shares[i]);
-        }
-        end_timer!(repack_shares_timer);
-        transpose(px_shares)
-    });
-
-    let communication_timer = start_timer!(|| "ComFromKing");
-    let got_from_king = Net::recv_from_king(king_answer);
-    end_timer!(communication_timer);
-
-    got_from_king
+    debug_assert_eq!(masks.high.len(), px.len(), "Mismatch of size in deg_red");
+    let _label = label("deg_red");
+    let px: Vec<F> = px.iter().zip(masks.high.iter()).map(|(x, r)| *x + r).collect();
+
+    // Every column is reduced on its own, so they can be split across the kings
+    let king_timer = start_timer!(|| "Unpack Pack shares with kings");
+    let got_from_king: Vec<F> = net.king_columns(px, |mut column: Vec<F>| {
+        pp.unpack2_in_place(&mut column);
+        pp.pack_from_public_in_place(&mut column);
+        column
+    })?;
+    end_timer!(king_timer);
+
+    Ok(got_from_king
+        .iter()
+        .zip(masks.low.iter())
+        .map(|(x, r)| *x - r)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preprocessing::{KingDealer, Preprocessing};
+    use crate::utils::pack::{pack_vec, transpose};
+    use ff::Field;
+    use halo2_proofs::halo2curves::bn256::Fr as F;
+    use mpc_net::{local::simulate, Kings};
+
+    const L: usize = 2;
+    const M: usize = 16;
+
+    fn check_deg_red(kings: Kings) {
+        let pp = PackedSharingParams::<F>::new(L);
+        let rng = &mut ark_std::test_rng();
+        let a: Vec<F> = (0..M).map(|_| F::random(&mut *rng)).collect();
+        let b: Vec<F> = (0..M).map(|_| F::random(&mut *rng)).collect();
+        let a_shares = transpose(pack_vec(&a, &pp));
+        let b_shares = transpose(pack_vec(&b, &pp));
+
+        let shares = simulate(pp.n, |net| {
+            let net = &net.with_kings(kings);
+            let id = net.party_id();
+            let product: Vec<F> = a_shares[id]
+                .iter()
+                .zip(b_shares[id].iter())
+                .map(|(a, b)| *a * b)
+                .collect();
+            let masks = KingDealer::new(net)
+                .double_sharings(product.len(), &pp)
+                .unwrap();
+            deg_red(product, masks, net, &pp).unwrap()
+        });
+
+        let shares = transpose(shares);
+        assert!(shares.iter().all(|x| pp.is_consistent(x, false)));
+        let products: Vec<F> = shares.iter().flat_map(|x| pp.unpack(x)).collect();
+        let expected: Vec<F> = a.iter().zip(b.iter()).map(|(a, b)| *a * b).collect();
+        assert_eq!(products, expected);
+    }
+
+    #[test]
+    fn deg_red_test() {
+        check_deg_red(Kings::One(0));
+        check_deg_red(Kings::One(1));
+    }
+
+    #[test]
+    fn deg_red_spread_test() {
+        // Three kings for the M / L columns
+        check_deg_red(Kings::Spread(3));
+    }
 }
@@ -5,6 +5,9 @@ use std::fmt::Debug;
 
 pub trait EvaluationDomainExt<F: PrimeField + WithSmallOrderMulGroup<3>> {
     fn size(&self) -> usize;
+
+    /// The plain domain over the extended domain, whose omega is the extended omega
+    fn extended_domain(&self) -> EvaluationDomain<F>;
 }
 
 impl<F: PrimeField + WithSmallOrderMulGroup<3>> EvaluationDomainExt<F> for EvaluationDomain<F> {
@@ -14,6 +17,10 @@ impl<F: PrimeField + WithSmallOrderMulGroup<3>> EvaluationDomainExt<F> for Evalu
     {
         1 << self.k() as usize
     }
+
+    fn extended_domain(&self) -> EvaluationDomain<F> {
+        EvaluationDomain::new(1, self.extended_k())
+    }
 }
 
 // Helper function for FFT on projective group elements
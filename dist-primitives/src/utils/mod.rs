@@ -1,5 +1,6 @@
 pub mod deg_red;
 pub mod pack;
+pub mod reshare;
 pub mod domain_utils;
 pub mod g1_serialization;
 pub mod bn256;
@@ -0,0 +1,155 @@
+use ark_std::{end_timer, start_timer};
+use ff::{PrimeField, WithSmallOrderMulGroup};
+use mpc_net::{MpcNetwork, NetError};
+use secret_sharing::pss::PackedSharingParams;
+use serde::{Deserialize, Serialize};
+
+use crate::{channel::channel::MpcSerNet, preprocessing::Preprocessing};
+
+use super::pack::{pack_vec, transpose};
+
+/// Applies a public map to a packed vector with the help of king.
+/// King opens the secrets, applies f and sends back fresh packed shares of the result.
+/// f is an arbitrary map over all the secrets, so with several kings the leading king does all
+/// of it, spreading the opening would only add rounds to and from the leader.
+/// The output length of f must be a multiple of pp.l
+pub fn d_reshare<F, N, G>(
+    px: Vec<F>,
+    degree2: bool,
+    net: &N,
+    pp: &PackedSharingParams<F>,
+    f: G,
+) -> Result<Vec<F>, NetError>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    N: MpcNetwork,
+    G: FnOnce(Vec<F>) -> Vec<F>,
+{
+    let unpack = |x: &Vec<F>| {
+        if degree2 {
+            pp.unpack2(x)
+        } else {
+            pp.unpack(x)
+        }
+    };
+    let communication_timer = start_timer!(|| "ComToKing");
+    let received_shares = net.send_to_king(&px)?;
+    end_timer!(communication_timer);
+
+    let king_answer: Option<Vec<Vec<F>>> = received_shares.map(|px_shares: Vec<Vec<F>>| {
+        let reshare_timer = start_timer!(|| "Reshare");
+        let px_shares = transpose(px_shares);
+        let secrets: Vec<F> = px_shares.iter().flat_map(unpack).collect();
+
+        let out = f(secrets);
+        let out_shares = transpose(pack_vec(&out, pp));
+        end_timer!(reshare_timer);
+        out_shares
+    });
+
+    let communication_timer = start_timer!(|| "ComFromKing");
+    let got_from_king = net.recv_from_king(king_answer)?;
+    end_timer!(communication_timer);
+
+    Ok(got_from_king)
+}
+
+/// Moves packed values to other positions without the king learning them, output i takes value
+/// map[i], or zero for None. Parties mask the values with preprocessed shares of r, the king
+/// opens and moves the masked values and the parties subtract their shares of the moved r.
+/// px has degree 2d if degree2, the output has degree d. The length of map must be a multiple
+/// of pp.l
+pub fn d_route<F, P, N>(
+    mut px: Vec<F>,
+    map: &[Option<usize>],
+    degree2: bool,
+    preprocessing: &mut P,
+    net: &N,
+    pp: &PackedSharingParams<F>,
+) -> Result<Vec<F>, NetError>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    P: Preprocessing<F>,
+    N: MpcNetwork,
+{
+    // Zeros are read from an extra share of zeros, masked and moved like any other value
+    let zeros = px.len() * pp.l;
+    if map.iter().any(|i| i.is_none()) {
+        px.push(F::ZERO);
+    }
+    let map: Vec<usize> = map.iter().map(|i| i.unwrap_or(zeros)).collect();
+
+    let masks = preprocessing.route_masks(px.len() * pp.l, &map, degree2, pp)?;
+    let masked: Vec<F> = px.iter().zip(masks.mask.iter()).map(|(x, r)| *x + r).collect();
+    let routed = d_reshare(masked, degree2, net, pp, |values| {
+        map.iter().map(|&i| values[i]).collect()
+    })?;
+    Ok(routed
+        .iter()
+        .zip(masks.routed.iter())
+        .map(|(x, r)| *x - r)
+        .collect())
+}
+
+/// King samples secrets using f and deals packed shares of them
+pub fn d_deal<F, N, G>(
+    net: &N,
+    pp: &PackedSharingParams<F>,
+    f: G,
+) -> Result<Vec<F>, NetError>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    N: MpcNetwork,
+    G: FnOnce() -> Vec<F>,
+{
+    let king_answer: Option<Vec<Vec<F>>> = if net.am_king() {
+        Some(transpose(pack_vec(&f(), pp)))
+    } else {
+        None
+    };
+
+    let communication_timer = start_timer!(|| "ComFromKing");
+    let got_from_king = net.recv_from_king(king_answer)?;
+    end_timer!(communication_timer);
+
+    Ok(got_from_king)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preprocessing::KingDealer;
+    use ff::Field;
+    use halo2_proofs::halo2curves::bn256::Fr as F;
+    use mpc_net::local::simulate;
+
+    const L: usize = 2;
+
+    #[test]
+    fn d_route_test() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let rng = &mut ark_std::test_rng();
+        let values: Vec<F> = (0..4 * L).map(|_| F::random(&mut *rng)).collect();
+        // Shares of degree 2d, reversed and followed by zeros
+        let shares = transpose(
+            values
+                .chunks(L)
+                .map(|chunk| pp.pack2(&chunk.to_vec(), &mut *rng))
+                .collect(),
+        );
+        let map: Vec<Option<usize>> = (0..4 * L).rev().map(Some).chain([None; L]).collect();
+
+        let outputs = simulate(pp.n, |net| {
+            let dealer = &mut KingDealer::new(net);
+            let px = shares[net.party_id()].clone();
+            d_route(px, &map, true, dealer, net, &pp).unwrap()
+        });
+
+        let routed: Vec<F> = transpose(outputs).iter().flat_map(|x| pp.unpack(x)).collect();
+        let expected: Vec<F> = map
+            .iter()
+            .map(|i| i.map_or(F::ZERO, |i| values[i]))
+            .collect();
+        assert_eq!(routed, expected);
+    }
+}
@@ -299,6 +299,53 @@ where
         &self.vk
     }
 
+    /// Returns the evaluations of $l_0(X)$ over the extended domain.
+    pub fn l0(&self) -> &Polynomial<C::Scalar, ExtendedLagrangeCoeff> {
+        &self.l0
+    }
+
+    /// Returns the evaluations of $l_{last}(X)$ over the extended domain.
+    pub fn l_last(&self) -> &Polynomial<C::Scalar, ExtendedLagrangeCoeff> {
+        &self.l_last
+    }
+
+    /// Returns the evaluations of $1 - (l_{last}(X) + l_{blind}(X))$ over the
+    /// extended domain.
+    pub fn l_active_row(&self) -> &Polynomial<C::Scalar, ExtendedLagrangeCoeff> {
+        &self.l_active_row
+    }
+
+    /// Returns the fixed columns in Lagrange form.
+    pub fn fixed_values(&self) -> &[Polynomial<C::Scalar, LagrangeCoeff>] {
+        &self.fixed_values
+    }
+
+    /// Returns the fixed columns in coefficient form.
+    pub fn fixed_polys(&self) -> &[Polynomial<C::Scalar, Coeff>] {
+        &self.fixed_polys
+    }
+
+    /// Returns the fixed columns evaluated over the extended domain.
+    pub fn fixed_cosets(&self) -> &[Polynomial<C::Scalar, ExtendedLagrangeCoeff>] {
+        &self.fixed_cosets
+    }
+
+    /// Returns the permutation polynomials $\sigma_i(X)$ in Lagrange form.
+    pub fn permutation_values(&self) -> &[Polynomial<C::Scalar, LagrangeCoeff>] {
+        self.permutation.permutations()
+    }
+
+    /// Returns the permutation polynomials $\sigma_i(X)$ in coefficient form.
+    pub fn permutation_polys(&self) -> &[Polynomial<C::Scalar, Coeff>] {
+        self.permutation.polys()
+    }
+
+    /// Returns the permutation polynomials $\sigma_i(X)$ evaluated over the
+    /// extended domain.
+    pub fn permutation_cosets(&self) -> &[Polynomial<C::Scalar, ExtendedLagrangeCoeff>] {
+        &self.permutation.cosets
+    }
+
     /// Gets the total number of bytes in the serialization of `self`
     fn bytes_length(&self) -> usize {
         let scalar_len = C::Scalar::default().to_repr().as_ref().len();
@@ -166,4 +166,14 @@ impl<C: CurveAffine> ProvingKey<C> {
             + polynomial_slice_byte_length(&self.polys)
             + polynomial_slice_byte_length(&self.cosets)
     }
+
+    /// Returns the permutation polynomials in Lagrange form.
+    pub(crate) fn permutations(&self) -> &[Polynomial<C::Scalar, LagrangeCoeff>] {
+        &self.permutations
+    }
+
+    /// Returns the permutation polynomials in coefficient form.
+    pub(crate) fn polys(&self) -> &[Polynomial<C::Scalar, Coeff>] {
+        &self.polys
+    }
 }
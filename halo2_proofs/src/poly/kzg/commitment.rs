@@ -163,6 +163,11 @@ where
         self.s_g2
     }
 
+    /// Returns the powers of the secret on G1 in monomial form
+    pub fn g(&self) -> &[E::G1Affine] {
+        &self.g
+    }
+
     /// Writes parameters to buffer
     pub fn write_custom<W: io::Write>(&self, writer: &mut W, format: SerdeFormat) -> io::Result<()>
     where
@@ -0,0 +1,19 @@
+use mpc_net::noise::Keypair;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "keygen", about = "Generates the static key of a party.")]
+struct Opt {
+    /// Where to write the private key
+    #[structopt(parse(from_os_str))]
+    private_key: PathBuf,
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    let keypair = Keypair::generate();
+    std::fs::write(&opt.private_key, hex::encode(&keypair.private)).expect("private key path");
+    // Goes after the address of the party in the host configuration
+    println!("{}", hex::encode(&keypair.public));
+}
@@ -0,0 +1,787 @@
+// A network over TCP driven by tokio. Every connection has a task writing the frames queued for
+// the peer and a task reading the frames of the peer into an inbox, so no thread of the rayon
+// pool that runs the FFTs and MSMs waits on a socket. Operations queue their frames as soon as
+// they are called, which fixes their round, and return futures for what they receive. Parties
+// can therefore start the next operation, or keep computing, before the previous one finished.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::future::Future;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use ark_std::{end_timer, start_timer};
+use log::{debug, warn};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout, timeout_at};
+
+use super::config::HostConfig;
+use super::frame::{self, Header, Kind, HEADER_LEN};
+use super::stats::current_label;
+use super::{Kings, MpcNetwork, NetError, Stats};
+
+/// How long to wait for the other parties to show up
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long a new connection has to say which party it is
+const HELLO_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Frames of a peer are filed by whether they are direct messages and by their round
+type Slot = (bool, u64);
+
+enum Entry {
+    /// An operation waits for the frame
+    Waiting(oneshot::Sender<Vec<u8>>),
+    /// The frame came before anyone asked for it
+    Arrived(Vec<u8>),
+}
+
+/// Frames received from a peer, until an operation takes them
+#[derive(Default)]
+struct Inbox {
+    entries: HashMap<Slot, Entry>,
+    /// Why the connection stopped, frames that did not arrive yet never will
+    failure: Option<(io::ErrorKind, String)>,
+}
+
+struct Peer {
+    outbox: mpsc::UnboundedSender<Vec<u8>>,
+    inbox: Arc<Mutex<Inbox>>,
+    /// Number of direct messages sent to and received from the peer
+    sent: AtomicU64,
+    received: AtomicU64,
+}
+
+impl Peer {
+    /// Returns a receiver for the frame of slot, whether it arrived already or not
+    fn take(&self, slot: Slot) -> oneshot::Receiver<Vec<u8>> {
+        let (tx, rx) = oneshot::channel();
+        let mut inbox = self.inbox.lock().expect("Poisoned Inbox");
+        match inbox.entries.remove(&slot) {
+            Some(Entry::Arrived(frame)) => {
+                let _ = tx.send(frame);
+            }
+            Some(Entry::Waiting(_)) => panic!("slot {:?} is taken twice", slot),
+            // tx is dropped, so rx fails right away
+            None if inbox.failure.is_some() => {}
+            None => {
+                inbox.entries.insert(slot, Entry::Waiting(tx));
+            }
+        }
+        rx
+    }
+
+    /// Why the frames of the peer stopped coming
+    fn failure(&self, id: usize) -> NetError {
+        let inbox = self.inbox.lock().expect("Poisoned Inbox");
+        match &inbox.failure {
+            Some((kind, reason)) => NetError::io(id, io::Error::new(*kind, reason.clone())),
+            None => NetError::Disconnected { peer: id },
+        }
+    }
+}
+
+/// Reads the frames of peer into inbox until the connection fails
+async fn read_frames(mut stream: OwnedReadHalf, inbox: Arc<Mutex<Inbox>>, peer: usize) {
+    let failure = loop {
+        let mut header = [0u8; HEADER_LEN];
+        if let Err(e) = stream.read_exact(&mut header).await {
+            break (e.kind(), e.to_string());
+        }
+        let len = match Header::from_bytes(&header, peer) {
+            Ok(h) => h.len,
+            Err(e) => break (io::ErrorKind::InvalidData, e.to_string()),
+        };
+        let round = u64::from_le_bytes(header[8..16].try_into().unwrap());
+        let slot = (header[16] == Kind::Direct as u8, round);
+        // The buffer grows with the bytes that arrive, not with the length in the header
+        let mut frame = header.to_vec();
+        match (&mut stream).take(len).read_to_end(&mut frame).await {
+            Ok(read) if read as u64 == len => {}
+            Ok(read) => {
+                let reason = format!("stream ended after {} of {} bytes", read, len);
+                break (io::ErrorKind::UnexpectedEof, reason);
+            }
+            Err(e) => break (e.kind(), e.to_string()),
+        }
+        let mut inbox = inbox.lock().expect("Poisoned Inbox");
+        match inbox.entries.remove(&slot) {
+            Some(Entry::Waiting(tx)) => {
+                let _ = tx.send(frame);
+            }
+            Some(Entry::Arrived(_)) => {
+                break (io::ErrorKind::InvalidData, format!("second frame for {:?}", slot));
+            }
+            None => {
+                inbox.entries.insert(slot, Entry::Arrived(frame));
+            }
+        }
+    };
+    debug!("Stopped reading from {}: {}", peer, failure.1);
+    let mut inbox = inbox.lock().expect("Poisoned Inbox");
+    // Dropping the senders wakes up the operations waiting on the peer
+    inbox.entries.retain(|_, entry| matches!(entry, Entry::Arrived(_)));
+    inbox.failure = Some(failure);
+}
+
+/// Writes the frames queued for peer, in order
+async fn write_frames(
+    mut stream: OwnedWriteHalf,
+    mut outbox: mpsc::UnboundedReceiver<Vec<u8>>,
+    peer: usize,
+) {
+    while let Some(frame) = outbox.recv().await {
+        if let Err(e) = stream.write_all(&frame).await {
+            warn!("Writing to {} failed: {}", peer, e);
+            return;
+        }
+    }
+}
+
+/// Connects to peer, which has a higher id, and tells it who we are
+async fn dial(id: usize, addr: &str, peer: usize) -> Result<TcpStream, NetError> {
+    debug!("Contacting {}", peer);
+    let deadline = Instant::now() + CONNECT_TIMEOUT;
+    let mut stream = loop {
+        match TcpStream::connect(addr).await {
+            Ok(s) => break s,
+            Err(e) => match e.kind() {
+                io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset => {
+                    if Instant::now() > deadline {
+                        return Err(NetError::Timeout { peer });
+                    }
+                    sleep(Duration::from_millis(10)).await;
+                }
+                _ => return Err(NetError::io(peer, e)),
+            },
+        }
+    };
+    stream
+        .write_all(&(id as u64).to_le_bytes())
+        .await
+        .map_err(|e| NetError::io(peer, e))?;
+    Ok(stream)
+}
+
+/// Checks that peer loaded the same host configuration as us
+async fn check_config(stream: &mut TcpStream, digest: u64, peer: usize) -> Result<(), NetError> {
+    let mut peer_digest = [0u8; 8];
+    stream
+        .write_all(&digest.to_le_bytes())
+        .await
+        .map_err(|e| NetError::io(peer, e))?;
+    stream
+        .read_exact(&mut peer_digest)
+        .await
+        .map_err(|e| NetError::io(peer, e))?;
+    let peer_digest = u64::from_le_bytes(peer_digest);
+    if peer_digest != digest {
+        return Err(NetError::Desync {
+            peer,
+            reason: format!(
+                "host configurations differ, ours hashes to {:x}, theirs to {:x}",
+                digest, peer_digest
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Waits for peer, which has a lower id, to connect.
+/// Parties can connect in any order, the others are kept in pending.
+async fn answer(
+    listener: &TcpListener,
+    pending: &mut HashMap<usize, TcpStream>,
+    peer: usize,
+) -> Result<TcpStream, NetError> {
+    debug!("Awaiting {}", peer);
+    let deadline = Instant::now() + CONNECT_TIMEOUT;
+    while !pending.contains_key(&peer) {
+        let (mut stream, _addr) = timeout_at(deadline.into(), listener.accept())
+            .await
+            .map_err(|_| NetError::Timeout { peer })?
+            .map_err(|e| NetError::io(peer, e))?;
+        let mut id = [0u8; 8];
+        // Connections that don't say which party they are in time are dropped
+        if let Ok(Ok(_)) = timeout(HELLO_TIMEOUT, stream.read_exact(&mut id)).await {
+            pending.insert(u64::from_le_bytes(id) as usize, stream);
+        }
+    }
+    Ok(pending.remove(&peer).unwrap())
+}
+
+/// One session over TCP, driven by the tokio runtime it was created in.
+/// Connections are plain TCP and are closed once it is dropped and the queued frames are written.
+pub struct AsyncMultiNetwork {
+    id: usize,
+    /// None at our own id
+    peers: Vec<Option<Peer>>,
+    /// Picked by king once everyone is connected, zero before
+    session: u64,
+    /// Number of operations started in the session so far
+    round: AtomicU64,
+    stats: Mutex<Stats>,
+    /// Reading the frames of every peer, the writing tasks end once their outbox is dropped
+    readers: Vec<JoinHandle<()>>,
+    runtime: Handle,
+    kings: Kings,
+}
+
+impl AsyncMultiNetwork {
+    /// Connects to all the parties listed in the file, see HostConfig::from_file.
+    /// Has to run inside a tokio runtime, which then drives the connections.
+    pub async fn from_file(path: &str, party_id: usize) -> Result<Self, NetError> {
+        Self::from_config(HostConfig::from_file(path)?, party_id).await
+    }
+
+    /// Connects to all the parties of config over plain TCP.
+    /// Fails with NetError::Config if id is not a party or if the config has public keys, the
+    /// connections are not authenticated so such sessions have to use MultiNetwork, which runs
+    /// Noise.
+    /// Fails with NetError::Desync if a party loaded another configuration.
+    pub async fn from_config(config: HostConfig, id: usize) -> Result<Self, NetError> {
+        if id >= config.n_parties() {
+            return Err(NetError::Config {
+                reason: format!("party {} is not among the {} parties", id, config.n_parties()),
+            });
+        }
+        if config.parties.iter().any(|p| p.public_key.is_some()) {
+            return Err(NetError::Config {
+                reason: "AsyncMultiNetwork has no Noise, use MultiNetwork for keys".to_string(),
+            });
+        }
+        let timer = start_timer!(|| "Connecting");
+        let digest = config.digest();
+        let listener = TcpListener::bind(config.parties[id].listen)
+            .await
+            .map_err(|e| NetError::io(id, e))?;
+        let mut pending = HashMap::new();
+        let mut net = AsyncMultiNetwork {
+            id,
+            peers: Vec::new(),
+            session: 0,
+            round: AtomicU64::new(0),
+            stats: Mutex::new(Stats::default()),
+            readers: Vec::new(),
+            runtime: Handle::current(),
+            kings: Kings::default(),
+        };
+        // Everyone connects in increasing order of peer, the lower id dials
+        for (peer, party) in config.parties.iter().enumerate() {
+            if peer == id {
+                net.peers.push(None);
+                continue;
+            }
+            let mut stream = if id < peer {
+                dial(id, &party.advertise, peer).await?
+            } else {
+                answer(&listener, &mut pending, peer).await?
+            };
+            stream.set_nodelay(true).map_err(|e| NetError::io(peer, e))?;
+            check_config(&mut stream, digest, peer).await?;
+            let (read_half, write_half) = stream.into_split();
+            let inbox = Arc::new(Mutex::new(Inbox::default()));
+            let (outbox, queued) = mpsc::unbounded_channel();
+            net.readers
+                .push(tokio::spawn(read_frames(read_half, inbox.clone(), peer)));
+            tokio::spawn(write_frames(write_half, queued, peer));
+            net.peers.push(Some(Peer {
+                outbox,
+                inbox,
+                sent: AtomicU64::new(0),
+                received: AtomicU64::new(0),
+            }));
+        }
+        // Do a round with the king, to be sure everyone is ready, and agree on the session
+        let from_all = net.gather_bytes(0, &[id as u8]).await?;
+        let session = from_all.map(|from_all| {
+            vec![frame::new_session().to_le_bytes().to_vec(); from_all.len()]
+        });
+        let session = frame::check_len(net.scatter_bytes(0, session).await?, 8, 0)?;
+        net.session = u64::from_le_bytes(session[..].try_into().unwrap());
+        *net.round.get_mut() = 0;
+        end_timer!(timer);
+        Ok(net)
+    }
+
+    #[inline]
+    pub fn party_id(&self) -> usize {
+        self.id
+    }
+
+    #[inline]
+    pub fn n_parties(&self) -> usize {
+        self.peers.len()
+    }
+
+    pub fn reset_stats(&self) {
+        *self.stats.lock().expect("Poisoned Stats") = Stats::default();
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats.lock().expect("Poisoned Stats").clone()
+    }
+
+    fn peer(&self, id: usize) -> &Peer {
+        self.peers[id].as_ref().expect("no connection to ourselves")
+    }
+
+    /// Returns the round of the next operation
+    fn next_round(&self) -> u64 {
+        self.round.fetch_add(1, Ordering::SeqCst)
+    }
+
+    /// Accounts a call, the label is taken when the call is made since it may finish on
+    /// another thread
+    fn record(&self, label: String, kind: Kind, start: Instant, traffic: &[(usize, usize, usize)]) {
+        let mut stats = self.stats.lock().expect("Poisoned Stats");
+        stats.record_as(label, kind, start, traffic);
+    }
+
+    /// Queues a frame for peer id
+    fn send(&self, id: usize, header: &Header, payload: &[u8]) -> Result<(), NetError> {
+        self.peer(id)
+            .outbox
+            .send(frame::encode(header, payload))
+            .map_err(|_| NetError::Disconnected { peer: id })
+    }
+
+    /// Waits for the frame of peer id and checks it is the expected one
+    async fn recv(
+        &self,
+        id: usize,
+        frame_in: oneshot::Receiver<Vec<u8>>,
+        round: u64,
+        kind: Kind,
+    ) -> Result<Vec<u8>, NetError> {
+        let frame_in = frame_in.await.map_err(|_| self.peer(id).failure(id))?;
+        frame::decode(frame_in, self.session, round, kind, id)
+    }
+
+    /// Sends bytes_out[i] to every party i and receives from all of them in one round
+    fn exchange(
+        &self,
+        kind: Kind,
+        bytes_out: Vec<&[u8]>,
+    ) -> impl Future<Output = Result<Vec<Vec<u8>>, NetError>> + '_ {
+        let (start, label) = (Instant::now(), current_label());
+        let round = self.next_round();
+        let own = bytes_out[self.id].to_vec();
+        let sent: Vec<usize> = bytes_out.iter().map(|bytes| HEADER_LEN + bytes.len()).collect();
+        let queued: Result<Vec<_>, NetError> = (0..self.n_parties())
+            .map(|id| {
+                if id == self.id {
+                    return Ok(None);
+                }
+                let header = Header::new(self.session, round, kind, bytes_out[id]);
+                self.send(id, &header, bytes_out[id])?;
+                Ok(Some(self.peer(id).take((false, round))))
+            })
+            .collect();
+        async move {
+            let mut r = Vec::with_capacity(self.n_parties());
+            for (id, frame_in) in queued?.into_iter().enumerate() {
+                r.push(match frame_in {
+                    Some(frame_in) => self.recv(id, frame_in, round, kind).await?,
+                    None => own.clone(),
+                });
+            }
+            let traffic: Vec<_> = (0..r.len())
+                .filter(|id| *id != self.id)
+                .map(|id| (id, sent[id], HEADER_LEN + r[id].len()))
+                .collect();
+            self.record(label, kind, start, &traffic);
+            Ok(r)
+        }
+    }
+
+    /// All parties send bytes to each other, the bytes of every party can have any length
+    pub fn broadcast_bytes(
+        &self,
+        bytes: &[u8],
+    ) -> impl Future<Output = Result<Vec<Vec<u8>>, NetError>> + '_ {
+        self.exchange(Kind::Broadcast, vec![bytes; self.n_parties()])
+    }
+
+    /// All parties send bytes[i] to party i and get what every party sent them
+    pub fn all_to_all_bytes(
+        &self,
+        bytes: Vec<Vec<u8>>,
+    ) -> impl Future<Output = Result<Vec<Vec<u8>>, NetError>> + '_ {
+        assert_eq!(bytes.len(), self.n_parties());
+        self.exchange(Kind::AllToAll, bytes.iter().map(|bytes| &bytes[..]).collect())
+    }
+
+    /// Sends bytes to party only, which has to call recv_bytes_from. Never waits, the bytes are
+    /// queued and written in the background.
+    pub fn send_bytes_to(&self, party: usize, bytes: &[u8]) -> Result<(), NetError> {
+        assert_ne!(party, self.id, "cannot send to ourselves");
+        let start = Instant::now();
+        let round = self.peer(party).sent.fetch_add(1, Ordering::SeqCst);
+        self.send(party, &Header::new(self.session, round, Kind::Direct, bytes), bytes)?;
+        let traffic = [(party, HEADER_LEN + bytes.len(), 0)];
+        self.record(current_label(), Kind::Direct, start, &traffic);
+        Ok(())
+    }
+
+    /// Receives the next bytes party sent to us with send_bytes_to
+    pub fn recv_bytes_from(
+        &self,
+        party: usize,
+    ) -> impl Future<Output = Result<Vec<u8>, NetError>> + '_ {
+        assert_ne!(party, self.id, "cannot receive from ourselves");
+        let (start, label) = (Instant::now(), current_label());
+        let round = self.peer(party).received.fetch_add(1, Ordering::SeqCst);
+        let frame_in = self.peer(party).take((true, round));
+        async move {
+            let bytes_in = self.recv(party, frame_in, round, Kind::Direct).await?;
+            let traffic = [(party, 0, HEADER_LEN + bytes_in.len())];
+            self.record(label, Kind::Direct, start, &traffic);
+            Ok(bytes_in)
+        }
+    }
+
+    fn gather_at(
+        &self,
+        king: usize,
+        round: u64,
+        label: String,
+        bytes: &[u8],
+    ) -> impl Future<Output = Result<Option<Vec<Vec<u8>>>, NetError>> + '_ {
+        let start = Instant::now();
+        let m = bytes.len();
+        let queued = if self.id == king {
+            let frames_in: Vec<_> = (0..self.n_parties())
+                .map(|id| (id != king).then(|| self.peer(id).take((false, round))))
+                .collect();
+            Ok(Some((bytes.to_vec(), frames_in)))
+        } else {
+            let header = Header::new(self.session, round, Kind::ToKing, bytes);
+            let sent = self.send(king, &header, bytes);
+            if sent.is_ok() {
+                self.record(label.clone(), Kind::ToKing, start, &[(king, HEADER_LEN + m, 0)]);
+            }
+            sent.map(|_| None)
+        };
+        async move {
+            let (own, frames_in) = match queued? {
+                Some(queued) => queued,
+                None => return Ok(None),
+            };
+            let mut r = Vec::with_capacity(frames_in.len());
+            for (id, frame_in) in frames_in.into_iter().enumerate() {
+                r.push(match frame_in {
+                    Some(frame_in) => {
+                        let bytes_in = self.recv(id, frame_in, round, Kind::ToKing).await?;
+                        frame::check_len(bytes_in, m, id)?
+                    }
+                    None => own.clone(),
+                });
+            }
+            let others = (0..r.len()).filter(|id| *id != king);
+            let traffic: Vec<_> = others.map(|id| (id, 0, HEADER_LEN + m)).collect();
+            self.record(label, Kind::ToKing, start, &traffic);
+            Ok(Some(r))
+        }
+    }
+
+    fn scatter_at(
+        &self,
+        king: usize,
+        round: u64,
+        label: String,
+        bytes: Option<Vec<Vec<u8>>>,
+    ) -> impl Future<Output = Result<Vec<u8>, NetError>> + '_ {
+        let start = Instant::now();
+        let queued = if self.id == king {
+            let mut bytes = bytes.expect("king has to provide the bytes");
+            let m = bytes[0].len();
+            (0..self.n_parties())
+                .filter(|&id| id != king)
+                .try_for_each(|id| {
+                    assert_eq!(bytes[id].len(), m);
+                    let header = Header::new(self.session, round, Kind::FromKing, &bytes[id]);
+                    self.send(id, &header, &bytes[id])
+                })
+                .map(|_| {
+                    let others = (0..bytes.len()).filter(|id| *id != king);
+                    let traffic: Vec<_> = others.map(|id| (id, HEADER_LEN + m, 0)).collect();
+                    self.record(label.clone(), Kind::FromKing, start, &traffic);
+                    Err(bytes.swap_remove(king))
+                })
+        } else {
+            Ok(Ok(self.peer(king).take((false, round))))
+        };
+        async move {
+            match queued? {
+                Ok(frame_in) => {
+                    let bytes_in = self.recv(king, frame_in, round, Kind::FromKing).await?;
+                    let traffic = [(king, 0, HEADER_LEN + bytes_in.len())];
+                    self.record(label, Kind::FromKing, start, &traffic);
+                    Ok(bytes_in)
+                }
+                Err(own) => Ok(own),
+            }
+        }
+    }
+
+    /// All parties send bytes to king, who gets the bytes of every party
+    pub fn gather_bytes(
+        &self,
+        king: usize,
+        bytes: &[u8],
+    ) -> impl Future<Output = Result<Option<Vec<Vec<u8>>>, NetError>> + '_ {
+        self.gather_at(king, self.next_round(), current_label(), bytes)
+    }
+
+    /// King sends bytes[i] to every party i.
+    /// Provide bytes iff you're the king!
+    pub fn scatter_bytes(
+        &self,
+        king: usize,
+        bytes: Option<Vec<Vec<u8>>>,
+    ) -> impl Future<Output = Result<Vec<u8>, NetError>> + '_ {
+        self.scatter_at(king, self.next_round(), current_label(), bytes)
+    }
+
+    /// Picks the kings of the calls made with this handle, king_compute goes to their leader
+    pub fn set_kings(&mut self, kings: Kings) {
+        self.kings = kings;
+    }
+
+    /// Everyone sends bytes to the leading king, who runs f on the bytes of every party and sends
+    /// back the results. Both rounds are taken when called, so parties can start other
+    /// operations before awaiting it.
+    pub fn king_compute<'a, F>(
+        &'a self,
+        bytes: &[u8],
+        f: F,
+    ) -> impl Future<Output = Result<Vec<u8>, NetError>> + 'a
+    where
+        F: FnOnce(Vec<Vec<u8>>) -> Vec<Vec<u8>> + 'a,
+    {
+        let round = self.round.fetch_add(2, Ordering::SeqCst);
+        let label = current_label();
+        let king = self.kings.leader();
+        let gathered = self.gather_at(king, round, label.clone(), bytes);
+        async move {
+            let king_response = gathered.await?.map(f);
+            self.scatter_at(king, round + 1, label, king_response).await
+        }
+    }
+
+    /// Runs an operation to completion, for the blocking MpcNetwork interface.
+    /// Handle::block_on panics on a thread that drives a runtime, so a worker of a multi thread
+    /// runtime first hands its other tasks over with block_in_place. A current thread runtime
+    /// cannot give its thread away, there the async methods have to be used.
+    fn block_on<T>(&self, f: impl Future<Output = T>) -> T {
+        match Handle::try_current().map(|handle| handle.runtime_flavor()) {
+            Err(_) => self.runtime.block_on(f),
+            Ok(RuntimeFlavor::MultiThread) => {
+                tokio::task::block_in_place(|| self.runtime.block_on(f))
+            }
+            Ok(_) => panic!(
+                "the blocking interface of AsyncMultiNetwork cannot run on a current thread \
+                 runtime, await the async methods instead"
+            ),
+        }
+    }
+}
+
+/// Blocking interface, so the protocols written against MpcNetwork can use the session. Called
+/// from a task of a multi thread runtime it blocks the whole worker, see block_on.
+impl MpcNetwork for AsyncMultiNetwork {
+    #[inline]
+    fn kings(&self) -> Kings {
+        self.kings
+    }
+
+    #[inline]
+    fn party_id(&self) -> usize {
+        self.id
+    }
+
+    #[inline]
+    fn n_parties(&self) -> usize {
+        self.peers.len()
+    }
+
+    fn reset_stats(&self) {
+        AsyncMultiNetwork::reset_stats(self)
+    }
+
+    fn stats(&self) -> Stats {
+        AsyncMultiNetwork::stats(self)
+    }
+
+    fn broadcast_bytes(&self, bytes: &[u8]) -> Result<Vec<Vec<u8>>, NetError> {
+        self.block_on(AsyncMultiNetwork::broadcast_bytes(self, bytes))
+    }
+
+    fn all_to_all_bytes(&self, bytes: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>, NetError> {
+        self.block_on(AsyncMultiNetwork::all_to_all_bytes(self, bytes))
+    }
+
+    fn send_bytes_to(&self, party: usize, bytes: &[u8]) -> Result<(), NetError> {
+        AsyncMultiNetwork::send_bytes_to(self, party, bytes)
+    }
+
+    fn recv_bytes_from(&self, party: usize) -> Result<Vec<u8>, NetError> {
+        self.block_on(AsyncMultiNetwork::recv_bytes_from(self, party))
+    }
+
+    fn gather_bytes(&self, king: usize, bytes: &[u8]) -> Result<Option<Vec<Vec<u8>>>, NetError> {
+        self.block_on(AsyncMultiNetwork::gather_bytes(self, king, bytes))
+    }
+
+    fn scatter_bytes(
+        &self,
+        king: usize,
+        bytes: Option<Vec<Vec<u8>>>,
+    ) -> Result<Vec<u8>, NetError> {
+        self.block_on(AsyncMultiNetwork::scatter_bytes(self, king, bytes))
+    }
+}
+
+impl Drop for AsyncMultiNetwork {
+    fn drop(&mut self) {
+        for reader in &self.readers {
+            reader.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const N: usize = 3;
+
+    fn hosts(port: u16) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("mpc-net-hosts-{}", port));
+        let hosts: String = (0..N)
+            .map(|id| format!("127.0.0.1:{}\n", port + id as u16))
+            .collect();
+        std::fs::write(&path, hosts).unwrap();
+        path
+    }
+
+    #[test]
+    fn out_of_order_test() {
+        let path = hosts(18320);
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let parties: Vec<_> = (0..N)
+            .map(|id| {
+                let path = path.to_str().unwrap().to_owned();
+                runtime.spawn(async move {
+                    let net = AsyncMultiNetwork::from_file(&path, id).await.unwrap();
+                    // Start three operations, then await them last to first
+                    let broadcast = net.broadcast_bytes(&[id as u8]);
+                    let sum = net.king_compute(&[id as u8 + 1], |inputs| {
+                        let sum: u8 = inputs.iter().map(|bytes| bytes[0]).sum();
+                        vec![vec![sum]; N]
+                    });
+                    let outs = (0..N).map(|to| vec![id as u8, to as u8]).collect();
+                    let all_to_all = net.all_to_all_bytes(outs);
+                    let all_to_all = all_to_all.await.unwrap();
+                    let sum = sum.await.unwrap();
+                    (broadcast.await.unwrap(), sum, all_to_all)
+                })
+            })
+            .collect();
+        for (id, party) in parties.into_iter().enumerate() {
+            let (broadcast, sum, all_to_all) = runtime.block_on(party).unwrap();
+            assert_eq!(broadcast, (0..N as u8).map(|from| vec![from]).collect::<Vec<_>>());
+            assert_eq!(sum, vec![(1..=N as u8).sum::<u8>()]);
+            let expected: Vec<_> = (0..N as u8).map(|from| vec![from, id as u8]).collect();
+            assert_eq!(all_to_all, expected);
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn blocking_test() {
+        // Protocols written against MpcNetwork run on other threads while the runtime does I/O
+        let path = hosts(18330);
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let nets: Vec<_> = (0..N)
+            .map(|id| {
+                let path = path.to_str().unwrap().to_owned();
+                runtime.spawn(async move { AsyncMultiNetwork::from_file(&path, id).await })
+            })
+            .collect();
+        let nets: Vec<_> = nets
+            .into_iter()
+            .map(|net| runtime.block_on(net).unwrap().unwrap())
+            .collect();
+        std::thread::scope(|s| {
+            for net in &nets {
+                s.spawn(move || {
+                    let id = net.party_id();
+                    MpcNetwork::reset_stats(net);
+                    if id == 1 {
+                        MpcNetwork::send_bytes_to(net, 2, &[7; 5]).unwrap();
+                    } else if id == 2 {
+                        assert_eq!(MpcNetwork::recv_bytes_from(net, 1).unwrap(), vec![7; 5]);
+                    }
+                    let output = MpcNetwork::king_compute(net, &[id as u8], |inputs| {
+                        inputs.into_iter().rev().collect()
+                    });
+                    assert_eq!(output.unwrap(), vec![(N - 1 - id) as u8]);
+                    let stats = MpcNetwork::stats(net);
+                    assert_eq!((stats.to_king, stats.from_king), (1, 1));
+                });
+            }
+        });
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn runtime_task_test() {
+        // The blocking interface also works from the tasks of a multi thread runtime
+        let path = hosts(18340);
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let parties: Vec<_> = (0..N)
+            .map(|id| {
+                let path = path.to_str().unwrap().to_owned();
+                runtime.spawn(async move {
+                    let mut net = AsyncMultiNetwork::from_file(&path, id).await.unwrap();
+                    net.set_kings(Kings::One(N - 1));
+                    net.reset_stats();
+                    let output = MpcNetwork::king_compute(&net, &[id as u8], |inputs| {
+                        inputs.into_iter().rev().collect()
+                    });
+                    let sum = net.king_compute(&[1], |inputs| vec![vec![inputs.len() as u8]; N]);
+                    (output.unwrap(), sum.await.unwrap(), MpcNetwork::stats(&net))
+                })
+            })
+            .collect();
+        for (id, party) in parties.into_iter().enumerate() {
+            let (output, sum, stats) = runtime.block_on(party).unwrap();
+            assert_eq!(output, vec![(N - 1 - id) as u8]);
+            assert_eq!(sum, vec![N as u8]);
+            assert_eq!((stats.to_king, stats.from_king), (2, 2));
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn keys_rejected_test() {
+        let key = "11".repeat(32);
+        let hosts: String = (0..N)
+            .map(|id| format!("127.0.0.1:{} {}\n", 18350 + id, key))
+            .collect();
+        let config = HostConfig::parse_lines(&hosts).unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let net = runtime.block_on(AsyncMultiNetwork::from_config(config.clone(), 0));
+        assert!(matches!(net, Err(NetError::Config { .. })));
+        let net = runtime.block_on(AsyncMultiNetwork::from_config(config, N));
+        assert!(matches!(net, Err(NetError::Config { .. })));
+    }
+}
@@ -0,0 +1,239 @@
+// Host configuration of a session. Either one party per line, HOST:PORT optionally followed by
+// the hex public key of the party, or a TOML file with a [[party]] table per party:
+//
+//     [[party]]
+//     id = 0
+//     listen = "0.0.0.0:8000"        # address the party binds
+//     advertise = "10.0.0.1:8000"    # address the others connect to, listen if omitted
+//     public_key = "8f1e..."         # optional, every party or none has one
+//     [party.resources]              # optional hints, e.g. for a scheduler
+//     threads = 32
+//
+// Every party has to load the same configuration, connections check it with digest.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+
+use super::{noise, NetError};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartyConfig {
+    pub id: usize,
+    /// Address the party binds
+    pub listen: SocketAddr,
+    /// HOST:PORT the other parties connect to, differs from listen behind NAT or a load balancer
+    pub advertise: String,
+    /// Static public key, authenticates the party when the session has keys
+    pub public_key: Option<Vec<u8>>,
+    /// Optional hints on the resources of the party, the network does not use them
+    pub resources: BTreeMap<String, String>,
+}
+
+impl PartyConfig {
+    /// Resolves the advertised address
+    pub fn advertise_addr(&self) -> io::Result<SocketAddr> {
+        self.advertise.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} has no address", self.advertise),
+            )
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HostConfig {
+    /// Ordered by id
+    pub parties: Vec<PartyConfig>,
+}
+
+impl HostConfig {
+    /// Reads a configuration, files ending in .toml are TOML and other files have a party per line
+    pub fn from_file(path: &str) -> Result<Self, NetError> {
+        let config = |reason: String| NetError::Config {
+            reason: format!("host configuration {}: {}", path, reason),
+        };
+        let text = std::fs::read_to_string(path).map_err(|e| config(e.to_string()))?;
+        if path.ends_with(".toml") {
+            Self::parse_toml(&text).map_err(config)
+        } else {
+            Self::parse_lines(&text).map_err(config)
+        }
+    }
+
+    /// Parses the format with a HOST:PORT and an optional hex public key per line
+    pub fn parse_lines(text: &str) -> Result<Self, String> {
+        let mut parties = Vec::new();
+        for line in text.lines() {
+            let mut tokens = line.split_whitespace();
+            if let Some(addr) = tokens.next() {
+                let listen: SocketAddr = addr
+                    .parse()
+                    .map_err(|e| format!("bad socket address: {}: {}", addr, e))?;
+                parties.push(PartyConfig {
+                    id: parties.len(),
+                    listen,
+                    advertise: addr.to_string(),
+                    public_key: tokens.next().map(noise::parse_key).transpose()?,
+                    resources: BTreeMap::new(),
+                });
+            }
+        }
+        Self::new(parties)
+    }
+
+    /// Parses the TOML format, see the top of this file
+    pub fn parse_toml(text: &str) -> Result<Self, String> {
+        let table: toml::Table = text.parse().map_err(|e| format!("{}", e))?;
+        let parties = match table.get("party") {
+            Some(toml::Value::Array(parties)) => parties,
+            _ => return Err("no [[party]] tables".to_string()),
+        };
+        let parties = parties
+            .iter()
+            .map(|party| {
+                let party = party.as_table().ok_or("a party is not a table")?;
+                let string = |key: &str| -> Result<Option<String>, String> {
+                    match party.get(key) {
+                        None => Ok(None),
+                        Some(toml::Value::String(s)) => Ok(Some(s.clone())),
+                        Some(_) => Err(format!("{} is not a string", key)),
+                    }
+                };
+                let id = match party.get("id") {
+                    Some(toml::Value::Integer(id)) if *id >= 0 => *id as usize,
+                    _ => return Err("a party has no id".to_string()),
+                };
+                let listen = string("listen")?.ok_or(format!("party {} has no listen", id))?;
+                let advertise = string("advertise")?.unwrap_or_else(|| listen.clone());
+                let resources = match party.get("resources") {
+                    None => BTreeMap::new(),
+                    Some(toml::Value::Table(resources)) => resources
+                        .iter()
+                        .map(|(key, value)| {
+                            let value = match value {
+                                toml::Value::String(s) => s.clone(),
+                                toml::Value::Integer(i) => i.to_string(),
+                                toml::Value::Float(f) => f.to_string(),
+                                toml::Value::Boolean(b) => b.to_string(),
+                                _ => return Err(format!("resource {} is not a scalar", key)),
+                            };
+                            Ok((key.clone(), value))
+                        })
+                        .collect::<Result<_, String>>()?,
+                    Some(_) => return Err(format!("resources of party {} is not a table", id)),
+                };
+                Ok(PartyConfig {
+                    id,
+                    listen: listen
+                        .parse()
+                        .map_err(|e| format!("bad socket address: {}: {}", listen, e))?,
+                    advertise,
+                    public_key: string("public_key")?
+                        .as_deref()
+                        .map(noise::parse_key)
+                        .transpose()?,
+                    resources,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Self::new(parties)
+    }
+
+    /// Checks that the ids are 0..n and that every party or none has a public key
+    pub fn new(mut parties: Vec<PartyConfig>) -> Result<Self, String> {
+        parties.sort_by_key(|party| party.id);
+        if parties.is_empty() {
+            return Err("no parties".to_string());
+        }
+        for (i, party) in parties.iter().enumerate() {
+            if party.id != i {
+                return Err(format!("ids must be 0 to {}, got {}", parties.len() - 1, party.id));
+            }
+        }
+        let keys = parties.iter().filter(|p| p.public_key.is_some()).count();
+        if keys != 0 && keys != parties.len() {
+            return Err("either every party or no party has a public key".to_string());
+        }
+        Ok(HostConfig { parties })
+    }
+
+    pub fn n_parties(&self) -> usize {
+        self.parties.len()
+    }
+
+    /// Fingerprint of the configuration, the same for every party that loaded the same one
+    /// in either format
+    pub fn digest(&self) -> u64 {
+        // FNV-1a, unlike the hashers of std it does not change between builds
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        let mut write = |bytes: &[u8]| {
+            for byte in bytes.iter().chain(&[0xff]) {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+        for party in &self.parties {
+            write(&(party.id as u64).to_le_bytes());
+            write(party.listen.to_string().as_bytes());
+            write(party.advertise.as_bytes());
+            write(party.public_key.as_deref().unwrap_or(&[]));
+            for (key, value) in &party.resources {
+                write(key.as_bytes());
+                write(value.as_bytes());
+            }
+        }
+        hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let lines = HostConfig::parse_lines("127.0.0.1:8000\n\n127.0.0.1:8001\n").unwrap();
+        let toml = HostConfig::parse_toml(
+            r#"
+            [[party]]
+            id = 1
+            listen = "127.0.0.1:8001"
+
+            [[party]]
+            id = 0
+            listen = "127.0.0.1:8000"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(lines, toml);
+        assert_eq!(lines.digest(), toml.digest());
+
+        let nat = HostConfig::parse_toml(
+            r#"
+            [[party]]
+            id = 0
+            listen = "0.0.0.0:8000"
+            advertise = "localhost:9000"
+            [party.resources]
+            threads = 32
+            gpu = "a100"
+            "#,
+        )
+        .unwrap();
+        let party = &nat.parties[0];
+        assert_eq!(party.advertise_addr().unwrap().port(), 9000);
+        assert_eq!(party.resources["threads"], "32");
+        assert_eq!(party.resources["gpu"], "a100");
+        assert_ne!(nat.digest(), lines.digest());
+
+        assert!(HostConfig::parse_toml("[[party]]\nid = 1\nlisten = \"127.0.0.1:1\"").is_err());
+        assert!(HostConfig::parse_lines("localhost").is_err());
+        assert!(HostConfig::parse_lines("127.0.0.1:8000 beef").is_err());
+        assert!(matches!(
+            HostConfig::from_file("/nonexistent/hosts"),
+            Err(NetError::Config { .. })
+        ));
+    }
+}
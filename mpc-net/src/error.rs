@@ -0,0 +1,93 @@
+use std::fmt;
+use std::io;
+
+/// Why talking to a peer failed. Every variant but Config names the party at the other end, or
+/// the party at fault for failures that are not about a connection.
+#[derive(Debug)]
+pub enum NetError {
+    /// The peer did not show up or answer in time
+    Timeout { peer: usize },
+    /// The peer closed the connection
+    Disconnected { peer: usize },
+    /// The peer could not prove it holds the key of its party id
+    Authentication { peer: usize, reason: String },
+    /// The peer sent bytes that don't follow the protocol
+    MalformedFrame { peer: usize, reason: String },
+    /// The peer is at another step of the protocol or in another session
+    Desync { peer: usize, reason: String },
+    /// A message from the peer is not a valid encoding of what we expected
+    Deserialization { peer: usize, reason: String },
+    /// Any other failure of the connection to the peer
+    Io { peer: usize, source: io::Error },
+    /// The local preprocessing was generated for another run of the protocol
+    Preprocessing { reason: String },
+    /// The host configuration or a key could not be loaded, before talking to anyone
+    Config { reason: String },
+}
+
+impl NetError {
+    /// Classifies a failed read or write on the connection to peer
+    pub fn io(peer: usize, source: io::Error) -> Self {
+        match source.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => NetError::Timeout { peer },
+            io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe => NetError::Disconnected { peer },
+            io::ErrorKind::InvalidData => NetError::MalformedFrame {
+                peer,
+                reason: source.to_string(),
+            },
+            _ => NetError::Io { peer, source },
+        }
+    }
+
+    /// The party at the other end, None for a bad configuration or local preprocessing
+    pub fn peer(&self) -> Option<usize> {
+        match self {
+            NetError::Timeout { peer }
+            | NetError::Disconnected { peer }
+            | NetError::Authentication { peer, .. }
+            | NetError::MalformedFrame { peer, .. }
+            | NetError::Desync { peer, .. }
+            | NetError::Deserialization { peer, .. }
+            | NetError::Io { peer, .. } => Some(*peer),
+            NetError::Preprocessing { .. } | NetError::Config { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetError::Timeout { peer } => write!(f, "party {} timed out", peer),
+            NetError::Disconnected { peer } => write!(f, "party {} disconnected", peer),
+            NetError::Authentication { peer, reason } => {
+                write!(f, "party {} failed to authenticate: {}", peer, reason)
+            }
+            NetError::MalformedFrame { peer, reason } => {
+                write!(f, "malformed message from party {}: {}", peer, reason)
+            }
+            NetError::Desync { peer, reason } => {
+                write!(f, "party {} is at another step of the protocol: {}", peer, reason)
+            }
+            NetError::Deserialization { peer, reason } => {
+                write!(f, "could not deserialize the message of party {}: {}", peer, reason)
+            }
+            NetError::Io { peer, source } => write!(f, "connection to party {}: {}", peer, source),
+            NetError::Preprocessing { reason } => {
+                write!(f, "preprocessing does not match the protocol: {}", reason)
+            }
+            NetError::Config { reason } => write!(f, "bad configuration: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for NetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NetError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
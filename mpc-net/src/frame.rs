@@ -0,0 +1,319 @@
+// Wire format shared by the networks. Every message is a frame: a fixed size header followed
+// by the payload. The header names the session and the step of the protocol the message belongs
+// to, so parties that run different steps fail on the first message instead of hanging or
+// reading garbage.
+
+use std::collections::hash_map::RandomState;
+use std::convert::TryInto;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Write};
+
+use super::NetError;
+
+/// session (8) | round (8) | kind (1) | payload length (8) | payload checksum (4), little endian
+pub const HEADER_LEN: usize = 29;
+
+/// Frames announcing a longer payload are rejected. Shorter payloads are read into a buffer that
+/// grows with the bytes that arrive, so the length in a header alone never makes us allocate.
+pub const MAX_PAYLOAD_LEN: u64 = 1 << 36;
+
+/// The operation a message is part of
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Broadcast = 1,
+    ToKing = 2,
+    FromKing = 3,
+    AllToAll = 4,
+    /// A message to a single party, its round counts the messages between the two parties
+    Direct = 5,
+}
+
+impl Kind {
+    fn from_u8(byte: u8) -> Option<Self> {
+        match byte {
+            1 => Some(Kind::Broadcast),
+            2 => Some(Kind::ToKing),
+            3 => Some(Kind::FromKing),
+            4 => Some(Kind::AllToAll),
+            5 => Some(Kind::Direct),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Broadcast => "broadcast",
+            Kind::ToKing => "to_king",
+            Kind::FromKing => "from_king",
+            Kind::AllToAll => "all_to_all",
+            Kind::Direct => "direct",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    /// Random per session, tells apart messages of an older session on the same address
+    pub session: u64,
+    /// Number of operations the sender did in this session before this one, see Kind::Direct
+    pub round: u64,
+    pub kind: Kind,
+    pub len: u64,
+    /// CRC-32 of the payload
+    pub checksum: u32,
+}
+
+impl Header {
+    pub fn new(session: u64, round: u64, kind: Kind, payload: &[u8]) -> Self {
+        Header {
+            session,
+            round,
+            kind,
+            len: payload.len() as u64,
+            checksum: crc32(payload),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0u8; HEADER_LEN];
+        bytes[0..8].copy_from_slice(&self.session.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.round.to_le_bytes());
+        bytes[16] = self.kind as u8;
+        bytes[17..25].copy_from_slice(&self.len.to_le_bytes());
+        bytes[25..29].copy_from_slice(&self.checksum.to_le_bytes());
+        bytes
+    }
+
+    /// Parses the header of a frame from peer
+    pub fn from_bytes(bytes: &[u8; HEADER_LEN], peer: usize) -> Result<Self, NetError> {
+        let u64_at = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+        let kind = Kind::from_u8(bytes[16]).ok_or_else(|| NetError::MalformedFrame {
+            peer,
+            reason: format!("unknown message kind {}", bytes[16]),
+        })?;
+        let len = u64_at(17);
+        if len > MAX_PAYLOAD_LEN {
+            return Err(NetError::MalformedFrame {
+                peer,
+                reason: format!("payload of {} bytes is too long", len),
+            });
+        }
+        Ok(Header {
+            session: u64_at(0),
+            round: u64_at(8),
+            kind,
+            len,
+            checksum: u32::from_le_bytes(bytes[25..29].try_into().unwrap()),
+        })
+    }
+
+    /// Checks that peer sent this frame for the same step of the same session as ours
+    pub fn check_step(
+        &self,
+        session: u64,
+        round: u64,
+        kind: Kind,
+        peer: usize,
+    ) -> Result<(), NetError> {
+        if self.session != session {
+            return Err(NetError::Desync {
+                peer,
+                reason: format!("expected session {:x}, got {:x}", session, self.session),
+            });
+        }
+        if self.round != round || self.kind != kind {
+            return Err(NetError::Desync {
+                peer,
+                reason: format!(
+                    "expected round {} ({:?}), got round {} ({:?})",
+                    round, kind, self.round, self.kind
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks the payload of peer against the length and checksum of the header
+    pub fn check_payload(&self, payload: &[u8], peer: usize) -> Result<(), NetError> {
+        if payload.len() as u64 != self.len {
+            return Err(NetError::MalformedFrame {
+                peer,
+                reason: format!("header announces {} bytes, got {}", self.len, payload.len()),
+            });
+        }
+        if crc32(payload) != self.checksum {
+            return Err(NetError::MalformedFrame {
+                peer,
+                reason: "checksum mismatch".to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Checks that peer sent as many bytes as everyone else at this step
+pub fn check_len(payload: Vec<u8>, len: usize, peer: usize) -> Result<Vec<u8>, NetError> {
+    if payload.len() != len {
+        return Err(NetError::MalformedFrame {
+            peer,
+            reason: format!("expected {} bytes, got {}", len, payload.len()),
+        });
+    }
+    Ok(payload)
+}
+
+/// A fresh session id, drawn by king when a session starts
+pub fn new_session() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// Writes payload as a single frame
+pub fn write_frame<W: Write>(stream: &mut W, header: &Header, payload: &[u8]) -> io::Result<()> {
+    stream.write_all(&header.to_bytes())?;
+    stream.write_all(payload)
+}
+
+/// Reads the next frame from peer and returns its payload if it is the expected one
+pub fn read_frame<R: Read>(
+    stream: &mut R,
+    session: u64,
+    round: u64,
+    kind: Kind,
+    peer: usize,
+) -> Result<Vec<u8>, NetError> {
+    let mut bytes = [0u8; HEADER_LEN];
+    stream
+        .read_exact(&mut bytes)
+        .map_err(|e| NetError::io(peer, e))?;
+    let header = Header::from_bytes(&bytes, peer)?;
+    header.check_step(session, round, kind, peer)?;
+    let mut payload = Vec::new();
+    read_payload(stream, header.len, &mut payload).map_err(|e| NetError::io(peer, e))?;
+    header.check_payload(&payload, peer)?;
+    Ok(payload)
+}
+
+/// Reads the next frame from peer as is, header included, see decode
+pub fn read_raw<R: Read>(stream: &mut R, peer: usize) -> Result<Vec<u8>, NetError> {
+    let mut frame = vec![0u8; HEADER_LEN];
+    stream
+        .read_exact(&mut frame)
+        .map_err(|e| NetError::io(peer, e))?;
+    let header = Header::from_bytes(frame[..].try_into().unwrap(), peer)?;
+    read_payload(stream, header.len, &mut frame).map_err(|e| NetError::io(peer, e))?;
+    Ok(frame)
+}
+
+/// Appends the next len bytes of stream to buf, which only grows as the bytes arrive
+fn read_payload<R: Read>(stream: &mut R, len: u64, buf: &mut Vec<u8>) -> io::Result<()> {
+    let read = stream.take(len).read_to_end(buf)?;
+    if (read as u64) < len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("stream ended after {} of {} bytes", read, len),
+        ));
+    }
+    Ok(())
+}
+
+/// Encodes a whole frame, for transports that carry messages instead of byte streams
+pub fn encode(header: &Header, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+    frame.extend_from_slice(&header.to_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Decodes a frame made by encode, see read_frame
+pub fn decode(
+    mut frame: Vec<u8>,
+    session: u64,
+    round: u64,
+    kind: Kind,
+    peer: usize,
+) -> Result<Vec<u8>, NetError> {
+    if frame.len() < HEADER_LEN {
+        return Err(NetError::MalformedFrame {
+            peer,
+            reason: format!("frame of {} bytes has no header", frame.len()),
+        });
+    }
+    let header = Header::from_bytes(frame[..HEADER_LEN].try_into().unwrap(), peer)?;
+    header.check_step(session, round, kind, peer)?;
+    let payload = frame.split_off(HEADER_LEN);
+    header.check_payload(&payload, peer)?;
+    Ok(payload)
+}
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// CRC-32 as used by zip and ethernet
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, byte| {
+        CRC32_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_test() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn frame_test() {
+        let payload = vec![7u8; 100];
+        let header = Header::new(42, 3, Kind::ToKing, &payload);
+        let mut frame = encode(&header, &payload);
+
+        let mut stream = &frame[..];
+        assert_eq!(read_frame(&mut stream, 42, 3, Kind::ToKing, 1).unwrap(), payload);
+        assert_eq!(decode(frame.clone(), 42, 3, Kind::ToKing, 1).unwrap(), payload);
+
+        // A party at another step of the protocol
+        let wrong_round = decode(frame.clone(), 42, 4, Kind::ToKing, 1);
+        assert!(matches!(wrong_round, Err(NetError::Desync { peer: 1, .. })));
+        let wrong_kind = decode(frame.clone(), 42, 3, Kind::Broadcast, 1);
+        assert!(matches!(wrong_kind, Err(NetError::Desync { peer: 1, .. })));
+        let wrong_session = decode(frame.clone(), 41, 3, Kind::ToKing, 1);
+        assert!(matches!(wrong_session, Err(NetError::Desync { peer: 1, .. })));
+
+        // A corrupted payload
+        frame[HEADER_LEN + 10] ^= 1;
+        let corrupted = decode(frame, 42, 3, Kind::ToKing, 1);
+        assert!(matches!(corrupted, Err(NetError::MalformedFrame { peer: 1, .. })));
+    }
+
+    #[test]
+    fn truncated_test() {
+        // A header announcing the longest payload, followed by a few bytes only
+        let mut header = Header::new(42, 3, Kind::ToKing, &[]);
+        header.len = MAX_PAYLOAD_LEN;
+        let frame = [&header.to_bytes()[..], &[1, 2, 3]].concat();
+
+        let truncated = read_frame(&mut &frame[..], 42, 3, Kind::ToKing, 1);
+        assert!(matches!(truncated, Err(NetError::Disconnected { peer: 1 })));
+        let truncated = read_raw(&mut &frame[..], 1);
+        assert!(matches!(truncated, Err(NetError::Disconnected { peer: 1 })));
+    }
+}
@@ -0,0 +1,146 @@
+// Which parties play king. Protocols that send everything to a single king make that party
+// receive n times the traffic of the others, so work on independent chunks can be split over
+// several kings instead. Every party of a call must use the same Kings.
+
+use std::ops::Range;
+
+use super::{MpcNetwork, NetError, Stats};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kings {
+    /// The party with this id does all the work of king
+    One(usize),
+    /// Parties 0..k split the independent chunks of the work, party 0 leads the rest
+    Spread(usize),
+}
+
+impl std::default::Default for Kings {
+    fn default() -> Self {
+        Kings::One(0)
+    }
+}
+
+impl Kings {
+    /// The party that does the work that cannot be split
+    pub fn leader(&self) -> usize {
+        match *self {
+            Kings::One(king) => king,
+            Kings::Spread(_) => 0,
+        }
+    }
+
+    /// Number of kings among n parties
+    pub fn count(&self, n: usize) -> usize {
+        match *self {
+            Kings::One(_) => 1,
+            Kings::Spread(k) => k.clamp(1, n),
+        }
+    }
+
+    pub fn is_king(&self, party: usize, n: usize) -> bool {
+        match *self {
+            Kings::One(king) => party == king,
+            Kings::Spread(_) => party < self.count(n),
+        }
+    }
+
+    /// Splits len chunks into contiguous ranges of nearly equal size, one per party.
+    /// The ranges of parties that are not kings are empty.
+    pub fn ranges(&self, n: usize, len: usize) -> Vec<Range<usize>> {
+        let k = self.count(n);
+        let mut start = 0;
+        let mut kings_seen = 0;
+        (0..n)
+            .map(|party| {
+                if !self.is_king(party, n) {
+                    return start..start;
+                }
+                let size = len / k + usize::from(kings_seen < len % k);
+                kings_seen += 1;
+                start += size;
+                start - size..start
+            })
+            .collect()
+    }
+}
+
+/// A network handle that uses other kings, see MpcNetwork::with_kings
+pub struct WithKings<'a, N: ?Sized> {
+    net: &'a N,
+    kings: Kings,
+}
+
+impl<'a, N: MpcNetwork + ?Sized> WithKings<'a, N> {
+    pub fn new(net: &'a N, kings: Kings) -> Self {
+        assert!(kings.leader() < net.n_parties(), "king {} does not exist", kings.leader());
+        WithKings { net, kings }
+    }
+}
+
+impl<'a, N: MpcNetwork + ?Sized> MpcNetwork for WithKings<'a, N> {
+    #[inline]
+    fn kings(&self) -> Kings {
+        self.kings
+    }
+
+    #[inline]
+    fn n_parties(&self) -> usize {
+        self.net.n_parties()
+    }
+
+    #[inline]
+    fn party_id(&self) -> usize {
+        self.net.party_id()
+    }
+
+    fn reset_stats(&self) {
+        self.net.reset_stats()
+    }
+
+    fn stats(&self) -> Stats {
+        self.net.stats()
+    }
+
+    fn broadcast_bytes(&self, bytes: &[u8]) -> Result<Vec<Vec<u8>>, NetError> {
+        self.net.broadcast_bytes(bytes)
+    }
+
+    fn all_to_all_bytes(&self, bytes: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>, NetError> {
+        self.net.all_to_all_bytes(bytes)
+    }
+
+    fn send_bytes_to(&self, party: usize, bytes: &[u8]) -> Result<(), NetError> {
+        self.net.send_bytes_to(party, bytes)
+    }
+
+    fn recv_bytes_from(&self, party: usize) -> Result<Vec<u8>, NetError> {
+        self.net.recv_bytes_from(party)
+    }
+
+    fn gather_bytes(&self, king: usize, bytes: &[u8]) -> Result<Option<Vec<Vec<u8>>>, NetError> {
+        self.net.gather_bytes(king, bytes)
+    }
+
+    fn scatter_bytes(
+        &self,
+        king: usize,
+        bytes: Option<Vec<Vec<u8>>>,
+    ) -> Result<Vec<u8>, NetError> {
+        self.net.scatter_bytes(king, bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_test() {
+        assert_eq!(Kings::One(2).ranges(4, 5), vec![0..0, 0..0, 0..5, 5..5]);
+        assert_eq!(Kings::Spread(3).ranges(4, 5), vec![0..2, 2..4, 4..5, 5..5]);
+        // More kings than chunks
+        assert_eq!(Kings::Spread(4).ranges(4, 2), vec![0..1, 1..2, 2..2, 2..2]);
+        // More kings than parties
+        assert_eq!(Kings::Spread(9).ranges(2, 4), vec![0..2, 2..4]);
+    }
+}
@@ -1,30 +1,24 @@
+pub mod async_multi;
+pub mod config;
+pub mod error;
+pub mod frame;
+pub mod kings;
+mod link;
+pub mod local;
 pub mod multi;
+pub mod noise;
+pub mod stats;
 pub mod two;
 
-pub use multi::MpcMultiNet;
+pub use async_multi::AsyncMultiNetwork;
+pub use config::{HostConfig, PartyConfig};
+pub use error::NetError;
+pub use kings::{Kings, WithKings};
+pub use local::LocalNetwork;
+pub use multi::{MpcMultiNet, MultiNetwork};
+pub use stats::{label, Stats};
 pub use two::MpcTwoNet;
 
-#[derive(Clone, Debug)]
-pub struct Stats {
-    pub bytes_sent: usize,
-    pub bytes_recv: usize,
-    pub broadcasts: usize,
-    pub to_king: usize,
-    pub from_king: usize,
-}
-
-impl std::default::Default for Stats {
-    fn default() -> Self {
-        Self {
-            bytes_sent: 0,
-            bytes_recv: 0,
-            broadcasts: 0,
-            to_king: 0,
-            from_king: 0,
-        }
-    }
-}
-
 pub trait MpcNet {
     /// Am I the first party?
     #[inline]
@@ -37,10 +31,10 @@ pub trait MpcNet {
     fn party_id() -> usize;
     /// Initialize the network layer from a file.
     /// The file should contain one HOST:PORT setting per line, corresponding to the addresses of
-    /// the parties in increasing order.
+    /// the parties in increasing order, or be a TOML file, see HostConfig.
     ///
     /// Parties are zero-indexed.
-    fn init_from_file(path: &str, party_id: usize);
+    fn init_from_file(path: &str, party_id: usize) -> Result<(), NetError>;
     /// Is the network layer initalized?
     fn is_init() -> bool;
     /// Uninitialize the network layer, closing all connections.
@@ -49,13 +43,20 @@ pub trait MpcNet {
     fn reset_stats();
     /// Get statistics.
     fn stats() -> Stats;
-    /// All parties send bytes to each other.
-    fn broadcast_bytes(bytes: &[u8]) -> Vec<Vec<u8>>;
+    /// All parties send bytes to each other, the bytes of every party can have any length.
+    fn broadcast_bytes(bytes: &[u8]) -> Result<Vec<Vec<u8>>, NetError>;
+    /// All parties send bytes[i] to party i and get what every party sent them.
+    fn all_to_all_bytes(bytes: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>, NetError>;
+    /// Sends bytes to party only, which has to call recv_bytes_from.
+    /// May block until party reads, so two parties must not send to each other at once.
+    fn send_bytes_to(party: usize, bytes: &[u8]) -> Result<(), NetError>;
+    /// Receives the next bytes party sent to us with send_bytes_to.
+    fn recv_bytes_from(party: usize) -> Result<Vec<u8>, NetError>;
     /// All parties send bytes to the king.
-    fn send_bytes_to_king(bytes: &[u8]) -> Option<Vec<Vec<u8>>>;
+    fn send_bytes_to_king(bytes: &[u8]) -> Result<Option<Vec<Vec<u8>>>, NetError>;
     /// All parties recv bytes from the king.
     /// Provide bytes iff you're the king!
-    fn recv_bytes_from_king(bytes: Option<Vec<Vec<u8>>>) -> Vec<u8>;
+    fn recv_bytes_from_king(bytes: Option<Vec<Vec<u8>>>) -> Result<Vec<u8>, NetError>;
 
     /// Everyone sends bytes to the king, who receives those bytes, runs a computation on them, and
     /// redistributes the resulting bytes.
@@ -63,8 +64,81 @@ pub trait MpcNet {
     /// The king's computation is given by a function, `f`
     /// proceeds.
     #[inline]
-    fn king_compute(bytes: &[u8], f: impl Fn(Vec<Vec<u8>>) -> Vec<Vec<u8>>) -> Vec<u8> {
-        let king_response = Self::send_bytes_to_king(bytes).map(f);
+    fn king_compute(
+        bytes: &[u8],
+        f: impl Fn(Vec<Vec<u8>>) -> Vec<Vec<u8>>,
+    ) -> Result<Vec<u8>, NetError> {
+        let king_response = Self::send_bytes_to_king(bytes)?.map(f);
         Self::recv_bytes_from_king(king_response)
     }
 }
+
+/// A party's handle to one session with the other parties.
+/// Unlike MpcNet every handle is its own session, so a process can run many sessions at once
+/// and all parties of a session can live in the same process.
+pub trait MpcNetwork: Send + Sync {
+    /// The parties that play king in the calls made with this handle
+    #[inline]
+    fn kings(&self) -> Kings {
+        Kings::default()
+    }
+    /// The same session with other kings, so the kings can be picked per call
+    #[inline]
+    fn with_kings(&self, kings: Kings) -> WithKings<'_, Self> {
+        WithKings::new(self, kings)
+    }
+    /// Am I the leading king?
+    #[inline]
+    fn am_king(&self) -> bool {
+        self.party_id() == self.kings().leader()
+    }
+    /// How many parties are there?
+    fn n_parties(&self) -> usize;
+    /// What is my party number (0 to n-1)?
+    fn party_id(&self) -> usize;
+    /// Set statistics to zero.
+    fn reset_stats(&self);
+    /// Get statistics.
+    fn stats(&self) -> Stats;
+    /// All parties send bytes to each other, the bytes of every party can have any length.
+    fn broadcast_bytes(&self, bytes: &[u8]) -> Result<Vec<Vec<u8>>, NetError>;
+    /// All parties send bytes[i] to party i and get what every party sent them.
+    fn all_to_all_bytes(&self, bytes: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>, NetError>;
+    /// Sends bytes to party only, which has to call recv_bytes_from.
+    /// May block until party reads, so two parties must not send to each other at once.
+    fn send_bytes_to(&self, party: usize, bytes: &[u8]) -> Result<(), NetError>;
+    /// Receives the next bytes party sent to us with send_bytes_to.
+    fn recv_bytes_from(&self, party: usize) -> Result<Vec<u8>, NetError>;
+    /// All parties send bytes of the same length to king, who gets them in the order of parties.
+    fn gather_bytes(&self, king: usize, bytes: &[u8]) -> Result<Option<Vec<Vec<u8>>>, NetError>;
+    /// All parties recv bytes from king.
+    /// Provide bytes iff you're king!
+    fn scatter_bytes(
+        &self,
+        king: usize,
+        bytes: Option<Vec<Vec<u8>>>,
+    ) -> Result<Vec<u8>, NetError>;
+    /// All parties send bytes to the leading king.
+    #[inline]
+    fn send_bytes_to_king(&self, bytes: &[u8]) -> Result<Option<Vec<Vec<u8>>>, NetError> {
+        self.gather_bytes(self.kings().leader(), bytes)
+    }
+    /// All parties recv bytes from the leading king.
+    /// Provide bytes iff you're the king!
+    #[inline]
+    fn recv_bytes_from_king(&self, bytes: Option<Vec<Vec<u8>>>) -> Result<Vec<u8>, NetError> {
+        self.scatter_bytes(self.kings().leader(), bytes)
+    }
+
+    /// Everyone sends bytes to the king, who receives those bytes, runs a computation on them, and
+    /// redistributes the resulting bytes.
+    #[inline]
+    fn king_compute(
+        &self,
+        bytes: &[u8],
+        f: impl Fn(Vec<Vec<u8>>) -> Vec<Vec<u8>>,
+    ) -> Result<Vec<u8>, NetError> {
+        let king_response = self.send_bytes_to_king(bytes)?.map(f);
+        self.recv_bytes_from_king(king_response)
+    }
+}
@@ -0,0 +1,415 @@
+// Connections to the other parties that survive dropped TCP streams. Every frame goes out with
+// the number of frames received from the peer so far, which acknowledges them. Frames the peer
+// has not acknowledged are kept, so when a stream drops both ends connect again, tell each
+// other how many frames they got and send the missing ones again.
+
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+
+use super::config::HostConfig;
+use super::frame;
+use super::noise::NoiseStream;
+use super::NetError;
+
+/// Bytes in front of every frame, the number of frames received from the peer
+pub(crate) const ACK_LEN: usize = 8;
+/// How long to try to reach a peer, the first time or after a drop
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long a new connection has to say which party it is
+const HELLO_TIMEOUT: Duration = Duration::from_secs(1);
+/// Times an operation reconnects before giving up on the peer
+const RECONNECT_ATTEMPTS: usize = 3;
+/// Bytes of unacknowledged frames kept per peer, older frames can't be sent again
+const MAX_UNACKED_BYTES: usize = 1 << 30;
+
+/// A connection to a peer, encrypted when the session has keys
+#[derive(Debug)]
+pub(crate) enum Stream {
+    Plain(TcpStream),
+    Noise(Box<NoiseStream>),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(s) => s.read(buf),
+            Stream::Noise(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(s) => s.write(buf),
+            Stream::Noise(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(s) => s.flush(),
+            Stream::Noise(s) => s.flush(),
+        }
+    }
+}
+
+/// Errors after which connecting again can help
+fn is_dropped(e: &NetError) -> bool {
+    matches!(e, NetError::Disconnected { .. } | NetError::Io { .. })
+}
+
+/// Our end of the connections to all parties
+#[derive(Default, Debug)]
+pub(crate) struct Endpoint {
+    pub(crate) id: usize,
+    /// Where every party listens and can be reached, with its public key
+    pub(crate) config: HostConfig,
+    /// Our static private key, connections are plain TCP without it
+    pub(crate) private_key: Option<Vec<u8>>,
+    /// Parties with a lower id connect to it, bound for the whole session
+    listener: Option<TcpListener>,
+    /// Connections accepted while waiting for another party
+    pending: Mutex<Vec<(usize, TcpStream)>>,
+}
+
+impl Endpoint {
+    pub(crate) fn listen(&mut self) -> Result<(), NetError> {
+        let listener = TcpListener::bind(self.config.parties[self.id].listen)
+            .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+            .map_err(|e| NetError::io(self.id, e))?;
+        self.listener = Some(listener);
+        Ok(())
+    }
+
+    pub(crate) fn is_listening(&self) -> bool {
+        self.listener.is_some()
+    }
+
+    pub(crate) fn close(&mut self) {
+        self.listener = None;
+        self.pending.lock().expect("Poisoned Endpoint").clear();
+    }
+
+    /// Authenticates the peer and encrypts the connection when the session has keys
+    fn secure(&self, stream: TcpStream, peer: usize, initiator: bool) -> Result<Stream, NetError> {
+        let private_key = match &self.private_key {
+            Some(private_key) => private_key,
+            None => return Ok(Stream::Plain(stream)),
+        };
+        let public_key = self.config.parties[peer]
+            .public_key
+            .as_ref()
+            .expect("MultiNetwork::from_config checks that every party has a public key");
+        let stream = if initiator {
+            NoiseStream::initiate(stream, private_key, public_key)
+        } else {
+            NoiseStream::respond(stream, private_key, public_key)
+        };
+        let stream = stream.map_err(|e| NetError::Authentication {
+            peer,
+            reason: e.to_string(),
+        })?;
+        Ok(Stream::Noise(Box::new(stream)))
+    }
+
+    /// Connects to a party with a higher id and tells it who we are
+    fn dial(&self, peer: usize, deadline: Instant) -> Result<Stream, NetError> {
+        debug!("Contacting {}", peer);
+        // Resolved on every attempt, the address behind a name can change while we reconnect
+        let addr = self.config.parties[peer].advertise.as_str();
+        let mut stream = loop {
+            match TcpStream::connect(addr) {
+                Ok(s) => break s,
+                Err(e) => match e.kind() {
+                    io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset => {
+                        if Instant::now() > deadline {
+                            return Err(NetError::Timeout { peer });
+                        }
+                        std::thread::sleep(Duration::from_millis(10));
+                    }
+                    _ => return Err(NetError::io(peer, e)),
+                },
+            }
+        };
+        stream
+            .set_nodelay(true)
+            .and_then(|_| stream.write_all(&(self.id as u64).to_le_bytes()))
+            .map_err(|e| NetError::io(peer, e))?;
+        // Whoever we reached has to prove it is peer before we use the connection
+        self.secure(stream, peer, true)
+    }
+
+    /// Waits for a party with a lower id to connect.
+    /// Parties can connect in any order, the others are kept for later.
+    fn answer(&self, peer: usize, deadline: Instant) -> Result<Stream, NetError> {
+        debug!("Awaiting {}", peer);
+        let listener = self.listener.as_ref().expect("Endpoint is not listening");
+        loop {
+            // Only held to look at the queue, another thread may accept while we wait for a hello
+            let queued = {
+                let mut pending = self.pending.lock().expect("Poisoned Endpoint");
+                let i = pending.iter().position(|(id, _)| *id == peer);
+                i.map(|i| pending.swap_remove(i).1)
+            };
+            if let Some(stream) = queued {
+                // Whoever connects has to prove it is peer before we use the connection
+                return self.secure(stream, peer, false);
+            }
+            match listener.accept() {
+                Ok((stream, _addr)) => {
+                    // Connections that don't say which party they are in time are dropped
+                    if let Ok(id) = read_hello(&stream) {
+                        if id < self.config.n_parties() && id != self.id {
+                            self.pending.lock().expect("Poisoned Endpoint").push((id, stream));
+                        }
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    if Instant::now() > deadline {
+                        return Err(NetError::Timeout { peer });
+                    }
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                Err(e) => return Err(NetError::io(peer, e)),
+            }
+        }
+    }
+}
+
+/// Reads the id a new connection starts with
+fn read_hello(mut stream: &TcpStream) -> io::Result<usize> {
+    let mut id = [0u8; 8];
+    stream.set_nonblocking(false)?;
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(HELLO_TIMEOUT))?;
+    stream.read_exact(&mut id)?;
+    stream.set_read_timeout(None)?;
+    Ok(u64::from_le_bytes(id) as usize)
+}
+
+fn write_acked(
+    stream: Option<&mut Stream>,
+    peer: usize,
+    ack: u64,
+    frame: &[u8],
+) -> Result<(), NetError> {
+    let stream = stream.ok_or(NetError::Disconnected { peer })?;
+    stream
+        .write_all(&ack.to_le_bytes())
+        .and_then(|_| stream.write_all(frame))
+        .map_err(|e| NetError::io(peer, e))
+}
+
+/// The connection to one peer
+#[derive(Debug)]
+pub(crate) struct Link {
+    peer: usize,
+    stream: Option<Stream>,
+    /// Number of frames sent to the peer, the last ones that it did not acknowledge are kept
+    sent: u64,
+    unacked: VecDeque<Vec<u8>>,
+    unacked_bytes: usize,
+    /// Number of frames received from the peer
+    received: u64,
+    /// Frames the peer sent again when we resumed, they come before the ones on the stream
+    replayed: VecDeque<Vec<u8>>,
+}
+
+impl Link {
+    pub(crate) fn new(peer: usize) -> Self {
+        Link {
+            peer,
+            stream: None,
+            sent: 0,
+            unacked: VecDeque::new(),
+            unacked_bytes: 0,
+            received: 0,
+            replayed: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn close(&mut self) {
+        self.stream = None;
+    }
+
+    /// Connects to the peer, or connects again after a drop and resumes where the last
+    /// connection stopped. The party with the lower id dials, the other one answers.
+    pub(crate) fn connect(&mut self, endpoint: &Endpoint, session: u64) -> Result<(), NetError> {
+        let deadline = Instant::now() + CONNECT_TIMEOUT;
+        loop {
+            match self.try_connect(endpoint, session, deadline) {
+                Err(e) if is_dropped(&e) && Instant::now() < deadline => {
+                    debug!("Connecting to {} failed: {}", self.peer, e);
+                }
+                r => return r,
+            }
+        }
+    }
+
+    fn try_connect(
+        &mut self,
+        endpoint: &Endpoint,
+        session: u64,
+        deadline: Instant,
+    ) -> Result<(), NetError> {
+        let peer = self.peer;
+        self.stream = None;
+        let mut stream = if endpoint.id < peer {
+            endpoint.dial(peer, deadline)?
+        } else {
+            endpoint.answer(peer, deadline)?
+        };
+
+        // session | host configuration | frames sent | frames received, from both ends
+        let digest = endpoint.config.digest();
+        let mut hello = [0u8; 32];
+        hello[..8].copy_from_slice(&session.to_le_bytes());
+        hello[8..16].copy_from_slice(&digest.to_le_bytes());
+        hello[16..24].copy_from_slice(&self.sent.to_le_bytes());
+        hello[24..].copy_from_slice(&self.received.to_le_bytes());
+        stream
+            .write_all(&hello)
+            .and_then(|_| stream.read_exact(&mut hello))
+            .map_err(|e| NetError::io(peer, e))?;
+        let u64_at = |i: usize| u64::from_le_bytes(hello[i..i + 8].try_into().unwrap());
+        let (peer_session, peer_digest) = (u64_at(0), u64_at(8));
+        let (peer_sent, peer_received) = (u64_at(16), u64_at(24));
+        if peer_digest != digest {
+            return Err(NetError::Desync {
+                peer,
+                reason: format!(
+                    "host configurations differ, ours hashes to {:x}, theirs to {:x}",
+                    digest, peer_digest
+                ),
+            });
+        }
+        if peer_session != session {
+            return Err(NetError::Desync {
+                peer,
+                reason: format!("expected session {:x}, got {:x}", session, peer_session),
+            });
+        }
+        if peer_received > self.sent || peer_sent < self.received {
+            return Err(NetError::Desync {
+                peer,
+                reason: format!(
+                    "sent {} and received {} frames, peer says {} and {}",
+                    self.sent, self.received, peer_received, peer_sent
+                ),
+            });
+        }
+        self.acknowledge(peer_received);
+        if peer_received < self.sent - self.unacked.len() as u64 {
+            warn!("Frames for {} were dropped from the replay buffer", peer);
+            return Err(NetError::Disconnected { peer });
+        }
+        self.stream = Some(stream);
+
+        // The lower id sends the missing frames first, so both ends never write at once
+        let missing = peer_sent - self.received;
+        if endpoint.id < peer {
+            self.replay()?;
+            self.read_replayed(missing)?;
+        } else {
+            self.read_replayed(missing)?;
+            self.replay()?;
+        }
+        if missing > 0 || !self.unacked.is_empty() {
+            debug!("Resumed {}, got {} frames again", peer, missing);
+        }
+        Ok(())
+    }
+
+    /// Drops the frames the peer received from the replay buffer
+    fn acknowledge(&mut self, ack: u64) {
+        let first = self.sent - self.unacked.len() as u64;
+        for _ in first..ack.min(self.sent) {
+            let frame = self.unacked.pop_front().unwrap();
+            self.unacked_bytes -= frame.len();
+        }
+    }
+
+    fn replay(&mut self) -> Result<(), NetError> {
+        for frame in self.unacked.iter() {
+            write_acked(self.stream.as_mut(), self.peer, self.received, frame)?;
+        }
+        Ok(())
+    }
+
+    fn read_replayed(&mut self, count: u64) -> Result<(), NetError> {
+        for _ in 0..count {
+            let frame = self.read_stream()?;
+            self.replayed.push_back(frame);
+        }
+        Ok(())
+    }
+
+    fn read_stream(&mut self) -> Result<Vec<u8>, NetError> {
+        let peer = self.peer;
+        let stream = self.stream.as_mut().ok_or(NetError::Disconnected { peer })?;
+        let mut ack = [0u8; ACK_LEN];
+        stream
+            .read_exact(&mut ack)
+            .map_err(|e| NetError::io(peer, e))?;
+        let frame = frame::read_raw(stream, peer)?;
+        self.acknowledge(u64::from_le_bytes(ack));
+        self.received += 1;
+        Ok(frame)
+    }
+
+    /// Sends a frame made by frame::encode, reconnecting if the stream dropped
+    pub(crate) fn write(
+        &mut self,
+        endpoint: &Endpoint,
+        session: u64,
+        frame: Vec<u8>,
+    ) -> Result<(), NetError> {
+        self.sent += 1;
+        self.unacked_bytes += frame.len();
+        self.unacked.push_back(frame);
+        while self.unacked_bytes > MAX_UNACKED_BYTES && self.unacked.len() > 1 {
+            let frame = self.unacked.pop_front().unwrap();
+            self.unacked_bytes -= frame.len();
+        }
+
+        let frame = self.unacked.back().unwrap();
+        let mut result = write_acked(self.stream.as_mut(), self.peer, self.received, frame);
+        for _ in 0..RECONNECT_ATTEMPTS {
+            match result {
+                // Resuming sends every frame the peer did not get, this one included
+                Err(e) if is_dropped(&e) => {
+                    warn!("Connection to {} dropped: {}, reconnecting", self.peer, e);
+                    result = self.connect(endpoint, session);
+                }
+                _ => break,
+            }
+        }
+        result
+    }
+
+    /// Receives the next frame, see frame::decode, reconnecting if the stream dropped
+    pub(crate) fn read(&mut self, endpoint: &Endpoint, session: u64) -> Result<Vec<u8>, NetError> {
+        for _ in 0..RECONNECT_ATTEMPTS {
+            if let Some(frame) = self.replayed.pop_front() {
+                return Ok(frame);
+            }
+            match self.read_stream() {
+                Err(e) if is_dropped(&e) => {
+                    warn!("Connection to {} dropped: {}, reconnecting", self.peer, e);
+                    self.connect(endpoint, session)?;
+                }
+                r => return r,
+            }
+        }
+        self.replayed
+            .pop_front()
+            .map_or_else(|| self.read_stream(), Ok)
+    }
+}
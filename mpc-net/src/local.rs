@@ -0,0 +1,413 @@
+// All parties of a session running as threads of one process, connected by channels.
+// Frames and statistics are the same as with MultiNetwork, so protocols can be tested with
+// cargo test instead of launching a process per party.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    mpsc::{channel, Receiver, Sender},
+    Mutex,
+};
+use std::time::Instant;
+
+use ark_std::{end_timer, start_timer};
+
+use super::frame::{self, Header, Kind, HEADER_LEN};
+use super::{MpcNetwork, NetError, Stats};
+
+/// One party of an in-process session
+pub struct LocalNetwork {
+    id: usize,
+    /// Channels to every party, including an unused one to ourselves
+    senders: Vec<Sender<Vec<u8>>>,
+    /// Channels from every party, including an unused one from ourselves
+    receivers: Vec<Mutex<Receiver<Vec<u8>>>>,
+    session: u64,
+    /// Number of operations done in the session so far
+    round: AtomicU64,
+    /// Number of direct messages sent to and received from every party
+    sent: Vec<AtomicU64>,
+    received: Vec<AtomicU64>,
+    stats: Mutex<Stats>,
+}
+
+impl LocalNetwork {
+    /// Connects n parties to each other and returns them in order
+    pub fn new_simulator(n: usize) -> Vec<Self> {
+        // channels[from][to]
+        let mut senders: Vec<Vec<Sender<Vec<u8>>>> = vec![Vec::with_capacity(n); n];
+        let mut receivers: Vec<Vec<Mutex<Receiver<Vec<u8>>>>> =
+            (0..n).map(|_| Vec::with_capacity(n)).collect();
+        for senders in senders.iter_mut() {
+            for receivers in receivers.iter_mut() {
+                let (sender, receiver) = channel();
+                senders.push(sender);
+                receivers.push(Mutex::new(receiver));
+            }
+        }
+
+        let session = frame::new_session();
+        senders
+            .into_iter()
+            .zip(receivers)
+            .enumerate()
+            .map(|(id, (senders, receivers))| LocalNetwork {
+                id,
+                senders,
+                receivers,
+                session,
+                round: AtomicU64::new(0),
+                sent: (0..n).map(|_| AtomicU64::new(0)).collect(),
+                received: (0..n).map(|_| AtomicU64::new(0)).collect(),
+                stats: Mutex::new(Stats::default()),
+            })
+            .collect()
+    }
+
+    /// Returns the round of the next operation
+    fn next_round(&self) -> u64 {
+        self.round.fetch_add(1, Ordering::SeqCst)
+    }
+
+    fn send(&self, to: usize, round: u64, kind: Kind, bytes: &[u8]) -> Result<(), NetError> {
+        let header = Header::new(self.session, round, kind, bytes);
+        self.senders[to]
+            .send(frame::encode(&header, bytes))
+            .map_err(|_| NetError::Disconnected { peer: to })
+    }
+
+    fn recv(&self, from: usize, round: u64, kind: Kind) -> Result<Vec<u8>, NetError> {
+        let frame = self.receivers[from]
+            .lock()
+            .expect("Poisoned LocalNetwork")
+            .recv()
+            .map_err(|_| NetError::Disconnected { peer: from })?;
+        frame::decode(frame, self.session, round, kind, from)
+    }
+
+    /// Receives a message that must be m bytes long
+    fn recv_exact(
+        &self,
+        from: usize,
+        round: u64,
+        kind: Kind,
+        m: usize,
+    ) -> Result<Vec<u8>, NetError> {
+        frame::check_len(self.recv(from, round, kind)?, m, from)
+    }
+
+    /// Sends bytes_out[i] to every party i and receives from all of them in one round
+    fn exchange(&self, kind: Kind, bytes_out: &[&[u8]]) -> Result<Vec<Vec<u8>>, NetError> {
+        let n = self.n_parties();
+        let start = Instant::now();
+        let round = self.next_round();
+
+        // Channels are unbounded, so everyone sends before receiving
+        for to in (0..n).filter(|to| *to != self.id) {
+            self.send(to, round, kind, bytes_out[to])?;
+        }
+        let r: Vec<Vec<u8>> = (0..n)
+            .map(|from| {
+                if from == self.id {
+                    Ok(bytes_out[from].to_vec())
+                } else {
+                    self.recv(from, round, kind)
+                }
+            })
+            .collect::<Result<_, _>>()?;
+
+        let traffic: Vec<_> = (0..n)
+            .filter(|id| *id != self.id)
+            .map(|id| (id, HEADER_LEN + bytes_out[id].len(), HEADER_LEN + r[id].len()))
+            .collect();
+        self.stats.lock().expect("Poisoned LocalNetwork").record(kind, start, &traffic);
+        Ok(r)
+    }
+}
+
+/// Runs f for every party of a fresh session on its own thread.
+/// Returns the outputs in the order of the parties.
+pub fn simulate<T, F>(n: usize, f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(&LocalNetwork) -> T + Sync,
+{
+    let nets = LocalNetwork::new_simulator(n);
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = nets
+            .iter()
+            .map(|net| {
+                let f = &f;
+                scope.spawn(move || f(net))
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("party panicked"))
+            .collect()
+    })
+}
+
+impl MpcNetwork for LocalNetwork {
+    #[inline]
+    fn party_id(&self) -> usize {
+        self.id
+    }
+
+    #[inline]
+    fn n_parties(&self) -> usize {
+        self.senders.len()
+    }
+
+    fn reset_stats(&self) {
+        *self.stats.lock().expect("Poisoned LocalNetwork") = Stats::default();
+    }
+
+    fn stats(&self) -> Stats {
+        self.stats.lock().expect("Poisoned LocalNetwork").clone()
+    }
+
+    fn broadcast_bytes(&self, bytes_out: &[u8]) -> Result<Vec<Vec<u8>>, NetError> {
+        let timer = start_timer!(|| format!("Broadcast {}", bytes_out.len()));
+        let r = self.exchange(Kind::Broadcast, &vec![bytes_out; self.n_parties()]);
+        end_timer!(timer);
+        r
+    }
+
+    fn all_to_all_bytes(&self, bytes_out: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>, NetError> {
+        let timer = start_timer!(|| "All to all");
+        assert_eq!(bytes_out.len(), self.n_parties());
+        let bytes_out: Vec<&[u8]> = bytes_out.iter().map(|bytes| &bytes[..]).collect();
+        let r = self.exchange(Kind::AllToAll, &bytes_out);
+        end_timer!(timer);
+        r
+    }
+
+    fn send_bytes_to(&self, party: usize, bytes_out: &[u8]) -> Result<(), NetError> {
+        assert_ne!(party, self.id, "cannot send to ourselves");
+        let start = Instant::now();
+        let round = self.sent[party].fetch_add(1, Ordering::SeqCst);
+        self.send(party, round, Kind::Direct, bytes_out)?;
+        let traffic = [(party, HEADER_LEN + bytes_out.len(), 0)];
+        self.stats.lock().expect("Poisoned LocalNetwork").record(Kind::Direct, start, &traffic);
+        Ok(())
+    }
+
+    fn recv_bytes_from(&self, party: usize) -> Result<Vec<u8>, NetError> {
+        assert_ne!(party, self.id, "cannot receive from ourselves");
+        let start = Instant::now();
+        let round = self.received[party].fetch_add(1, Ordering::SeqCst);
+        let bytes_in = self.recv(party, round, Kind::Direct)?;
+        let traffic = [(party, 0, HEADER_LEN + bytes_in.len())];
+        self.stats.lock().expect("Poisoned LocalNetwork").record(Kind::Direct, start, &traffic);
+        Ok(bytes_in)
+    }
+
+    fn gather_bytes(
+        &self,
+        king: usize,
+        bytes_out: &[u8],
+    ) -> Result<Option<Vec<Vec<u8>>>, NetError> {
+        let timer = start_timer!(|| format!("To king {}", bytes_out.len()));
+        let start = Instant::now();
+        let n = self.n_parties();
+        let m = bytes_out.len();
+        let round = self.next_round();
+        let r = if self.id == king {
+            (0..n)
+                .map(|from| {
+                    if from == self.id {
+                        Ok(bytes_out.to_vec())
+                    } else {
+                        self.recv_exact(from, round, Kind::ToKing, m)
+                    }
+                })
+                .collect::<Result<_, _>>()
+                .map(Some)
+        } else {
+            self.send(king, round, Kind::ToKing, bytes_out).map(|_| None)
+        };
+        if r.is_ok() {
+            let traffic: Vec<_> = if self.id == king {
+                let others = (0..n).filter(|id| *id != self.id);
+                others.map(|id| (id, 0, HEADER_LEN + m)).collect()
+            } else {
+                vec![(king, HEADER_LEN + m, 0)]
+            };
+            let mut stats = self.stats.lock().expect("Poisoned LocalNetwork");
+            stats.record(Kind::ToKing, start, &traffic);
+        }
+        end_timer!(timer);
+        r
+    }
+
+    fn scatter_bytes(
+        &self,
+        king: usize,
+        bytes_out: Option<Vec<Vec<u8>>>,
+    ) -> Result<Vec<u8>, NetError> {
+        let start = Instant::now();
+        let round = self.next_round();
+        if self.id == king {
+            let bytes_out = bytes_out.unwrap();
+            let m = bytes_out[0].len();
+            let timer = start_timer!(|| format!("From king {}", m));
+            for (to, bytes) in bytes_out.iter().enumerate().filter(|p| p.0 != self.id) {
+                assert_eq!(bytes.len(), m);
+                self.send(to, round, Kind::FromKing, bytes)?;
+            }
+            let others = (0..self.n_parties()).filter(|id| *id != self.id);
+            let traffic: Vec<_> = others.map(|id| (id, HEADER_LEN + m, 0)).collect();
+            let mut stats = self.stats.lock().expect("Poisoned LocalNetwork");
+            stats.record(Kind::FromKing, start, &traffic);
+            end_timer!(timer);
+            Ok(bytes_out[self.id].clone())
+        } else {
+            let bytes_in = self.recv(king, round, Kind::FromKing)?;
+            let traffic = [(king, 0, HEADER_LEN + bytes_in.len())];
+            let mut stats = self.stats.lock().expect("Poisoned LocalNetwork");
+            stats.record(Kind::FromKing, start, &traffic);
+            Ok(bytes_in)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Kings;
+
+    const N: usize = 4;
+
+    #[test]
+    fn broadcast_test() {
+        let outputs = simulate(N, |net| net.broadcast_bytes(&[net.party_id() as u8; 3]).unwrap());
+        for received in outputs {
+            let expected: Vec<Vec<u8>> = (0..N).map(|id| vec![id as u8; 3]).collect();
+            assert_eq!(received, expected);
+        }
+    }
+
+    #[test]
+    fn king_compute_test() {
+        // King sends every party the sum of all the inputs plus its id
+        let outputs = simulate(N, |net| {
+            net.king_compute(&[net.party_id() as u8 + 1], |inputs| {
+                let sum: u8 = inputs.iter().map(|bytes| bytes[0]).sum();
+                (0..N).map(|id| vec![sum + id as u8]).collect()
+            })
+            .unwrap()
+        });
+        let sum = (1..=N as u8).sum::<u8>();
+        for (id, output) in outputs.into_iter().enumerate() {
+            assert_eq!(output, vec![sum + id as u8]);
+        }
+    }
+
+    #[test]
+    fn other_king_test() {
+        // Party 2 plays king for this call, so it is the only one to get the inputs
+        let outputs = simulate(N, |net| {
+            let net = net.with_kings(Kings::One(2));
+            let inputs = net.send_bytes_to_king(&[net.party_id() as u8]).unwrap();
+            assert_eq!(inputs.is_some(), net.am_king());
+            net.recv_bytes_from_king(inputs.map(|inputs| inputs.into_iter().rev().collect()))
+                .unwrap()
+        });
+        for (id, output) in outputs.into_iter().enumerate() {
+            assert_eq!(output, vec![(N - 1 - id) as u8]);
+        }
+    }
+
+    #[test]
+    fn variable_broadcast_test() {
+        let outputs = simulate(N, |net| net.broadcast_bytes(&vec![0u8; net.party_id()]).unwrap());
+        for received in outputs {
+            let expected: Vec<Vec<u8>> = (0..N).map(|id| vec![0u8; id]).collect();
+            assert_eq!(received, expected);
+        }
+    }
+
+    #[test]
+    fn all_to_all_test() {
+        // Party i sends [i, j] to party j
+        let outputs = simulate(N, |net| {
+            let id = net.party_id() as u8;
+            net.all_to_all_bytes((0..N as u8).map(|to| vec![id, to]).collect())
+                .unwrap()
+        });
+        for (id, received) in outputs.into_iter().enumerate() {
+            let expected: Vec<Vec<u8>> = (0..N as u8).map(|from| vec![from, id as u8]).collect();
+            assert_eq!(received, expected);
+        }
+    }
+
+    #[test]
+    fn direct_test() {
+        // A ring where every party sends its id to the next one, twice
+        let outputs = simulate(N, |net| {
+            let id = net.party_id();
+            let (next, prev) = ((id + 1) % N, (id + N - 1) % N);
+            net.send_bytes_to(next, &[id as u8]).unwrap();
+            net.send_bytes_to(next, &[id as u8; 2]).unwrap();
+            (net.recv_bytes_from(prev).unwrap(), net.recv_bytes_from(prev).unwrap())
+        });
+        for (id, (first, second)) in outputs.into_iter().enumerate() {
+            let prev = ((id + N - 1) % N) as u8;
+            assert_eq!(first, vec![prev]);
+            assert_eq!(second, vec![prev; 2]);
+        }
+    }
+
+    #[test]
+    fn malformed_frame_test() {
+        let outputs = simulate(N, |net| net.send_bytes_to_king(&vec![0u8; net.party_id() + 1]));
+        // King gets a message of the wrong length from party 1 first
+        assert!(matches!(outputs[0], Err(NetError::MalformedFrame { peer: 1, .. })));
+        assert!(outputs[1..].iter().all(|output| output.is_ok()));
+    }
+
+    #[test]
+    fn desync_test() {
+        // King broadcasts while everyone else already sends to king
+        let outputs = simulate(N, |net| {
+            if net.am_king() {
+                net.broadcast_bytes(&[0u8]).map(|_| ())
+            } else {
+                net.send_bytes_to_king(&[0u8]).map(|_| ())
+            }
+        });
+        assert!(matches!(outputs[0], Err(NetError::Desync { peer: 1, .. })));
+        assert!(outputs[1..].iter().all(|output| output.is_ok()));
+    }
+
+    #[test]
+    fn stats_test() {
+        let stats = simulate(N, |net| {
+            let _label = crate::label("test");
+            net.broadcast_bytes(&[0u8; 2]).unwrap();
+            net.king_compute(&[0u8; 5], |inputs| inputs).unwrap();
+            let stats = net.stats();
+            net.reset_stats();
+            assert_eq!(net.stats().broadcasts, 0);
+            stats
+        });
+
+        // Every message carries a header
+        let (broadcast, to_king) = (HEADER_LEN + 2, HEADER_LEN + 5);
+        assert_eq!(stats[0].bytes_sent, (N - 1) * broadcast + (N - 1) * to_king);
+        assert_eq!(stats[0].bytes_recv, (N - 1) * broadcast + (N - 1) * to_king);
+        for stats in stats[1..].iter() {
+            assert_eq!(stats.bytes_sent, (N - 1) * broadcast + to_king);
+            assert_eq!(stats.bytes_recv, (N - 1) * broadcast + to_king);
+        }
+        assert!(stats
+            .iter()
+            .all(|s| s.broadcasts == 1 && s.to_king == 1 && s.from_king == 1));
+        for stats in stats.iter() {
+            let peers = stats.peers.iter();
+            assert_eq!(peers.map(|p| p.bytes_sent).sum::<usize>(), stats.bytes_sent);
+            assert_eq!(stats.labels["test"].calls, 3);
+            assert_eq!(stats.rounds.len(), 3);
+        }
+    }
+}
@@ -1,14 +1,15 @@
 use lazy_static::lazy_static;
-use log::debug;
 use rayon::prelude::*;
-use std::fs::File;
-use std::io::{BufRead, BufReader, Read, Write};
-use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::convert::TryInto;
 use std::sync::Mutex;
+use std::time::Instant;
 
 use ark_std::{end_timer, start_timer};
 
-use super::{MpcNet, Stats};
+use super::config::HostConfig;
+use super::frame::{self, Header, Kind, HEADER_LEN};
+use super::link::{Endpoint, Link, ACK_LEN};
+use super::{MpcNet, MpcNetwork, NetError, Stats};
 
 lazy_static! {
     static ref CONNECTIONS: Mutex<Connections> = Mutex::new(Connections::default());
@@ -23,226 +24,235 @@ macro_rules! get_ch {
 
 #[derive(Debug)]
 struct Peer {
-    _id: usize,
-    addr: SocketAddr,
-    stream: Option<TcpStream>,
+    link: Link,
+    /// Number of direct messages sent to and received from the peer
+    sent: u64,
+    received: u64,
 }
 
 #[derive(Default, Debug)]
 struct Connections {
     id: usize,
     peers: Vec<Peer>,
+    endpoint: Endpoint,
+    /// Picked by king once everyone is connected, zero before
+    session: u64,
+    /// Number of operations done in the session so far
+    round: u64,
     stats: Stats,
 }
 
-impl std::default::Default for Peer {
-    fn default() -> Self {
-        Self {
-            _id: 0,
-            addr: "127.0.0.1:8000".parse().unwrap(),
-            stream: None,
-        }
-    }
-}
+/// Bytes sent with every message on top of its payload
+const OVERHEAD: usize = ACK_LEN + HEADER_LEN;
 
 impl Connections {
-    /// Given a path and the `id` of oneself, initialize the structure
-    fn init_from_path(&mut self, path: &str, id: usize) {
-        let f = BufReader::new(File::open(path).expect("host configuration path"));
-        let mut peer_id = 0;
-        for line in f.lines() {
-            let line = line.unwrap();
-            let trimmed = line.trim();
-            if trimmed.len() > 0 {
-                let addr: SocketAddr = trimmed
-                    .parse()
-                    .unwrap_or_else(|e| panic!("bad socket address: {}:\n{}", trimmed, e));
-                let peer = Peer {
-                    _id: peer_id,
-                    addr,
-                    stream: None,
-                };
-                self.peers.push(peer);
-                peer_id += 1;
-            }
+    /// Given a host configuration and the `id` of oneself, initialize the structure.
+    fn init_from_config(&mut self, config: HostConfig, id: usize) -> Result<(), NetError> {
+        if id >= config.n_parties() {
+            return Err(NetError::Config {
+                reason: format!("party {} is not among the {} parties", id, config.n_parties()),
+            });
         }
-        assert!(id < self.peers.len());
+        self.peers = (0..config.n_parties())
+            .map(|peer| Peer {
+                link: Link::new(peer),
+                sent: 0,
+                received: 0,
+            })
+            .collect();
         self.id = id;
+        self.endpoint.id = id;
+        self.endpoint.config = config;
+        Ok(())
     }
-    fn connect_to_all(&mut self) {
+    fn connect_to_all(&mut self) -> Result<(), NetError> {
         let timer = start_timer!(|| "Connecting");
-        let n = self.peers.len();
-        for from_id in 0..n {
-            for to_id in (from_id + 1)..n {
-                debug!("{} to {}", from_id, to_id);
-                if self.id == from_id {
-                    let to_addr = self.peers[to_id].addr;
-                    debug!("Contacting {}", to_id);
-                    let stream = loop {
-                        let mut ms_waited = 0;
-                        match TcpStream::connect(to_addr) {
-                            Ok(s) => break s,
-                            Err(e) => match e.kind() {
-                                std::io::ErrorKind::ConnectionRefused
-                                | std::io::ErrorKind::ConnectionReset => {
-                                    ms_waited += 10;
-                                    std::thread::sleep(std::time::Duration::from_millis(10));
-                                    if ms_waited % 3_000 == 0 {
-                                        debug!("Still waiting");
-                                    } else if ms_waited > 30_000 {
-                                        panic!("Could not find peer in 30s");
-                                    }
-                                }
-                                _ => {
-                                    panic!("Error during FieldChannel::new: {}", e);
-                                }
-                            },
-                        }
-                    };
-                    stream.set_nodelay(true).unwrap();
-                    self.peers[to_id].stream = Some(stream);
-                } else if self.id == to_id {
-                    debug!("Awaiting {}", from_id);
-                    let listener = TcpListener::bind(self.peers[self.id].addr).unwrap();
-                    let (stream, _addr) = listener.accept().unwrap();
-                    stream.set_nodelay(true).unwrap();
-                    self.peers[from_id].stream = Some(stream);
-                }
-            }
-            // Sender for next round waits for note from this sender to prevent race on receipt.
-            if from_id + 1 < n {
-                if self.id == from_id {
-                    self.peers[self.id + 1]
-                        .stream
-                        .as_mut()
-                        .unwrap()
-                        .write_all(&[0u8])
-                        .unwrap();
-                } else if self.id == from_id + 1 {
-                    self.peers[self.id - 1]
-                        .stream
-                        .as_mut()
-                        .unwrap()
-                        .read_exact(&mut [0u8])
-                        .unwrap();
-                }
-            }
-        }
-        // Do a round with the king, to be sure everyone is ready
-        let from_all = self.send_to_king(&[self.id as u8]);
-        self.recv_from_king(from_all);
-        for id in 0..n {
+        // One listener for the whole session, so dropped connections can come back
+        self.endpoint.listen()?;
+        // Everyone connects in increasing order of peer, so nobody waits on a party that is
+        // waiting on someone else
+        for id in 0..self.peers.len() {
             if id != self.id {
-                assert!(self.peers[id].stream.is_some());
+                self.peers[id].link.connect(&self.endpoint, 0)?;
             }
         }
+        // Do a round with the king, to be sure everyone is ready, and agree on the session
+        let from_all = self.send_to_king(0, &[self.id as u8])?;
+        let session = from_all.map(|from_all| {
+            vec![frame::new_session().to_le_bytes().to_vec(); from_all.len()]
+        });
+        let session = frame::check_len(self.recv_from_king(0, session)?, 8, 0)?;
+        self.session = u64::from_le_bytes(session[..].try_into().unwrap());
+        self.round = 0;
         end_timer!(timer);
+        Ok(())
     }
-    fn am_king(&self) -> bool {
-        self.id == 0
+    /// Returns the round of the next operation
+    fn next_round(&mut self) -> u64 {
+        self.round += 1;
+        self.round - 1
     }
-    fn broadcast(&mut self, bytes_out: &[u8]) -> Vec<Vec<u8>> {
-        let timer = start_timer!(|| format!("Broadcast {}", bytes_out.len()));
-        let m = bytes_out.len();
+    /// Sends bytes_out[i] to every party i and receives from all of them in one round
+    fn exchange(&mut self, kind: Kind, bytes_out: &[&[u8]]) -> Result<Vec<Vec<u8>>, NetError> {
+        let start = Instant::now();
         let own_id = self.id;
-        self.stats.bytes_sent += (self.peers.len() - 1) * m;
-        self.stats.bytes_recv += (self.peers.len() - 1) * m;
-        self.stats.broadcasts += 1;
-        let r = self
+        let (session, round) = (self.session, self.next_round());
+        let endpoint = &self.endpoint;
+        let r: Vec<Vec<u8>> = self
             .peers
             .par_iter_mut()
             .enumerate()
             .map(|(id, peer)| {
-                let mut bytes_in = vec![0u8; m];
-                if id < own_id {
-                    let stream = peer.stream.as_mut().unwrap();
-                    stream.read_exact(&mut bytes_in[..]).unwrap();
-                    stream.write_all(bytes_out).unwrap();
-                } else if id == own_id {
-                    bytes_in.copy_from_slice(bytes_out);
+                if id == own_id {
+                    return Ok(bytes_out[id].to_vec());
+                }
+                let link = &mut peer.link;
+                let header = Header::new(session, round, kind, bytes_out[id]);
+                let frame_out = frame::encode(&header, bytes_out[id]);
+                // The lower id writes first
+                let frame_in = if id < own_id {
+                    let frame_in = link.read(endpoint, session)?;
+                    link.write(endpoint, session, frame_out)?;
+                    frame_in
                 } else {
-                    let stream = peer.stream.as_mut().unwrap();
-                    stream.write_all(bytes_out).unwrap();
-                    stream.read_exact(&mut bytes_in[..]).unwrap();
+                    link.write(endpoint, session, frame_out)?;
+                    link.read(endpoint, session)?
                 };
-                bytes_in
+                frame::decode(frame_in, session, round, kind, id)
             })
+            .collect::<Result<_, _>>()?;
+        let traffic: Vec<_> = (0..r.len())
+            .filter(|id| *id != own_id)
+            .map(|id| (id, OVERHEAD + bytes_out[id].len(), OVERHEAD + r[id].len()))
             .collect();
+        self.stats.record(kind, start, &traffic);
+        Ok(r)
+    }
+    fn broadcast(&mut self, bytes_out: &[u8]) -> Result<Vec<Vec<u8>>, NetError> {
+        let timer = start_timer!(|| format!("Broadcast {}", bytes_out.len()));
+        let r = self.exchange(Kind::Broadcast, &vec![bytes_out; self.peers.len()]);
         end_timer!(timer);
         r
     }
-    fn send_to_king(&mut self, bytes_out: &[u8]) -> Option<Vec<Vec<u8>>> {
+    fn all_to_all(&mut self, bytes_out: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>, NetError> {
+        let timer = start_timer!(|| "All to all");
+        assert_eq!(bytes_out.len(), self.peers.len());
+        let bytes_out: Vec<&[u8]> = bytes_out.iter().map(|bytes| &bytes[..]).collect();
+        let r = self.exchange(Kind::AllToAll, &bytes_out);
+        end_timer!(timer);
+        r
+    }
+    fn send_to(&mut self, id: usize, bytes_out: &[u8]) -> Result<(), NetError> {
+        assert_ne!(id, self.id, "cannot send to ourselves");
+        let start = Instant::now();
+        let session = self.session;
+        let peer = &mut self.peers[id];
+        let header = Header::new(session, peer.sent, Kind::Direct, bytes_out);
+        peer.sent += 1;
+        peer.link
+            .write(&self.endpoint, session, frame::encode(&header, bytes_out))?;
+        let traffic = [(id, OVERHEAD + bytes_out.len(), 0)];
+        self.stats.record(Kind::Direct, start, &traffic);
+        Ok(())
+    }
+    fn recv_from(&mut self, id: usize) -> Result<Vec<u8>, NetError> {
+        assert_ne!(id, self.id, "cannot receive from ourselves");
+        let start = Instant::now();
+        let session = self.session;
+        let peer = &mut self.peers[id];
+        let round = peer.received;
+        peer.received += 1;
+        let frame_in = peer.link.read(&self.endpoint, session)?;
+        let bytes_in = frame::decode(frame_in, session, round, Kind::Direct, id)?;
+        let traffic = [(id, 0, OVERHEAD + bytes_in.len())];
+        self.stats.record(Kind::Direct, start, &traffic);
+        Ok(bytes_in)
+    }
+    fn send_to_king(
+        &mut self,
+        king: usize,
+        bytes_out: &[u8],
+    ) -> Result<Option<Vec<Vec<u8>>>, NetError> {
         let timer = start_timer!(|| format!("To king {}", bytes_out.len()));
+        let start = Instant::now();
         let m = bytes_out.len();
         let own_id = self.id;
-        self.stats.to_king += 1;
-        let r = if self.am_king() {
-            self.stats.bytes_recv += (self.peers.len() - 1) * m;
-            Some(
-                self.peers
-                    .par_iter_mut()
-                    .enumerate()
-                    .map(|(id, peer)| {
-                        let mut bytes_in = vec![0u8; m];
-                        if id == own_id {
-                            bytes_in.copy_from_slice(bytes_out);
-                        } else {
-                            let stream = peer.stream.as_mut().unwrap();
-                            stream.read_exact(&mut bytes_in[..]).unwrap();
-                        };
-                        bytes_in
-                    })
-                    .collect(),
-            )
+        let (session, round) = (self.session, self.next_round());
+        let endpoint = &self.endpoint;
+        let r = if own_id == king {
+            self.peers
+                .par_iter_mut()
+                .enumerate()
+                .map(|(id, peer)| {
+                    if id == own_id {
+                        return Ok(bytes_out.to_vec());
+                    }
+                    let frame_in = peer.link.read(endpoint, session)?;
+                    let bytes_in = frame::decode(frame_in, session, round, Kind::ToKing, id)?;
+                    frame::check_len(bytes_in, m, id)
+                })
+                .collect::<Result<_, _>>()
+                .map(Some)
         } else {
-            self.stats.bytes_sent += m;
-            self.peers[0]
-                .stream
-                .as_mut()
-                .unwrap()
-                .write_all(bytes_out)
-                .unwrap();
-            None
+            let header = Header::new(session, round, Kind::ToKing, bytes_out);
+            self.peers[king]
+                .link
+                .write(endpoint, session, frame::encode(&header, bytes_out))
+                .map(|_| None)
         };
+        if r.is_ok() {
+            let traffic: Vec<_> = if own_id == king {
+                let others = (0..self.peers.len()).filter(|id| *id != own_id);
+                others.map(|id| (id, 0, OVERHEAD + m)).collect()
+            } else {
+                vec![(king, OVERHEAD + m, 0)]
+            };
+            self.stats.record(Kind::ToKing, start, &traffic);
+        }
         end_timer!(timer);
         r
     }
-    fn recv_from_king(&mut self, bytes_out: Option<Vec<Vec<u8>>>) -> Vec<u8> {
+    fn recv_from_king(
+        &mut self,
+        king: usize,
+        bytes_out: Option<Vec<Vec<u8>>>,
+    ) -> Result<Vec<u8>, NetError> {
+        let start = Instant::now();
         let own_id = self.id;
-        self.stats.from_king += 1;
-        if self.am_king() {
+        let (session, round) = (self.session, self.next_round());
+        let endpoint = &self.endpoint;
+        if own_id == king {
             let bytes_out = bytes_out.unwrap();
             let m = bytes_out[0].len();
             let timer = start_timer!(|| format!("From king {}", m));
-            let bytes_size = (m as u64).to_le_bytes();
-            self.stats.bytes_sent += (self.peers.len() - 1) * (m + 8);
             self.peers
                 .par_iter_mut()
                 .enumerate()
                 .filter(|p| p.0 != own_id)
-                .for_each(|(id, peer)| {
-                    let stream = peer.stream.as_mut().unwrap();
+                .try_for_each(|(id, peer)| {
                     assert_eq!(bytes_out[id].len(), m);
-                    stream.write_all(&bytes_size).unwrap();
-                    stream.write_all(&bytes_out[id]).unwrap();
-                });
+                    let header = Header::new(session, round, Kind::FromKing, &bytes_out[id]);
+                    peer.link
+                        .write(endpoint, session, frame::encode(&header, &bytes_out[id]))
+                })?;
+            let others = (0..self.peers.len()).filter(|id| *id != own_id);
+            let traffic: Vec<_> = others.map(|id| (id, OVERHEAD + m, 0)).collect();
+            self.stats.record(Kind::FromKing, start, &traffic);
             end_timer!(timer);
-            bytes_out[own_id].clone()
+            Ok(bytes_out[own_id].clone())
         } else {
-            let stream = self.peers[0].stream.as_mut().unwrap();
-            let mut bytes_size = [0u8; 8];
-            stream.read_exact(&mut bytes_size).unwrap();
-            let m = u64::from_le_bytes(bytes_size) as usize;
-            self.stats.bytes_recv += m;
-            let mut bytes_in = vec![0u8; m];
-            stream.read_exact(&mut bytes_in).unwrap();
-            bytes_in
+            let frame_in = self.peers[king].link.read(endpoint, session)?;
+            let bytes_in = frame::decode(frame_in, session, round, Kind::FromKing, king)?;
+            let traffic = [(king, 0, OVERHEAD + bytes_in.len())];
+            self.stats.record(Kind::FromKing, start, &traffic);
+            Ok(bytes_in)
         }
     }
     fn uninit(&mut self) {
         for p in &mut self.peers {
-            p.stream = None;
+            p.link.close();
         }
+        self.endpoint.close();
     }
 }
 
@@ -260,19 +270,15 @@ impl MpcNet for MpcMultiNet {
     }
 
     #[inline]
-    fn init_from_file(path: &str, party_id: usize) {
+    fn init_from_file(path: &str, party_id: usize) -> Result<(), NetError> {
         let mut ch = get_ch!();
-        ch.init_from_path(path, party_id);
-        ch.connect_to_all();
+        ch.init_from_config(HostConfig::from_file(path)?, party_id)?;
+        ch.connect_to_all()
     }
 
     #[inline]
     fn is_init() -> bool {
-        get_ch!()
-            .peers
-            .first()
-            .map(|p| p.stream.is_some())
-            .unwrap_or(false)
+        get_ch!().endpoint.is_listening()
     }
 
     #[inline]
@@ -291,17 +297,314 @@ impl MpcNet for MpcMultiNet {
     }
 
     #[inline]
-    fn broadcast_bytes(bytes: &[u8]) -> Vec<Vec<u8>> {
+    fn broadcast_bytes(bytes: &[u8]) -> Result<Vec<Vec<u8>>, NetError> {
         get_ch!().broadcast(bytes)
     }
 
     #[inline]
-    fn send_bytes_to_king(bytes: &[u8]) -> Option<Vec<Vec<u8>>> {
-        get_ch!().send_to_king(bytes)
+    fn all_to_all_bytes(bytes: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>, NetError> {
+        get_ch!().all_to_all(bytes)
+    }
+
+    #[inline]
+    fn send_bytes_to(party: usize, bytes: &[u8]) -> Result<(), NetError> {
+        get_ch!().send_to(party, bytes)
+    }
+
+    #[inline]
+    fn recv_bytes_from(party: usize) -> Result<Vec<u8>, NetError> {
+        get_ch!().recv_from(party)
+    }
+
+    #[inline]
+    fn send_bytes_to_king(bytes: &[u8]) -> Result<Option<Vec<Vec<u8>>>, NetError> {
+        get_ch!().send_to_king(0, bytes)
+    }
+
+    #[inline]
+    fn recv_bytes_from_king(bytes: Option<Vec<Vec<u8>>>) -> Result<Vec<u8>, NetError> {
+        get_ch!().recv_from_king(0, bytes)
+    }
+}
+
+/// The global session of MpcMultiNet, for code written against MpcNetwork
+impl MpcNetwork for MpcMultiNet {
+    #[inline]
+    fn party_id(&self) -> usize {
+        <Self as MpcNet>::party_id()
+    }
+
+    #[inline]
+    fn n_parties(&self) -> usize {
+        <Self as MpcNet>::n_parties()
+    }
+
+    #[inline]
+    fn reset_stats(&self) {
+        <Self as MpcNet>::reset_stats()
+    }
+
+    #[inline]
+    fn stats(&self) -> Stats {
+        <Self as MpcNet>::stats()
+    }
+
+    #[inline]
+    fn broadcast_bytes(&self, bytes: &[u8]) -> Result<Vec<Vec<u8>>, NetError> {
+        <Self as MpcNet>::broadcast_bytes(bytes)
+    }
+
+    #[inline]
+    fn all_to_all_bytes(&self, bytes: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>, NetError> {
+        <Self as MpcNet>::all_to_all_bytes(bytes)
+    }
+
+    #[inline]
+    fn send_bytes_to(&self, party: usize, bytes: &[u8]) -> Result<(), NetError> {
+        <Self as MpcNet>::send_bytes_to(party, bytes)
+    }
+
+    #[inline]
+    fn recv_bytes_from(&self, party: usize) -> Result<Vec<u8>, NetError> {
+        <Self as MpcNet>::recv_bytes_from(party)
+    }
+
+    #[inline]
+    fn gather_bytes(&self, king: usize, bytes: &[u8]) -> Result<Option<Vec<Vec<u8>>>, NetError> {
+        get_ch!().send_to_king(king, bytes)
+    }
+
+    #[inline]
+    fn scatter_bytes(
+        &self,
+        king: usize,
+        bytes: Option<Vec<Vec<u8>>>,
+    ) -> Result<Vec<u8>, NetError> {
+        get_ch!().recv_from_king(king, bytes)
+    }
+}
+
+/// One session over TCP, connections are closed when it is dropped
+pub struct MultiNetwork {
+    connections: Mutex<Connections>,
+}
+
+impl MultiNetwork {
+    /// Connects to all the parties listed in the file, see HostConfig::from_file
+    pub fn from_file(path: &str, party_id: usize) -> Result<Self, NetError> {
+        Self::from_config(HostConfig::from_file(path)?, party_id, None)
+    }
+
+    /// Connects to all the parties listed in the file, which has the public key of every party.
+    /// Every connection is encrypted and a peer that can't prove it holds the private key of
+    /// its party id is rejected.
+    pub fn from_file_with_key(
+        path: &str,
+        party_id: usize,
+        private_key: Vec<u8>,
+    ) -> Result<Self, NetError> {
+        Self::from_config(HostConfig::from_file(path)?, party_id, Some(private_key))
+    }
+
+    /// Connects to all the parties of config, encrypted if there is a private key.
+    /// Fails with NetError::Desync if a party loaded another configuration.
+    pub fn from_config(
+        config: HostConfig,
+        party_id: usize,
+        private_key: Option<Vec<u8>>,
+    ) -> Result<Self, NetError> {
+        if private_key.is_some() && config.parties.iter().any(|p| p.public_key.is_none()) {
+            return Err(NetError::Config {
+                reason: "a private key was given but the host configuration has no public keys"
+                    .to_string(),
+            });
+        }
+        let mut connections = Connections::default();
+        connections.init_from_config(config, party_id)?;
+        connections.endpoint.private_key = private_key;
+        connections.connect_to_all()?;
+        Ok(MultiNetwork {
+            connections: Mutex::new(connections),
+        })
+    }
+
+    /// The host configuration of the session, with the resource hints of every party
+    pub fn config(&self) -> HostConfig {
+        let connections = self.connections.lock().expect("Poisoned MultiNetwork");
+        connections.endpoint.config.clone()
+    }
+}
+
+/// Macro for locking the connections of a session in the current scope.
+macro_rules! get_session {
+    ($net:expr) => {
+        $net.connections.lock().expect("Poisoned MultiNetwork")
+    };
+}
+
+impl MpcNetwork for MultiNetwork {
+    #[inline]
+    fn party_id(&self) -> usize {
+        get_session!(self).id
+    }
+
+    #[inline]
+    fn n_parties(&self) -> usize {
+        get_session!(self).peers.len()
+    }
+
+    #[inline]
+    fn reset_stats(&self) {
+        get_session!(self).stats = Stats::default();
+    }
+
+    #[inline]
+    fn stats(&self) -> Stats {
+        get_session!(self).stats.clone()
+    }
+
+    #[inline]
+    fn broadcast_bytes(&self, bytes: &[u8]) -> Result<Vec<Vec<u8>>, NetError> {
+        get_session!(self).broadcast(bytes)
+    }
+
+    #[inline]
+    fn all_to_all_bytes(&self, bytes: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>, NetError> {
+        get_session!(self).all_to_all(bytes)
     }
 
     #[inline]
-    fn recv_bytes_from_king(bytes: Option<Vec<Vec<u8>>>) -> Vec<u8> {
-        get_ch!().recv_from_king(bytes)
+    fn send_bytes_to(&self, party: usize, bytes: &[u8]) -> Result<(), NetError> {
+        get_session!(self).send_to(party, bytes)
+    }
+
+    #[inline]
+    fn recv_bytes_from(&self, party: usize) -> Result<Vec<u8>, NetError> {
+        get_session!(self).recv_from(party)
+    }
+
+    #[inline]
+    fn gather_bytes(&self, king: usize, bytes: &[u8]) -> Result<Option<Vec<Vec<u8>>>, NetError> {
+        get_session!(self).send_to_king(king, bytes)
+    }
+
+    #[inline]
+    fn scatter_bytes(
+        &self,
+        king: usize,
+        bytes: Option<Vec<Vec<u8>>>,
+    ) -> Result<Vec<u8>, NetError> {
+        get_session!(self).recv_from_king(king, bytes)
+    }
+}
+
+impl Drop for MultiNetwork {
+    fn drop(&mut self) {
+        get_session!(self).uninit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const N: usize = 3;
+
+    /// Runs f at N parties connected over localhost, each in its own thread pool
+    fn simulate<T: Send + 'static>(
+        port: u16,
+        f: impl Fn(&MultiNetwork) -> T + Send + Sync + Copy + 'static,
+    ) -> Vec<T> {
+        let path = std::env::temp_dir().join(format!("mpc-net-hosts-{}", port));
+        let hosts: String = (0..N)
+            .map(|id| format!("127.0.0.1:{}\n", port + id as u16))
+            .collect();
+        std::fs::write(&path, hosts).unwrap();
+        let parties: Vec<_> = (0..N)
+            .map(|id| {
+                let path = path.to_str().unwrap().to_owned();
+                std::thread::spawn(move || {
+                    let pool = rayon::ThreadPoolBuilder::new().num_threads(N).build().unwrap();
+                    pool.install(|| f(&MultiNetwork::from_file(&path, id).unwrap()))
+                })
+            })
+            .collect();
+        let outputs = parties.into_iter().map(|p| p.join().unwrap()).collect();
+        std::fs::remove_file(path).unwrap();
+        outputs
+    }
+
+    #[test]
+    fn resume_test() {
+        let outputs = simulate(18310, |net| {
+            let id = net.party_id();
+            let mut received = net.broadcast_bytes(&[id as u8]).unwrap();
+            // Party 1 drops its connection to 2, and later party 0 the one to 1 while 1 is
+            // sending to it, the session goes on over new connections
+            if id == 1 {
+                get_session!(net).peers[2].link.close();
+            }
+            received.extend(net.broadcast_bytes(&[id as u8 + 10]).unwrap());
+            if id == 0 {
+                get_session!(net).peers[1].link.close();
+            }
+            let outs = (0..N).map(|to| vec![id as u8, to as u8]).collect();
+            received.extend(net.all_to_all_bytes(outs).unwrap());
+            if id == 1 {
+                net.send_bytes_to(0, &[42]).unwrap();
+            } else if id == 0 {
+                received.push(net.recv_bytes_from(1).unwrap());
+            }
+            received
+        });
+        for (id, received) in outputs.into_iter().enumerate() {
+            let mut expected: Vec<Vec<u8>> = (0..N as u8).map(|from| vec![from]).collect();
+            expected.extend((0..N as u8).map(|from| vec![from + 10]));
+            expected.extend((0..N as u8).map(|from| vec![from, id as u8]));
+            if id == 0 {
+                expected.push(vec![42]);
+            }
+            assert_eq!(received, expected);
+        }
+    }
+
+    #[test]
+    fn config_test() {
+        // Both parties bind every interface and are reached by name
+        let config = |port: u16, threads: usize| {
+            HostConfig::parse_toml(&format!(
+                "[[party]]\nid = 0\nlisten = \"0.0.0.0:{0}\"\nadvertise = \"localhost:{0}\"\n\
+                 [[party]]\nid = 1\nlisten = \"0.0.0.0:{1}\"\nadvertise = \"localhost:{1}\"\n\
+                 [party.resources]\nthreads = {2}\n",
+                port,
+                port + 1,
+                threads
+            ))
+            .unwrap()
+        };
+        let connect = |port: u16, threads: [usize; 2]| {
+            let parties: Vec<_> = (0..2)
+                .map(|id| {
+                    std::thread::spawn(move || {
+                        MultiNetwork::from_config(config(port, threads[id]), id, None)
+                            .map(|net| net.config())
+                    })
+                })
+                .collect();
+            parties.into_iter().map(|p| p.join().unwrap()).collect::<Vec<_>>()
+        };
+
+        for config in connect(18340, [32, 32]) {
+            assert_eq!(config.unwrap().parties[1].resources["threads"], "32");
+        }
+        // Party 1 loaded another configuration
+        for result in connect(18342, [32, 64]) {
+            assert!(matches!(result, Err(NetError::Desync { .. })));
+        }
+        // Neither reaches the network
+        let net = MultiNetwork::from_config(config(18344, 32), 2, None);
+        assert!(matches!(net, Err(NetError::Config { .. })));
+        let net = MultiNetwork::from_config(config(18344, 32), 0, Some(vec![0; 32]));
+        assert!(matches!(net, Err(NetError::Config { .. })));
     }
 }
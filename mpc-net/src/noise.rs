@@ -0,0 +1,250 @@
+// Authenticated and encrypted connections between parties with the Noise KK handshake.
+// Every party has a static X25519 key and knows the public keys of all the others from the host
+// configuration, so both ends of a connection prove their identity before any share is sent.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+
+use snow::{params::NoiseParams, Builder, TransportState};
+
+use super::NetError;
+
+/// Both static keys are known in advance, the handshake authenticates both of them
+const PATTERN: &str = "Noise_KK_25519_ChaChaPoly_BLAKE2s";
+/// Largest Noise message
+const MAX_MESSAGE: usize = 65535;
+/// Size of the authentication tag of every message
+const TAG_LEN: usize = 16;
+
+fn params() -> NoiseParams {
+    PATTERN.parse().expect("valid noise pattern")
+}
+
+fn to_io(e: snow::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// Static key of a party
+pub struct Keypair {
+    pub private: Vec<u8>,
+    pub public: Vec<u8>,
+}
+
+impl Keypair {
+    pub fn generate() -> Self {
+        let keypair = Builder::new(params())
+            .generate_keypair()
+            .expect("key generation should not fail");
+        Keypair {
+            private: keypair.private,
+            public: keypair.public,
+        }
+    }
+}
+
+/// Parses a hex encoded key
+pub fn parse_key(hex_key: &str) -> Result<Vec<u8>, String> {
+    let key = hex::decode(hex_key.trim()).map_err(|e| format!("bad key: {}", e))?;
+    if key.len() != 32 {
+        return Err(format!("keys must be 32 bytes, got {}", key.len()));
+    }
+    Ok(key)
+}
+
+/// Reads a hex encoded private key from a file
+pub fn read_private_key<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, NetError> {
+    let path = path.as_ref();
+    let config = |reason: String| NetError::Config {
+        reason: format!("private key {}: {}", path.display(), reason),
+    };
+    let text = fs::read_to_string(path).map_err(|e| config(e.to_string()))?;
+    parse_key(&text).map_err(config)
+}
+
+fn write_frame(stream: &mut TcpStream, message: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(2 + message.len());
+    frame.extend_from_slice(&(message.len() as u16).to_be_bytes());
+    frame.extend_from_slice(message);
+    stream.write_all(&frame)
+}
+
+fn read_frame(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len)?;
+    let mut message = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut message)?;
+    Ok(message)
+}
+
+/// A connection whose bytes are encrypted and authenticated
+pub(crate) struct NoiseStream {
+    stream: TcpStream,
+    transport: TransportState,
+    /// Decrypted bytes that were not read yet
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl NoiseStream {
+    /// Runs the handshake as the party that opened the connection.
+    /// Fails unless the other end holds the private key of remote_public.
+    pub(crate) fn initiate(
+        mut stream: TcpStream,
+        private: &[u8],
+        remote_public: &[u8],
+    ) -> io::Result<Self> {
+        let mut handshake = Builder::new(params())
+            .local_private_key(private)
+            .remote_public_key(remote_public)
+            .build_initiator()
+            .map_err(to_io)?;
+        let mut message = vec![0u8; MAX_MESSAGE];
+
+        // -> e, es, ss
+        let len = handshake.write_message(&[], &mut message).map_err(to_io)?;
+        write_frame(&mut stream, &message[..len])?;
+        // <- e, ee, se
+        handshake
+            .read_message(&read_frame(&mut stream)?, &mut message)
+            .map_err(to_io)?;
+
+        Ok(NoiseStream {
+            stream,
+            transport: handshake.into_transport_mode().map_err(to_io)?,
+            buffer: Vec::new(),
+            position: 0,
+        })
+    }
+
+    /// Runs the handshake as the party that accepted the connection.
+    /// Fails unless the other end holds the private key of remote_public.
+    pub(crate) fn respond(
+        mut stream: TcpStream,
+        private: &[u8],
+        remote_public: &[u8],
+    ) -> io::Result<Self> {
+        let mut handshake = Builder::new(params())
+            .local_private_key(private)
+            .remote_public_key(remote_public)
+            .build_responder()
+            .map_err(to_io)?;
+        let mut message = vec![0u8; MAX_MESSAGE];
+
+        handshake
+            .read_message(&read_frame(&mut stream)?, &mut message)
+            .map_err(to_io)?;
+        let len = handshake.write_message(&[], &mut message).map_err(to_io)?;
+        write_frame(&mut stream, &message[..len])?;
+
+        Ok(NoiseStream {
+            stream,
+            transport: handshake.into_transport_mode().map_err(to_io)?,
+            buffer: Vec::new(),
+            position: 0,
+        })
+    }
+}
+
+impl Read for NoiseStream {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.buffer.len() {
+            let message = read_frame(&mut self.stream)?;
+            self.buffer.resize(message.len(), 0);
+            let len = self
+                .transport
+                .read_message(&message, &mut self.buffer)
+                .map_err(to_io)?;
+            self.buffer.truncate(len);
+            self.position = 0;
+        }
+        let len = out.len().min(self.buffer.len() - self.position);
+        out[..len].copy_from_slice(&self.buffer[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+impl Write for NoiseStream {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        // Empty messages would be read as the end of the stream
+        let len = bytes.len().min(MAX_MESSAGE - TAG_LEN);
+        if len == 0 {
+            return Ok(0);
+        }
+        let mut message = vec![0u8; len + TAG_LEN];
+        let message_len = self
+            .transport
+            .write_message(&bytes[..len], &mut message)
+            .map_err(to_io)?;
+        write_frame(&mut self.stream, &message[..message_len])?;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl fmt::Debug for NoiseStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NoiseStream")
+            .field("stream", &self.stream)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// Connects two streams over localhost, the accepting side runs on its own thread
+    fn handshake(
+        initiator: &Keypair,
+        responder: &Keypair,
+        expected_initiator: &[u8],
+    ) -> (io::Result<NoiseStream>, io::Result<NoiseStream>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::scope(|scope| {
+            let accepted = scope.spawn(|| {
+                let (stream, _) = listener.accept().unwrap();
+                NoiseStream::respond(stream, &responder.private, expected_initiator)
+            });
+            let stream = TcpStream::connect(addr).unwrap();
+            let connected = NoiseStream::initiate(stream, &initiator.private, &responder.public);
+            (connected, accepted.join().unwrap())
+        })
+    }
+
+    #[test]
+    fn roundtrip_test() {
+        let (a, b) = (Keypair::generate(), Keypair::generate());
+        let (a_stream, b_stream) = handshake(&a, &b, &a.public);
+        let (mut a_stream, mut b_stream) = (a_stream.unwrap(), b_stream.unwrap());
+
+        // Longer than a single Noise message
+        let bytes: Vec<u8> = (0..3 * MAX_MESSAGE).map(|i| i as u8).collect();
+        std::thread::scope(|scope| {
+            scope.spawn(|| a_stream.write_all(&bytes).unwrap());
+            let mut received = vec![0u8; bytes.len()];
+            b_stream.read_exact(&mut received).unwrap();
+            assert_eq!(received, bytes);
+        });
+        b_stream.write_all(&[7u8]).unwrap();
+        let mut received = [0u8];
+        a_stream.read_exact(&mut received).unwrap();
+        assert_eq!(received, [7u8]);
+    }
+
+    #[test]
+    fn impersonation_test() {
+        let (a, b, mallory) = (Keypair::generate(), Keypair::generate(), Keypair::generate());
+        // Mallory claims to be a, but b only accepts the key of a
+        let (_, b_stream) = handshake(&mallory, &b, &a.public);
+        assert!(b_stream.is_err());
+    }
+}
@@ -0,0 +1,338 @@
+// Traffic and timing of the network calls. Calls are grouped by the label of the code that made
+// them, set with label(..) for as long as the returned guard lives. Labels nest, the label of a
+// call is the labels in force joined by ':', e.g. "dfft:fft2". Labels belong to the thread that
+// sets them, calls made from the threads of a rayon pool are unlabeled unless labeled there.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+
+use super::frame::Kind;
+
+thread_local! {
+    static LABELS: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Labels the network calls of this thread until it is dropped, see label
+#[must_use = "the label only lasts as long as the guard"]
+pub struct Label {
+    // Labels are per thread, so the guard has to stay on its thread
+    _thread: PhantomData<*const ()>,
+}
+
+/// Labels the network calls of this thread with name, nested in the labels already in force
+pub fn label(name: &'static str) -> Label {
+    LABELS.with(|labels| labels.borrow_mut().push(name));
+    Label {
+        _thread: PhantomData,
+    }
+}
+
+impl Drop for Label {
+    fn drop(&mut self) {
+        LABELS.with(|labels| labels.borrow_mut().pop());
+    }
+}
+
+/// The label of the calls this thread makes now, empty if there is none
+pub fn current_label() -> String {
+    LABELS.with(|labels| labels.borrow().join(":"))
+}
+
+/// Latencies, bucket i counts the calls that took less than 2^i microseconds and at least
+/// 2^(i-1) microseconds
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Histogram {
+    pub buckets: Vec<usize>,
+}
+
+impl Histogram {
+    pub fn add(&mut self, time: Duration) {
+        let micros = time.as_micros().min(u64::MAX as u128) as u64;
+        let bucket = (64 - micros.leading_zeros()) as usize;
+        if self.buckets.len() <= bucket {
+            self.buckets.resize(bucket + 1, 0);
+        }
+        self.buckets[bucket] += 1;
+    }
+
+    pub fn count(&self) -> usize {
+        self.buckets.iter().sum()
+    }
+
+    /// A bound on the time within which a fraction q of the calls finished
+    pub fn quantile(&self, q: f64) -> Duration {
+        let target = ((q * self.count() as f64).ceil() as usize).max(1);
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return Duration::from_micros(1 << bucket);
+            }
+        }
+        Duration::from_micros(1 << self.buckets.len())
+    }
+}
+
+/// Traffic with one party, headers included
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PeerStats {
+    pub bytes_sent: usize,
+    pub bytes_recv: usize,
+    pub messages_sent: usize,
+    pub messages_recv: usize,
+}
+
+/// Totals of the calls with one label
+#[derive(Clone, Debug, Default)]
+pub struct LabelStats {
+    pub calls: usize,
+    pub bytes_sent: usize,
+    pub bytes_recv: usize,
+    /// Wall-clock time spent in the calls
+    pub time: Duration,
+    pub latency: Histogram,
+}
+
+/// One call of the network
+#[derive(Clone, Debug)]
+pub struct Round {
+    pub label: String,
+    pub kind: Kind,
+    /// When the call started, from the start of the first call since the last reset
+    pub start: Duration,
+    pub time: Duration,
+    pub bytes_sent: usize,
+    pub bytes_recv: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    pub bytes_sent: usize,
+    pub bytes_recv: usize,
+    pub broadcasts: usize,
+    pub to_king: usize,
+    pub from_king: usize,
+    /// Messages sent to a single party
+    pub direct: usize,
+    pub all_to_all: usize,
+    /// Traffic with every party, by party id
+    pub peers: Vec<PeerStats>,
+    /// Totals by label, calls without a label are under ""
+    pub labels: BTreeMap<String, LabelStats>,
+    /// Every call since the last reset, in the order they finished
+    pub rounds: Vec<Round>,
+    origin: Option<Instant>,
+}
+
+impl Stats {
+    /// Accounts a call of this thread that started at start.
+    /// traffic has the bytes sent to and received from every party of the call, headers included.
+    pub(crate) fn record(&mut self, kind: Kind, start: Instant, traffic: &[(usize, usize, usize)]) {
+        self.record_as(current_label(), kind, start, traffic)
+    }
+
+    /// Accounts a call with the given label, for calls that finish on another thread
+    pub(crate) fn record_as(
+        &mut self,
+        label: String,
+        kind: Kind,
+        start: Instant,
+        traffic: &[(usize, usize, usize)],
+    ) {
+        let time = start.elapsed();
+        let origin = *self.origin.get_or_insert(start);
+        let (mut bytes_sent, mut bytes_recv) = (0, 0);
+        for &(party, sent, recv) in traffic {
+            if self.peers.len() <= party {
+                self.peers.resize(party + 1, PeerStats::default());
+            }
+            let peer = &mut self.peers[party];
+            peer.bytes_sent += sent;
+            peer.bytes_recv += recv;
+            peer.messages_sent += usize::from(sent > 0);
+            peer.messages_recv += usize::from(recv > 0);
+            bytes_sent += sent;
+            bytes_recv += recv;
+        }
+        self.bytes_sent += bytes_sent;
+        self.bytes_recv += bytes_recv;
+        match kind {
+            Kind::Broadcast => self.broadcasts += 1,
+            Kind::ToKing => self.to_king += 1,
+            Kind::FromKing => self.from_king += 1,
+            Kind::AllToAll => self.all_to_all += 1,
+            Kind::Direct if bytes_sent > 0 => self.direct += 1,
+            Kind::Direct => {}
+        }
+
+        let totals = self.labels.entry(label.clone()).or_default();
+        totals.calls += 1;
+        totals.bytes_sent += bytes_sent;
+        totals.bytes_recv += bytes_recv;
+        totals.time += time;
+        totals.latency.add(time);
+        self.rounds.push(Round {
+            label,
+            kind,
+            start: start.saturating_duration_since(origin),
+            time,
+            bytes_sent,
+            bytes_recv,
+        });
+    }
+
+    /// All the statistics as a JSON object, times in microseconds
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        let _ = write!(
+            json,
+            "{{\"bytes_sent\":{},\"bytes_recv\":{},\"broadcasts\":{},\"to_king\":{},\
+             \"from_king\":{},\"direct\":{},\"all_to_all\":{},\"peers\":[",
+            self.bytes_sent,
+            self.bytes_recv,
+            self.broadcasts,
+            self.to_king,
+            self.from_king,
+            self.direct,
+            self.all_to_all
+        );
+        for (i, peer) in self.peers.iter().enumerate() {
+            let _ = write!(
+                json,
+                "{}{{\"bytes_sent\":{},\"bytes_recv\":{},\"messages_sent\":{},\
+                 \"messages_recv\":{}}}",
+                if i > 0 { "," } else { "" },
+                peer.bytes_sent,
+                peer.bytes_recv,
+                peer.messages_sent,
+                peer.messages_recv
+            );
+        }
+        json.push_str("],\"labels\":{");
+        for (i, (label, totals)) in self.labels.iter().enumerate() {
+            let _ = write!(
+                json,
+                "{}{}:{{\"calls\":{},\"bytes_sent\":{},\"bytes_recv\":{},\"time_us\":{},\
+                 \"latency_us\":{{\"p50\":{},\"p90\":{},\"p99\":{}}},\"latency_buckets\":{:?}}}",
+                if i > 0 { "," } else { "" },
+                json_string(label),
+                totals.calls,
+                totals.bytes_sent,
+                totals.bytes_recv,
+                totals.time.as_micros(),
+                totals.latency.quantile(0.5).as_micros(),
+                totals.latency.quantile(0.9).as_micros(),
+                totals.latency.quantile(0.99).as_micros(),
+                totals.latency.buckets
+            );
+        }
+        json.push_str("},\"rounds\":[");
+        for (i, round) in self.rounds.iter().enumerate() {
+            let _ = write!(
+                json,
+                "{}{{\"label\":{},\"kind\":\"{}\",\"start_us\":{},\"time_us\":{},\
+                 \"bytes_sent\":{},\"bytes_recv\":{}}}",
+                if i > 0 { "," } else { "" },
+                json_string(&round.label),
+                round.kind.name(),
+                round.start.as_micros(),
+                round.time.as_micros(),
+                round.bytes_sent,
+                round.bytes_recv
+            );
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+/// Totals and a line per label, see to_json for everything
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "sent {} B, received {} B, {} broadcasts, {} to king, {} from king, {} direct, \
+             {} all to all",
+            self.bytes_sent,
+            self.bytes_recv,
+            self.broadcasts,
+            self.to_king,
+            self.from_king,
+            self.direct,
+            self.all_to_all
+        )?;
+        for (label, totals) in &self.labels {
+            writeln!(
+                f,
+                "  {:<24} {:>6} calls {:>12?} {:>14} B sent {:>14} B received, p50 {:?} p99 {:?}",
+                if label.is_empty() { "(unlabeled)" } else { label },
+                totals.calls,
+                totals.time,
+                totals.bytes_sent,
+                totals.bytes_recv,
+                totals.latency.quantile(0.5),
+                totals.latency.quantile(0.99)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn label_test() {
+        let mut stats = Stats::default();
+        let start = Instant::now();
+        {
+            let _dfft = label("dfft");
+            let _fft2 = label("fft2");
+            stats.record(Kind::ToKing, start, &[(0, 0, 10), (2, 0, 10)]);
+        }
+        let _dmsm = label("dmsm");
+        stats.record(Kind::FromKing, start, &[(2, 5, 0)]);
+        stats.record(Kind::Direct, start, &[(1, 0, 7)]);
+
+        assert_eq!((stats.bytes_sent, stats.bytes_recv), (5, 27));
+        assert_eq!((stats.to_king, stats.from_king, stats.direct), (1, 1, 0));
+        assert_eq!(stats.peers[2].bytes_recv, 10);
+        assert_eq!((stats.peers[2].messages_sent, stats.peers[2].messages_recv), (1, 1));
+        assert_eq!(stats.labels["dfft:fft2"].bytes_recv, 20);
+        assert_eq!(stats.labels["dmsm"].calls, 2);
+        assert_eq!(stats.rounds.len(), 3);
+        assert!(stats.to_json().contains("\"dfft:fft2\":{\"calls\":1,"));
+    }
+
+    #[test]
+    fn histogram_test() {
+        let mut latency = Histogram::default();
+        for micros in [0, 1, 3, 3, 900] {
+            latency.add(Duration::from_micros(micros));
+        }
+        assert_eq!(latency.buckets, vec![1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(latency.quantile(0.5), Duration::from_micros(4));
+        assert_eq!(latency.quantile(1.0), Duration::from_micros(1024));
+    }
+}
@@ -1,13 +1,15 @@
 use lazy_static::lazy_static;
 use log::debug;
-use std::fs::File;
-use std::io::{BufRead, BufReader, Read, Write};
+use std::io::{Read, Write};
 use std::net::{SocketAddr, TcpListener, TcpStream};
 use std::sync::Mutex;
+use std::time::Instant;
 
 use ark_std::{end_timer, start_timer};
 
-use super::{MpcNet, Stats};
+use super::config::HostConfig;
+use super::frame::Kind;
+use super::{MpcNet, NetError, Stats};
 
 lazy_static! {
     pub static ref CH: Mutex<FieldChannel> = Mutex::new(FieldChannel::default());
@@ -20,6 +22,9 @@ macro_rules! get_ch {
     };
 }
 
+/// Every message is preceded by its length
+const LEN_PREFIX: usize = 8;
+
 pub struct FieldChannel {
     /// Empty if unitialized
     pub stream: Option<TcpStream>,
@@ -43,33 +48,37 @@ impl std::default::Default for FieldChannel {
 }
 
 impl FieldChannel {
-    fn init_from_path(&mut self, path: &str, id: usize) {
-        let f = BufReader::new(File::open(path).expect("host configuration path"));
-        let mut addrs = Vec::new();
-        for line in f.lines() {
-            let line = line.unwrap();
-            let trimmed = line.trim();
-            if trimmed.len() > 0 {
-                let addr: SocketAddr = trimmed
-                    .parse()
-                    .unwrap_or_else(|e| panic!("bad socket address: {}:\n{}", trimmed, e));
-                addrs.push(addr);
-            }
-        }
-        assert_eq!(addrs.len(), 2);
-        assert!(id < addrs.len());
-        self.self_addr = addrs[id];
-        self.other_addr = addrs[1 - id];
+    fn init_from_path(&mut self, path: &str, id: usize) -> Result<(), NetError> {
+        let config = HostConfig::from_file(path)?;
+        assert_eq!(config.n_parties(), 2);
+        assert!(id < 2);
+        let other = &config.parties[1 - id];
+        self.self_addr = config.parties[id].listen;
+        self.other_addr = other.advertise_addr().map_err(|e| NetError::Config {
+            reason: format!("bad socket address: {}: {}", other.advertise, e),
+        })?;
         self.talk_first = id == 0;
+        Ok(())
     }
 
+    /// Party id of the other end
     #[inline]
-    pub fn connect(&mut self) {
+    fn peer(&self) -> usize {
+        if self.talk_first {
+            1
+        } else {
+            0
+        }
+    }
+
+    #[inline]
+    pub fn connect(&mut self) -> Result<(), NetError> {
         debug!("I am {}, connecting to {}", self.self_addr, self.other_addr);
-        self.stream = Some(if self.talk_first {
+        let peer = self.peer();
+        let stream = if self.talk_first {
             debug!("Attempting to contact peer");
+            let mut ms_waited = 0;
             loop {
-                let mut ms_waited = 0;
                 match TcpStream::connect(self.other_addr) {
                     Ok(s) => break s,
                     Err(e) => {
@@ -79,23 +88,28 @@ impl FieldChannel {
                             if ms_waited % 3_000 == 0 {
                                 debug!("Still waiting");
                             } else if ms_waited > 30_000 {
-                                panic!("Could not find peer in 30s");
+                                return Err(NetError::Timeout { peer });
                             }
                         } else {
-                            panic!("Error during FieldChannel::new: {}", e);
+                            return Err(NetError::io(peer, e));
                         }
                     }
                 }
             }
         } else {
-            let listener = TcpListener::bind(self.self_addr).unwrap();
+            let listener =
+                TcpListener::bind(self.self_addr).map_err(|e| NetError::io(1 - peer, e))?;
             debug!("Waiting for peer to contact us");
-            let (stream, _addr) = listener.accept().unwrap();
+            let (stream, _addr) = listener.accept().map_err(|e| NetError::io(peer, e))?;
             stream
-        });
+        };
         // disable nagle's alg
-        self.stream.as_mut().unwrap().set_nodelay(true).unwrap();
-        self.stream.as_mut().unwrap().set_nonblocking(true).unwrap();
+        stream
+            .set_nodelay(true)
+            .and_then(|_| stream.set_nonblocking(true))
+            .map_err(|e| NetError::io(peer, e))?;
+        self.stream = Some(stream);
+        Ok(())
     }
     #[inline]
     pub fn stream(&mut self) -> &mut TcpStream {
@@ -105,32 +119,38 @@ impl FieldChannel {
     }
 
     #[inline]
-    pub fn send_slice(&mut self, v: &[u8]) {
+    pub fn send_slice(&mut self, v: &[u8]) -> Result<(), NetError> {
+        let peer = self.peer();
         let s = self.stream();
-        s.set_nonblocking(false).unwrap();
         let bytes = (v.len() as u64).to_ne_bytes();
-        s.write_all(&bytes[..]).unwrap();
-        s.write_all(v).unwrap();
-        s.set_nonblocking(true).unwrap();
-        self.stats.bytes_sent += bytes.len() + v.len();
+        s.set_nonblocking(false)
+            .and_then(|_| s.write_all(&bytes[..]))
+            .and_then(|_| s.write_all(v))
+            .and_then(|_| s.set_nonblocking(true))
+            .map_err(|e| NetError::io(peer, e))?;
+        Ok(())
     }
 
     #[inline]
-    pub fn recv_vec(&mut self) -> Vec<u8> {
+    pub fn recv_vec(&mut self) -> Result<Vec<u8>, NetError> {
+        let peer = self.peer();
         let s = self.stream();
         let mut len = [0u8; 8];
-        s.set_nonblocking(false).unwrap();
-        s.read_exact(&mut len[..]).unwrap();
+        s.set_nonblocking(false)
+            .and_then(|_| s.read_exact(&mut len[..]))
+            .map_err(|e| NetError::io(peer, e))?;
         let mut bytes = vec![0u8; u64::from_ne_bytes(len) as usize];
-        s.read_exact(&mut bytes[..]).unwrap();
-        s.set_nonblocking(true).unwrap();
-        self.stats.bytes_recv += bytes.len() + len.len();
-        bytes
+        s.read_exact(&mut bytes[..])
+            .and_then(|_| s.set_nonblocking(true))
+            .map_err(|e| NetError::io(peer, e))?;
+        Ok(bytes)
     }
 
     #[inline]
     pub fn exchange_bytes(&mut self, bytes_out: &[u8]) -> std::io::Result<Vec<u8>> {
         let timer = start_timer!(|| format!("Exchanging {}", bytes_out.len()));
+        let start = Instant::now();
+        let peer = self.peer();
         let s = self.stream();
         let n = bytes_out.len();
         let mut bytes_in = vec![0u8; n];
@@ -167,13 +187,24 @@ impl FieldChannel {
                 }
             }
         }
-        self.stats.broadcasts += 1;
-        self.stats.bytes_sent += n;
-        self.stats.bytes_recv += n;
+        self.stats.record(Kind::Broadcast, start, &[(peer, n, n)]);
         end_timer!(timer);
         Ok(bytes_in)
     }
 
+    /// Sends bytes_out and receives the message of the other party, which can have another length
+    #[inline]
+    pub fn swap(&mut self, bytes_out: &[u8]) -> Result<Vec<u8>, NetError> {
+        if self.talk_first {
+            self.send_slice(bytes_out)?;
+            self.recv_vec()
+        } else {
+            let bytes_in = self.recv_vec()?;
+            self.send_slice(bytes_out)?;
+            Ok(bytes_in)
+        }
+    }
+
     #[inline]
     pub fn stats(&self) -> Stats {
         self.stats.clone()
@@ -187,15 +218,16 @@ impl FieldChannel {
 
 #[inline]
 /// Initialize the MPC
-pub fn init_from_path(path: &str, id: usize) {
+pub fn init_from_path(path: &str, id: usize) -> Result<(), NetError> {
     let mut ch = get_ch!();
     assert!(
         ch.stream.is_none(),
         "FieldChannel should no be re-intialized. Did you call init(..) twice?"
     );
-    ch.init_from_path(path, id);
-    ch.connect();
+    ch.init_from_path(path, id)?;
+    ch.connect()?;
     debug!("Connected");
+    Ok(())
 }
 
 #[inline]
@@ -254,8 +286,8 @@ impl MpcNet for MpcTwoNet {
     }
 
     #[inline]
-    fn init_from_file(path: &str, party_id: usize) {
-        get_ch!().init_from_path(path, party_id);
+    fn init_from_file(path: &str, party_id: usize) -> Result<(), NetError> {
+        get_ch!().init_from_path(path, party_id)
     }
 
     #[inline]
@@ -279,40 +311,92 @@ impl MpcNet for MpcTwoNet {
     }
 
     #[inline]
-    fn broadcast_bytes(bytes: &[u8]) -> Vec<Vec<u8>> {
-        let other = get_ch!().exchange_bytes(bytes).unwrap();
-        if Self::am_king() {
-            vec![bytes.to_vec(), other]
+    fn broadcast_bytes(bytes: &[u8]) -> Result<Vec<Vec<u8>>, NetError> {
+        let mut ch = get_ch!();
+        let start = Instant::now();
+        let other = ch.swap(bytes)?;
+        let traffic = [(ch.peer(), LEN_PREFIX + bytes.len(), LEN_PREFIX + other.len())];
+        ch.stats.record(Kind::Broadcast, start, &traffic);
+        if ch.talk_first {
+            Ok(vec![bytes.to_vec(), other])
         } else {
-            vec![other, bytes.to_vec()]
+            Ok(vec![other, bytes.to_vec()])
         }
     }
 
     #[inline]
-    fn send_bytes_to_king(bytes: &[u8]) -> Option<Vec<Vec<u8>>> {
+    fn all_to_all_bytes(mut bytes: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>, NetError> {
+        assert_eq!(bytes.len(), 2);
+        let mut ch = get_ch!();
+        let start = Instant::now();
+        let peer = ch.peer();
+        let sent = LEN_PREFIX + bytes[peer].len();
+        bytes[peer] = ch.swap(&bytes[peer])?;
+        let traffic = [(peer, sent, LEN_PREFIX + bytes[peer].len())];
+        ch.stats.record(Kind::AllToAll, start, &traffic);
+        Ok(bytes)
+    }
+
+    #[inline]
+    fn send_bytes_to(party: usize, bytes: &[u8]) -> Result<(), NetError> {
         let mut ch = get_ch!();
-        ch.stats.to_king += 1;
+        assert_eq!(party, ch.peer(), "cannot send to ourselves");
+        let start = Instant::now();
+        ch.send_slice(bytes)?;
+        let traffic = [(party, LEN_PREFIX + bytes.len(), 0)];
+        ch.stats.record(Kind::Direct, start, &traffic);
+        Ok(())
+    }
+
+    #[inline]
+    fn recv_bytes_from(party: usize) -> Result<Vec<u8>, NetError> {
+        let mut ch = get_ch!();
+        assert_eq!(party, ch.peer(), "cannot receive from ourselves");
+        let start = Instant::now();
+        let bytes = ch.recv_vec()?;
+        let traffic = [(party, 0, LEN_PREFIX + bytes.len())];
+        ch.stats.record(Kind::Direct, start, &traffic);
+        Ok(bytes)
+    }
+
+    #[inline]
+    fn send_bytes_to_king(bytes: &[u8]) -> Result<Option<Vec<Vec<u8>>>, NetError> {
+        let mut ch = get_ch!();
+        let start = Instant::now();
+        let peer = ch.peer();
         if ch.talk_first {
-            let other = ch.recv_vec();
-            debug_assert_eq!(bytes.len(), other.len());
-            Some(vec![bytes.to_vec(), other])
+            let other = ch.recv_vec()?;
+            if bytes.len() != other.len() {
+                return Err(NetError::MalformedFrame {
+                    peer: 1,
+                    reason: format!("expected {} bytes, got {}", bytes.len(), other.len()),
+                });
+            }
+            ch.stats.record(Kind::ToKing, start, &[(peer, 0, LEN_PREFIX + other.len())]);
+            Ok(Some(vec![bytes.to_vec(), other]))
         } else {
-            ch.send_slice(bytes);
-            None
+            ch.send_slice(bytes)?;
+            ch.stats.record(Kind::ToKing, start, &[(peer, LEN_PREFIX + bytes.len(), 0)]);
+            Ok(None)
         }
     }
 
     #[inline]
-    fn recv_bytes_from_king(bytes: Option<Vec<Vec<u8>>>) -> Vec<u8> {
+    fn recv_bytes_from_king(bytes: Option<Vec<Vec<u8>>>) -> Result<Vec<u8>, NetError> {
         let mut ch = get_ch!();
-        ch.stats.from_king += 1;
+        let start = Instant::now();
+        let peer = ch.peer();
         if ch.talk_first {
             let mut bytes = bytes.expect("king needs bytes");
             assert_eq!(bytes.len(), 2);
-            ch.send_slice(&bytes.pop().unwrap());
-            bytes.pop().unwrap()
+            let theirs = bytes.pop().unwrap();
+            ch.send_slice(&theirs)?;
+            ch.stats.record(Kind::FromKing, start, &[(peer, LEN_PREFIX + theirs.len(), 0)]);
+            Ok(bytes.pop().unwrap())
         } else {
-            ch.recv_vec()
+            let bytes = ch.recv_vec()?;
+            ch.stats.record(Kind::FromKing, start, &[(peer, 0, LEN_PREFIX + bytes.len())]);
+            Ok(bytes)
         }
     }
 }
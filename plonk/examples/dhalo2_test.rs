@@ -0,0 +1,214 @@
+use std::marker::PhantomData;
+
+use ark_std::{end_timer, start_timer};
+use dist_primitives::{
+    channel::channel::MpcSerNet,
+    preprocessing::{dealer, store::Store},
+    Opt,
+};
+use ff::Field;
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter, SimpleFloorPlanner},
+    halo2curves::bn256::{Bn256, Fr, G1Affine},
+    plonk::{
+        keygen_pk, keygen_vk, verify_proof, Advice, Circuit, Column, ConstraintSystem, Error,
+        Instance, Selector,
+    },
+    poly::{
+        commitment::ParamsProver,
+        kzg::{
+            commitment::{KZGCommitmentScheme, ParamsKZG},
+            multiopen::VerifierGWC,
+            strategy::SingleStrategy,
+        },
+        Rotation,
+    },
+    transcript::{
+        Blake2bRead, Blake2bWrite, Challenge255, TranscriptReadBuffer, TranscriptWriterBuffer,
+    },
+};
+use log::debug;
+use mpc_net::MpcNetwork;
+use plonk::dhalo2::{
+    dealer::{deal_witness, ShareBundle},
+    error::Error as ProverError,
+    prover::{d_create_proof_from_shares, proof_shape},
+};
+use secret_sharing::pss::PackedSharingParams;
+use structopt::StructOpt;
+
+#[derive(Debug, Clone)]
+struct FiboConfig {
+    advice: [Column<Advice>; 3],
+    instance: Column<Instance>,
+    selector: Selector,
+}
+
+#[derive(Default)]
+struct FiboCircuit<F> {
+    rows: usize,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Circuit<F> for FiboCircuit<F> {
+    type Config = FiboConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            rows: self.rows,
+            _marker: PhantomData,
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> FiboConfig {
+        let advice = [meta.advice_column(), meta.advice_column(), meta.advice_column()];
+        let instance = meta.instance_column();
+        let selector = meta.selector();
+
+        meta.enable_equality(instance);
+        for column in advice.iter() {
+            meta.enable_equality(*column);
+        }
+
+        meta.create_gate("add", |meta| {
+            let s = meta.query_selector(selector);
+            let a = meta.query_advice(advice[0], Rotation::cur());
+            let b = meta.query_advice(advice[1], Rotation::cur());
+            let c = meta.query_advice(advice[2], Rotation::cur());
+            vec![s * (a + b - c)]
+        });
+
+        FiboConfig {
+            advice,
+            instance,
+            selector,
+        }
+    }
+
+    fn synthesize(&self, config: FiboConfig, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+        let out = layouter.assign_region(
+            || "fibonacci",
+            |mut region| {
+                let mut a: AssignedCell<F, F> = region.assign_advice_from_instance(
+                    || "a",
+                    config.instance,
+                    0,
+                    config.advice[0],
+                    0,
+                )?;
+                let mut b: AssignedCell<F, F> = region.assign_advice_from_instance(
+                    || "b",
+                    config.instance,
+                    1,
+                    config.advice[1],
+                    0,
+                )?;
+
+                let mut c = None;
+                for row in 0..self.rows {
+                    config.selector.enable(&mut region, row)?;
+                    if row > 0 {
+                        a = a.copy_advice(|| "a", &mut region, config.advice[0], row)?;
+                        b = b.copy_advice(|| "b", &mut region, config.advice[1], row)?;
+                    }
+                    let out = region.assign_advice(
+                        || "c",
+                        config.advice[2],
+                        row,
+                        || a.value().copied() + b.value().copied(),
+                    )?;
+                    a = b;
+                    b = out.clone();
+                    c = Some(out);
+                }
+                Ok(c.unwrap())
+            },
+        )?;
+
+        layouter.constrain_instance(out.cell(), config.instance, 2)
+    }
+}
+
+fn main() -> Result<(), ProverError> {
+    debug!("Start");
+
+    env_logger::builder().format_timestamp(None).init();
+    let opt = Opt::from_args();
+    let net = opt.network()?;
+
+    let k = (opt.m as f64).log2() as u32;
+    let pp = PackedSharingParams::<Fr>::new(opt.l);
+
+    // Every party derives the same setup from a fixed seed
+    let params = ParamsKZG::<Bn256>::setup(k, ark_std::test_rng());
+    let circuit = FiboCircuit::<Fr> {
+        rows: opt.m / 2,
+        _marker: PhantomData,
+    };
+    let vk = keygen_vk(&params, &circuit).expect("keygen_vk should not fail");
+    let pk = keygen_pk(&params, vk, &circuit).expect("keygen_pk should not fail");
+
+    let mut out = [Fr::ONE, Fr::ONE];
+    for _ in 0..circuit.rows {
+        out = [out[1], out[0] + out[1]];
+    }
+    let instance = [Fr::ONE, Fr::ONE, out[1]];
+
+    // Offline: the shape only depends on the proving key. King plays the trusted dealer and
+    // the witness owner, which is only acceptable in a test
+    let offline_timer = start_timer!(|| "Offline");
+    let stores = if net.am_king() {
+        let shape = proof_shape(&params, &pk, &pp)?;
+        Some(dealer::deal(&shape, &pp, &mut ark_std::test_rng()))
+    } else {
+        None
+    };
+    let mut store: Store<Fr> = net.recv_from_king(stores)?;
+    let bundles = if net.am_king() {
+        Some(deal_witness(&pk, &circuit, &[&instance], &pp, ark_std::test_rng())?)
+    } else {
+        None
+    };
+    let witness: ShareBundle<Fr> = net.recv_from_king(bundles)?;
+    end_timer!(offline_timer);
+
+    // Online: every mask comes from the store
+    net.reset_stats();
+    let online_timer = start_timer!(|| "Online");
+    let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
+    d_create_proof_from_shares(
+        &params,
+        &pk,
+        &witness,
+        &[&instance],
+        ark_std::test_rng(),
+        &mut transcript,
+        &mut store,
+        &net.with_kings(opt.kings()),
+        &pp,
+    )?;
+    let proof = transcript.finalize();
+    end_timer!(online_timer);
+    assert!(store.is_empty(), "the shape of the proof changed");
+
+    if net.am_king() {
+        let mut transcript = Blake2bRead::<_, G1Affine, Challenge255<_>>::init(&proof[..]);
+        let strategy = SingleStrategy::new(&params);
+        verify_proof::<KZGCommitmentScheme<Bn256>, VerifierGWC<'_, Bn256>, _, _, _>(
+            params.verifier_params(),
+            pk.get_vk(),
+            strategy,
+            &[&[&instance]],
+            &mut transcript,
+        )
+        .expect("proof should verify");
+        println!("Proof verified");
+        println!("Stats: {}", net.stats());
+    }
+    opt.write_stats(&net.stats());
+
+    drop(net);
+    debug!("Done");
+    Ok(())
+}
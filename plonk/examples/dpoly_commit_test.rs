@@ -5,7 +5,7 @@ use ff::{PrimeField, WithSmallOrderMulGroup};
 use halo2_proofs::halo2curves::bn256::{Bn256, Fr};
 use halo2_proofs::halo2curves::pairing::Engine;
 use halo2_proofs::poly::EvaluationDomain;
-use mpc_net::{MpcMultiNet as Net, MpcNet};
+use mpc_net::{MpcNetwork, NetError};
 use plonk::dpoly_commit::PackPolyCk;
 use plonk::poly_commit::PolyCk;
 use secret_sharing::pss::PackedSharingParams;
@@ -13,10 +13,12 @@ use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 use structopt::StructOpt;
 
-pub fn d_poly_commit_test<E: Engine>(
+pub fn d_poly_commit_test<E: Engine, N: MpcNetwork>(
     pp: &PackedSharingParams<E::Scalar>,
     dom: &EvaluationDomain<E::Scalar>,
-) where
+    net: &N,
+) -> Result<(), NetError>
+where
     E: Engine + Debug,
     E::Scalar: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
 {
@@ -29,14 +31,14 @@ pub fn d_poly_commit_test<E: Engine>(
     let peval_share: Vec<E::Scalar> = (0..mbyl).map(|i| E::Scalar::from(i as u64)).collect();
 
     let dmsm = start_timer!(|| "Distributed poly_commit");
-    pck.commit(&peval_share, pp);
+    pck.commit(&peval_share, net, pp)?;
     end_timer!(dmsm);
 
     let dmsm = start_timer!(|| "Distributed commit_open");
-    pck.open(&peval_share, E::Scalar::from(123 as u64), dom, pp);
+    pck.open(&peval_share, E::Scalar::from(123 as u64), dom, net, pp)?;
     end_timer!(dmsm);
 
-    if Net::am_king() {
+    if net.am_king() {
         let ck = PolyCk::<E>::new(dom.size(), rng);
         let pevals: Vec<E::Scalar> = (0..dom.size()).map(|i| E::Scalar::from(i as u64)).collect();
         let nmsm = start_timer!(|| "Ark poly_commit");
@@ -46,18 +48,17 @@ pub fn d_poly_commit_test<E: Engine>(
         ck.open(&pevals, E::Scalar::from(123 as u64), dom);
         end_timer!(nmsm);
     }
+    Ok(())
 }
 
-fn main() {
+fn main() -> Result<(), NetError> {
     env_logger::builder().format_timestamp(None).init();
 
     let opt = Opt::from_args();
 
-    Net::init_from_file(opt.input.to_str().unwrap(), opt.id);
+    let net = opt.network()?;
 
     let pp = PackedSharingParams::<Fr>::new(opt.l);
     let dom = EvaluationDomain::<Fr>::new(1, (opt.m as f64).log2() as u32);
-    d_poly_commit_test::<Bn256>(&pp, &dom);
-
-    Net::deinit();
+    d_poly_commit_test::<Bn256, _>(&pp, &dom, &net)
 }
@@ -1,27 +1,29 @@
 use dist_primitives::Opt;
 use log::debug;
-use mpc_net::{MpcMultiNet as Net, MpcNet};
+use mpc_net::{MpcNetwork, NetError};
 use plonk::{dplonk::d_plonk_test, PlonkDomain};
 
 use halo2_proofs::halo2curves::bn256::{Bn256, Fr};
 use secret_sharing::pss::PackedSharingParams;
 use structopt::StructOpt;
 
-fn main() {
+fn main() -> Result<(), NetError> {
     debug!("Start");
 
     env_logger::builder().format_timestamp(None).init();
     let opt = Opt::from_args();
-    Net::init_from_file(opt.input.to_str().unwrap(), opt.id);
+    let net = opt.network()?;
 
     let pd = PlonkDomain::<Fr>::new(opt.m);
     let pp = PackedSharingParams::<Fr>::new(opt.l);
-    d_plonk_test::<Bn256>(&pd, &pp);
+    d_plonk_test::<Bn256, _>(&pd, &net, &pp)?;
 
-    if Net::am_king() {
-        println!("Stats: {:#?}", Net::stats());
+    if net.am_king() {
+        println!("Stats: {}", net.stats());
     }
+    opt.write_stats(&net.stats());
 
-    Net::deinit();
+    drop(net);
     debug!("Done");
+    Ok(())
 }
@@ -0,0 +1,155 @@
+// The witness owner synthesizes the circuit and deals packed shares of the advice columns.
+// Parties only ever see their own bundle and never need the circuit or its witness.
+
+use std::io::{Read, Write};
+
+use dist_primitives::utils::pack::transpose;
+use ff::{FromUniformBytes, PrimeField, WithSmallOrderMulGroup};
+use halo2_proofs::{
+    arithmetic::CurveAffine,
+    plonk::{Circuit, Error, ProvingKey},
+};
+use rand::RngCore;
+use secret_sharing::pss::PackedSharingParams;
+use serde::{Deserialize, Serialize};
+
+use super::witness::synthesize_advice;
+
+/// Packed shares of the witness held by a single party.
+/// Shares are packed in natural order, l consecutive values per share.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ShareBundle<F> {
+    pub party_id: usize,
+    /// Advice columns in Lagrange form, including the blinding rows
+    pub advice_values: Vec<Vec<F>>,
+    /// Advice columns in coefficient form, ready for d_msm and d_coeff_to_extended
+    pub advice_polys: Vec<Vec<F>>,
+}
+
+impl<F: Serialize + for<'de> Deserialize<'de>> ShareBundle<F> {
+    pub fn write<W: Write>(&self, writer: W) -> bincode::Result<()> {
+        bincode::serialize_into(writer, self)
+    }
+
+    pub fn read<R: Read>(reader: R) -> bincode::Result<Self> {
+        bincode::deserialize_from(reader)
+    }
+}
+
+/// Packs a vector with fresh randomness, returns one vector of shares per packed chunk
+fn pack_random<F, R>(values: &[F], pp: &PackedSharingParams<F>, rng: &mut R) -> Vec<Vec<F>>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3>,
+    R: RngCore,
+{
+    values
+        .chunks(pp.l)
+        .map(|chunk| pp.pack(&chunk.to_vec(), rng))
+        .collect()
+}
+
+/// Synthesizes the circuit and returns one share bundle per party.
+/// The unusable rows of every advice column are filled with blinding factors before packing.
+pub fn deal_witness<C, ConcreteCircuit, R>(
+    pk: &ProvingKey<C>,
+    circuit: &ConcreteCircuit,
+    instances: &[&[C::Scalar]],
+    pp: &PackedSharingParams<C::Scalar>,
+    mut rng: R,
+) -> Result<Vec<ShareBundle<C::Scalar>>, Error>
+where
+    C: CurveAffine,
+    C::Scalar: PrimeField + WithSmallOrderMulGroup<3> + FromUniformBytes<64>,
+    ConcreteCircuit: Circuit<C::Scalar>,
+    R: RngCore,
+{
+    let cs = pk.get_vk().cs();
+    let domain = pk.get_vk().get_domain();
+    let n = 1usize << domain.k();
+    let usable_rows = n - (cs.blinding_factors() + 1);
+
+    if instances.len() != cs.num_instance_columns() {
+        return Err(Error::InvalidInstances);
+    }
+    if instances.iter().any(|values| values.len() > usable_rows) {
+        return Err(Error::InstanceTooLarge);
+    }
+
+    let advice_values = synthesize_advice(circuit, cs, domain.k(), instances, usable_rows)?;
+
+    // Shares of every column, indexed by [column][chunk][party]
+    let mut value_shares = Vec::with_capacity(advice_values.len());
+    let mut poly_shares = Vec::with_capacity(advice_values.len());
+    for mut values in advice_values.into_iter() {
+        for value in values[usable_rows..].iter_mut() {
+            *value = C::Scalar::random(&mut rng);
+        }
+        let poly = domain.lagrange_to_coeff(domain.lagrange_from_vec(values.clone()));
+
+        value_shares.push(pack_random(&values, pp, &mut rng));
+        poly_shares.push(pack_random(&poly, pp, &mut rng));
+    }
+
+    // [column][chunk][party] -> [party][column][chunk]
+    let by_party = |shares: Vec<Vec<Vec<C::Scalar>>>| -> Vec<Vec<Vec<C::Scalar>>> {
+        let mut parties = vec![Vec::with_capacity(shares.len()); pp.n];
+        for column in shares.into_iter() {
+            for (party, shares) in transpose(column).into_iter().enumerate() {
+                parties[party].push(shares);
+            }
+        }
+        parties
+    };
+
+    Ok(by_party(value_shares)
+        .into_iter()
+        .zip(by_party(poly_shares))
+        .enumerate()
+        .map(|(party_id, (advice_values, advice_polys))| ShareBundle {
+            party_id,
+            advice_values,
+            advice_polys,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ff::Field;
+    use halo2_proofs::halo2curves::bn256::Fr as F;
+
+    const L: usize = 4;
+
+    #[test]
+    fn test_pack_random() {
+        let pp = PackedSharingParams::<F>::new(L);
+        let rng = &mut ark_std::test_rng();
+
+        let values: Vec<F> = (0..2 * L).map(|_| F::random(&mut *rng)).collect();
+        let shares = pack_random(&values, &pp, rng);
+        let unpacked: Vec<F> = shares.iter().flat_map(|s| pp.unpack(s)).collect();
+        assert_eq!(values, unpacked);
+
+        // The same secrets are packed differently every time
+        assert_ne!(shares, pack_random(&values, &pp, rng));
+    }
+
+    #[test]
+    fn test_bundle_roundtrip() {
+        let rng = &mut ark_std::test_rng();
+        let bundle = ShareBundle {
+            party_id: 3,
+            advice_values: vec![vec![F::random(&mut *rng); L]],
+            advice_polys: vec![vec![F::random(&mut *rng); L]],
+        };
+
+        let mut bytes = Vec::new();
+        bundle.write(&mut bytes).unwrap();
+        let read = ShareBundle::<F>::read(&bytes[..]).unwrap();
+
+        assert_eq!(read.party_id, bundle.party_id);
+        assert_eq!(read.advice_values, bundle.advice_values);
+        assert_eq!(read.advice_polys, bundle.advice_polys);
+    }
+}
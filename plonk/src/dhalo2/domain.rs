@@ -0,0 +1,59 @@
+// Distributed counterpart of EvaluationDomain::rotate_extended for the prover.
+// The conversions between the domains are in dist_primitives::dfft::domain.
+
+use dist_primitives::{preprocessing::Preprocessing, utils::reshare::d_route};
+use ff::{PrimeField, WithSmallOrderMulGroup};
+use mpc_net::{MpcNetwork, NetError};
+use secret_sharing::pss::PackedSharingParams;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Returns packed shares of the rotated polynomials keyed by (index, rotation).
+/// All non-trivial rotations are done in a single masked route.
+pub fn d_rotate<F, P, N>(
+    pevals: &[Vec<F>],
+    rotations: &[(usize, i32)],
+    rot_scale: i32,
+    preprocessing: &mut P,
+    net: &N,
+    pp: &PackedSharingParams<F>,
+) -> Result<BTreeMap<(usize, i32), Vec<F>>, NetError>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    P: Preprocessing<F>,
+    N: MpcNetwork,
+{
+    let mut rotated = BTreeMap::new();
+    let mut pending = Vec::new();
+    for &(index, rotation) in rotations {
+        if rotation == 0 {
+            rotated.insert((index, 0), pevals[index].clone());
+        } else if !pending.contains(&(index, rotation)) {
+            pending.push((index, rotation));
+        }
+    }
+
+    if !pending.is_empty() {
+        let len = pevals[pending[0].0].len();
+        let shares: Vec<F> = pending
+            .iter()
+            .flat_map(|(index, _)| pevals[*index].clone())
+            .collect();
+        // Row i of a rotated polynomial holds row i + rotation, as in packed::rotate
+        let m = len * pp.l;
+        let map: Vec<Option<usize>> = pending
+            .iter()
+            .enumerate()
+            .flat_map(|(p, (_, rotation))| {
+                let shift = (rotation * rot_scale).rem_euclid(m as i32) as usize;
+                (0..m).map(move |i| Some(p * m + (i + shift) % m))
+            })
+            .collect();
+        let shares = d_route(shares, &map, false, preprocessing, net, pp)?;
+        for (shares, key) in shares.chunks(len).zip(pending.into_iter()) {
+            rotated.insert(key, shares.to_vec());
+        }
+    }
+
+    Ok(rotated)
+}
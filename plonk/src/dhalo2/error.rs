@@ -0,0 +1,51 @@
+use std::fmt;
+use std::io;
+
+use halo2_proofs::plonk;
+use mpc_net::NetError;
+
+/// Why a distributed proof failed
+#[derive(Debug)]
+pub enum Error {
+    /// The same failures as a local proof, from synthesis to the transcript
+    Plonk(plonk::Error),
+    /// Talking to the other parties failed, the proof can be retried from scratch
+    Net(NetError),
+}
+
+impl From<plonk::Error> for Error {
+    fn from(error: plonk::Error) -> Self {
+        Error::Plonk(error)
+    }
+}
+
+impl From<NetError> for Error {
+    fn from(error: NetError) -> Self {
+        Error::Net(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        // Like plonk::Error, io::Error only comes from the transcript
+        Error::Plonk(plonk::Error::Transcript(error))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Plonk(error) => write!(f, "{}", error),
+            Error::Net(error) => write!(f, "Network error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Plonk(error) => Some(error),
+            Error::Net(error) => Some(error),
+        }
+    }
+}
@@ -0,0 +1,6 @@
+pub mod dealer;
+pub mod domain;
+pub mod error;
+pub mod packed;
+pub mod prover;
+pub mod witness;
@@ -0,0 +1,401 @@
+use std::{cell::RefCell, fmt::Debug};
+
+use dist_primitives::{
+    channel::channel::MpcSerNet,
+    dmsm::dmsm::{d_msm, d_msm_batch, packexp_from_public},
+    preprocessing::Preprocessing,
+    utils::{
+        deg_red::deg_red,
+        pack::{pack_vec, transpose},
+    },
+};
+use ff::{PrimeField, WithSmallOrderMulGroup};
+use group::{prime::PrimeCurveAffine, Curve};
+use halo2_proofs::{
+    halo2curves::pairing::Engine,
+    plonk::{AdviceQuery, Expression, FixedQuery, InstanceQuery},
+    poly::kzg::commitment::ParamsKZG,
+};
+use mpc_net::{label, MpcNetwork, NetError};
+use secret_sharing::pss::PackedSharingParams;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Returns the packed shares of party_id of a public vector
+pub fn pack_public<F>(values: &[F], party_id: usize, pp: &PackedSharingParams<F>) -> Vec<F>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3>,
+{
+    pack_vec(&values.to_vec(), pp)
+        .into_iter()
+        .map(|shares| shares[party_id])
+        .collect()
+}
+
+/// Rotates a vector of rows so that row i holds the value of row i + rotation
+pub fn rotate<F: Copy>(values: &[F], rotation: i32) -> Vec<F> {
+    let mut rotated = values.to_vec();
+    rotated.rotate_left(rotation.rem_euclid(values.len() as i32) as usize);
+    rotated
+}
+
+/// Values over the rows of a domain as seen by one party.
+/// Shares are always packed in natural order, l consecutive rows per share.
+#[derive(Clone, Debug)]
+pub enum PackedValue<F> {
+    /// The same value on every row
+    Constant(F),
+    /// Values known to every party
+    Public(Vec<F>),
+    /// Packed shares, degree2 is set after a multiplication
+    Shared { shares: Vec<F>, degree2: bool },
+}
+
+impl<F> PackedValue<F>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+{
+    pub fn shared(shares: Vec<F>) -> Self {
+        PackedValue::Shared {
+            shares,
+            degree2: false,
+        }
+    }
+
+    /// Returns the packed shares of party_id of the values over size rows along with their degree
+    pub fn into_shares(
+        self,
+        size: usize,
+        party_id: usize,
+        pp: &PackedSharingParams<F>,
+    ) -> (Vec<F>, bool) {
+        match self {
+            // The constant polynomial is a valid packing of a constant vector
+            PackedValue::Constant(c) => (vec![c; size / pp.l], false),
+            PackedValue::Public(values) => (pack_public(&values, party_id, pp), false),
+            PackedValue::Shared { shares, degree2 } => (shares, degree2),
+        }
+    }
+
+    /// Returns packed shares of degree 1, reducing the degree with the help of king if needed
+    pub fn into_reduced_shares<P: Preprocessing<F>, N: MpcNetwork>(
+        self,
+        size: usize,
+        preprocessing: &mut P,
+        net: &N,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<Vec<F>, NetError> {
+        match self.into_shares(size, net.party_id(), pp) {
+            (shares, true) => reduce(shares, preprocessing, net, pp),
+            (shares, false) => Ok(shares),
+        }
+    }
+
+    pub fn neg(self) -> Self {
+        match self {
+            PackedValue::Constant(c) => PackedValue::Constant(-c),
+            PackedValue::Public(values) => PackedValue::Public(values.iter().map(|v| -*v).collect()),
+            PackedValue::Shared { shares, degree2 } => PackedValue::Shared {
+                shares: shares.iter().map(|v| -*v).collect(),
+                degree2,
+            },
+        }
+    }
+
+    pub fn scale(self, factor: F) -> Self {
+        match self {
+            PackedValue::Constant(c) => PackedValue::Constant(c * factor),
+            PackedValue::Public(values) => {
+                PackedValue::Public(values.iter().map(|v| *v * factor).collect())
+            }
+            PackedValue::Shared { shares, degree2 } => PackedValue::Shared {
+                shares: shares.iter().map(|v| *v * factor).collect(),
+                degree2,
+            },
+        }
+    }
+
+    pub fn add(self, other: Self, party_id: usize, pp: &PackedSharingParams<F>) -> Self {
+        match (self, other) {
+            (PackedValue::Constant(a), PackedValue::Constant(b)) => PackedValue::Constant(a + b),
+            (PackedValue::Constant(c), PackedValue::Public(values))
+            | (PackedValue::Public(values), PackedValue::Constant(c)) => {
+                PackedValue::Public(values.iter().map(|v| *v + c).collect())
+            }
+            (PackedValue::Constant(c), PackedValue::Shared { shares, degree2 })
+            | (PackedValue::Shared { shares, degree2 }, PackedValue::Constant(c)) => {
+                PackedValue::Shared {
+                    shares: shares.iter().map(|v| *v + c).collect(),
+                    degree2,
+                }
+            }
+            (PackedValue::Public(a), PackedValue::Public(b)) => {
+                PackedValue::Public(a.iter().zip(b.iter()).map(|(a, b)| *a + b).collect())
+            }
+            (PackedValue::Public(values), PackedValue::Shared { shares, degree2 })
+            | (PackedValue::Shared { shares, degree2 }, PackedValue::Public(values)) => {
+                PackedValue::Shared {
+                    shares: shares
+                        .iter()
+                        .zip(pack_public(&values, party_id, pp).iter())
+                        .map(|(a, b)| *a + b)
+                        .collect(),
+                    degree2,
+                }
+            }
+            (
+                PackedValue::Shared {
+                    shares: a,
+                    degree2: a_degree2,
+                },
+                PackedValue::Shared {
+                    shares: b,
+                    degree2: b_degree2,
+                },
+            ) => PackedValue::Shared {
+                shares: a.iter().zip(b.iter()).map(|(a, b)| *a + b).collect(),
+                degree2: a_degree2 || b_degree2,
+            },
+        }
+    }
+
+    pub fn sub(self, other: Self, party_id: usize, pp: &PackedSharingParams<F>) -> Self {
+        self.add(other.neg(), party_id, pp)
+    }
+
+    /// Multiplies two values, shares of degree 2 are reduced with the help of king first
+    pub fn mul<P: Preprocessing<F>, N: MpcNetwork>(
+        self,
+        other: Self,
+        preprocessing: &mut P,
+        net: &N,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<Self, NetError> {
+        Ok(match (self, other) {
+            (PackedValue::Constant(c), value) | (value, PackedValue::Constant(c)) => {
+                value.scale(c)
+            }
+            (PackedValue::Public(a), PackedValue::Public(b)) => {
+                PackedValue::Public(a.iter().zip(b.iter()).map(|(a, b)| *a * b).collect())
+            }
+            (PackedValue::Public(values), PackedValue::Shared { shares, degree2 })
+            | (PackedValue::Shared { shares, degree2 }, PackedValue::Public(values)) => {
+                let shares = if degree2 { reduce(shares, preprocessing, net, pp)? } else { shares };
+                PackedValue::Shared {
+                    shares: shares
+                        .iter()
+                        .zip(pack_public(&values, net.party_id(), pp).iter())
+                        .map(|(a, b)| *a * b)
+                        .collect(),
+                    degree2: true,
+                }
+            }
+            (
+                PackedValue::Shared {
+                    shares: a,
+                    degree2: a_degree2,
+                },
+                PackedValue::Shared {
+                    shares: b,
+                    degree2: b_degree2,
+                },
+            ) => {
+                let a = if a_degree2 { reduce(a, preprocessing, net, pp)? } else { a };
+                let b = if b_degree2 { reduce(b, preprocessing, net, pp)? } else { b };
+                PackedValue::Shared {
+                    shares: a.iter().zip(b.iter()).map(|(a, b)| *a * b).collect(),
+                    degree2: true,
+                }
+            }
+        })
+    }
+}
+
+/// Reduces the degree of shares with double sharings taken from preprocessing
+fn reduce<F, P, N>(
+    shares: Vec<F>,
+    preprocessing: &mut P,
+    net: &N,
+    pp: &PackedSharingParams<F>,
+) -> Result<Vec<F>, NetError>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    P: Preprocessing<F>,
+    N: MpcNetwork,
+{
+    let masks = preprocessing.double_sharings(shares.len(), pp)?;
+    deg_red(shares, masks, net, pp)
+}
+
+/// The columns of a circuit over the rows of one domain.
+/// rot_scale is the number of rows a rotation by one moves over.
+pub struct Rows<'a, F> {
+    pub rot_scale: i32,
+    pub fixed: Vec<&'a [F]>,
+    pub instance: Vec<&'a [F]>,
+    /// Shares of the advice columns keyed by (column, rotation)
+    pub advice: &'a BTreeMap<(usize, i32), Vec<F>>,
+}
+
+impl<'a, F> Rows<'a, F>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+{
+    /// Evaluates an expression over all rows
+    pub fn evaluate<P: Preprocessing<F>, N: MpcNetwork>(
+        &self,
+        expression: &Expression<F>,
+        preprocessing: &mut P,
+        net: &N,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<PackedValue<F>, NetError> {
+        // The callbacks of Expression::evaluate are shared, so they borrow preprocessing in turn
+        let preprocessing = RefCell::new(preprocessing);
+        expression.evaluate(
+            &|scalar| Ok::<_, NetError>(PackedValue::Constant(scalar)),
+            &|_| panic!("virtual selectors are removed during optimization"),
+            &|query: FixedQuery| {
+                Ok(PackedValue::Public(rotate(
+                    self.fixed[query.column_index()],
+                    query.rotation().0 * self.rot_scale,
+                )))
+            },
+            &|query: AdviceQuery| {
+                Ok(PackedValue::shared(
+                    self.advice[&(query.column_index(), query.rotation().0)].clone(),
+                ))
+            },
+            &|query: InstanceQuery| {
+                Ok(PackedValue::Public(rotate(
+                    self.instance[query.column_index()],
+                    query.rotation().0 * self.rot_scale,
+                )))
+            },
+            &|_| panic!("challenges are not supported by the distributed prover"),
+            &|a| Ok(a?.neg()),
+            &|a, b| Ok(a?.add(b?, net.party_id(), pp)),
+            &|a, b| a?.mul(b?, &mut **preprocessing.borrow_mut(), net, pp),
+            &|a, scalar| Ok(a?.scale(scalar)),
+        )
+    }
+
+    /// Compresses a list of expressions with powers of theta
+    pub fn compress<P: Preprocessing<F>, N: MpcNetwork>(
+        &self,
+        expressions: &[Expression<F>],
+        theta: F,
+        preprocessing: &mut P,
+        net: &N,
+        pp: &PackedSharingParams<F>,
+    ) -> Result<PackedValue<F>, NetError> {
+        expressions
+            .iter()
+            .try_fold(PackedValue::Constant(F::ZERO), |acc, expression| {
+                let value = self.evaluate(expression, preprocessing, net, pp)?;
+                Ok(acc.scale(theta).add(value, net.party_id(), pp))
+            })
+    }
+}
+
+/// Opens the evaluations of polynomials in packed coefficient form at the given points.
+/// Every party learns all the evaluations in a single round, the king only learns the
+/// evaluations, the partial sums in the slots are hidden by sharings that sum to zero.
+pub fn d_evaluate<F, P, N>(
+    queries: &[(&[F], F)],
+    preprocessing: &mut P,
+    net: &N,
+    pp: &PackedSharingParams<F>,
+) -> Result<Vec<F>, NetError>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    P: Preprocessing<F>,
+    N: MpcNetwork,
+{
+    // Packing is linear so shares of the powers in chunk i are point^(i*l) times those of chunk 0
+    let eval_shares: Vec<F> = queries
+        .iter()
+        .map(|(poly, point)| {
+            let mut powers = vec![F::ONE; pp.l];
+            for i in 1..pp.l {
+                powers[i] = powers[i - 1] * point;
+            }
+            let base_share = pp.pack_from_public(&powers)[net.party_id()];
+            let stride = point.pow_vartime([pp.l as u64]);
+
+            let mut scale = base_share;
+            let mut eval_share = F::ZERO;
+            for coeff in poly.iter() {
+                eval_share += *coeff * scale;
+                scale *= stride;
+            }
+            eval_share
+        })
+        .collect();
+    // The evaluations are the sums of the slots of shares of degree 2d, randomize the slots
+    let zeros = preprocessing.sum_zero_sharings(eval_shares.len(), pp)?;
+    let eval_shares: Vec<F> = eval_shares.iter().zip(zeros).map(|(x, z)| *x + z).collect();
+
+    let _label = label("evaluate");
+    let king_answer: Option<Vec<Vec<F>>> =
+        net.send_to_king(&eval_shares)?.map(|eval_shares: Vec<Vec<F>>| {
+            let evals: Vec<F> = transpose(eval_shares)
+                .iter()
+                .map(|shares| pp.unpack2(shares).iter().sum())
+                .collect();
+            vec![evals; net.n_parties()]
+        });
+
+    net.recv_from_king(king_answer)
+}
+
+/// Packed shares of the monomial commitment key
+pub struct PackedCommitKey<E: Engine> {
+    g: Vec<E::G1>,
+}
+
+impl<E> PackedCommitKey<E>
+where
+    E: Engine + Debug,
+    E::Scalar: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+{
+    pub fn new(
+        params: &ParamsKZG<E>,
+        n: usize,
+        party_id: usize,
+        pp: &PackedSharingParams<E::Scalar>,
+    ) -> Self {
+        let g = params.g()[..n]
+            .chunks(pp.l)
+            .map(|chunk| {
+                let chunk: Vec<E::G1> = chunk.iter().map(|g| g.to_curve()).collect();
+                packexp_from_public::<E>(&chunk, pp)[party_id]
+            })
+            .collect();
+        PackedCommitKey { g }
+    }
+
+    /// Commits to a polynomial given packed shares of its coefficients
+    pub fn commit<P: Preprocessing<E::Scalar>, N: MpcNetwork>(
+        &self,
+        pcoeff_share: &[E::Scalar],
+        preprocessing: &mut P,
+        net: &N,
+        pp: &PackedSharingParams<E::Scalar>,
+    ) -> Result<E::G1Affine, NetError> {
+        let bases = &self.g[..pcoeff_share.len()];
+        Ok(d_msm::<E, _, _>(bases, pcoeff_share, preprocessing, net, pp)?.to_affine())
+    }
+
+    /// Commits to several polynomials with a single round to and from the king
+    pub fn commit_batch<P: Preprocessing<E::Scalar>, N: MpcNetwork>(
+        &self,
+        pcoeff_shares: &[&[E::Scalar]],
+        preprocessing: &mut P,
+        net: &N,
+        pp: &PackedSharingParams<E::Scalar>,
+    ) -> Result<Vec<E::G1Affine>, NetError> {
+        let bases: Vec<&[E::G1]> = pcoeff_shares.iter().map(|p| &self.g[..p.len()]).collect();
+        let commitments = d_msm_batch::<E, _, _>(&bases, pcoeff_shares, preprocessing, net, pp)?;
+        Ok(commitments.iter().map(|c| c.to_affine()).collect())
+    }
+}
@@ -0,0 +1,1446 @@
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::iter;
+
+use dist_primitives::{
+    channel::channel::MpcSerNet,
+    dfft::domain::{
+        d_coeff_to_extended, d_coeff_to_extended_batch, d_extended_to_coeff,
+        d_extended_to_coeff_batch, d_lagrange_to_coeff, d_lagrange_to_coeff_batch,
+    },
+    dpp::dpp::d_pp,
+    preprocessing::{
+        shape::{Recorder, Shape},
+        KingDealer, Preprocessing,
+    },
+    utils::{
+        deg_red::deg_red,
+        pack::{pack_vec, transpose},
+        reshare::{d_deal, d_route},
+    },
+};
+use ff::{Field, FromUniformBytes, PrimeField, WithSmallOrderMulGroup};
+use group::Curve;
+use halo2_proofs::{
+    arithmetic::{best_multiexp, eval_polynomial, kate_division, CurveAffine},
+    halo2curves::pairing::Engine,
+    plonk::{Any, Circuit, Column, Error as PlonkError, ProvingKey},
+    poly::{kzg::commitment::ParamsKZG, Rotation},
+    transcript::{
+        Blake2bWrite, Challenge255, EncodedChallenge, TranscriptWrite, TranscriptWriterBuffer,
+    },
+};
+use log::debug;
+use mpc_net::{local::simulate, MpcNetwork, NetError};
+use rand::{rngs::OsRng, RngCore};
+use secret_sharing::pss::PackedSharingParams;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    dealer::ShareBundle,
+    domain::d_rotate,
+    error::Error,
+    packed::{d_evaluate, pack_public, PackedCommitKey, PackedValue, Rows},
+    witness::synthesize_advice,
+};
+
+/// A polynomial in coefficient form that is opened by the multiopen argument
+enum Opening<'a, F> {
+    Shared(&'a [F]),
+    Public(&'a [F]),
+}
+
+/// Polynomials of a lookup argument, shares are packed in natural order
+struct Lookup<F> {
+    compressed_input: PackedValue<F>,
+    compressed_table: PackedValue<F>,
+    permuted_input: Vec<F>,
+    permuted_table: Vec<F>,
+    permuted_input_poly: Vec<F>,
+    permuted_table_poly: Vec<F>,
+    product_poly: Vec<F>,
+}
+
+/// This creates a halo2 proof for the provided circuit with the KZG commitment scheme and
+/// the GWC multiopen argument, the transcript is accepted by the unmodified verify_proof.
+/// Every party synthesizes the circuit and packs the public witness itself, see
+/// d_create_proof_from_shares for the case where the witness is dealt by its owner.
+pub fn d_create_proof<E, Ch, R, T, P, N, ConcreteCircuit>(
+    params: &ParamsKZG<E>,
+    pk: &ProvingKey<E::G1Affine>,
+    circuit: &ConcreteCircuit,
+    instances: &[&[E::Scalar]],
+    mut rng: R,
+    transcript: &mut T,
+    preprocessing: &mut P,
+    net: &N,
+    pp: &PackedSharingParams<E::Scalar>,
+) -> Result<(), Error>
+where
+    E: Engine + Debug,
+    E::Scalar: PrimeField
+        + WithSmallOrderMulGroup<3>
+        + FromUniformBytes<64>
+        + Ord
+        + Serialize
+        + for<'de> Deserialize<'de>,
+    E::G1Affine: CurveAffine<ScalarExt = E::Scalar, CurveExt = E::G1>,
+    Ch: EncodedChallenge<E::G1Affine>,
+    R: RngCore,
+    T: TranscriptWrite<E::G1Affine, Ch>,
+    P: Preprocessing<E::Scalar>,
+    N: MpcNetwork,
+    ConcreteCircuit: Circuit<E::Scalar>,
+{
+    let cs = pk.get_vk().cs();
+    let domain = pk.get_vk().get_domain();
+    let n = 1usize << domain.k();
+    let party_id = net.party_id();
+    let usable_rows = n - (cs.blinding_factors() + 1);
+
+    let advice_values = synthesize_advice(circuit, cs, domain.k(), instances, usable_rows)?;
+    let num_advice = advice_values.len();
+
+    // King fills the unusable rows with blinding factors
+    let blinds = d_deal(net, pp, || {
+        (0..num_advice * n)
+            .map(|i| {
+                if i % n >= usable_rows {
+                    E::Scalar::random(&mut rng)
+                } else {
+                    E::Scalar::ZERO
+                }
+            })
+            .collect()
+    })?;
+    let advice_values: Vec<Vec<E::Scalar>> = advice_values
+        .iter()
+        .zip(blinds.chunks(n / pp.l))
+        .map(|(values, blinds)| {
+            pack_public(values, party_id, pp)
+                .iter()
+                .zip(blinds.iter())
+                .map(|(a, b)| *a + b)
+                .collect()
+        })
+        .collect();
+    let advice_polys =
+        d_lagrange_to_coeff_batch(advice_values.clone(), false, domain, preprocessing, net, pp)?;
+
+    let witness = ShareBundle {
+        party_id,
+        advice_values,
+        advice_polys,
+    };
+    d_create_proof_from_shares(
+        params,
+        pk,
+        &witness,
+        instances,
+        rng,
+        transcript,
+        preprocessing,
+        net,
+        pp,
+    )
+}
+
+/// Builds this party's share bundle from packed shares of the advice columns over all n rows,
+/// such as the wires of dwit::d_eval, so the witness owner only shares the primary inputs.
+/// A masked route clears the unusable rows, which king then fills with blinding factors.
+pub fn d_share_bundle<C, R, P, N>(
+    pk: &ProvingKey<C>,
+    advice_shares: Vec<Vec<C::Scalar>>,
+    mut rng: R,
+    preprocessing: &mut P,
+    net: &N,
+    pp: &PackedSharingParams<C::Scalar>,
+) -> Result<ShareBundle<C::Scalar>, NetError>
+where
+    C: CurveAffine,
+    C::Scalar: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    R: RngCore,
+    P: Preprocessing<C::Scalar>,
+    N: MpcNetwork,
+{
+    let cs = pk.get_vk().cs();
+    let domain = pk.get_vk().get_domain();
+    let n = 1usize << domain.k();
+    let usable_rows = n - (cs.blinding_factors() + 1);
+    assert_eq!(advice_shares.len(), cs.num_advice_columns());
+    for shares in advice_shares.iter() {
+        assert_eq!(shares.len() * pp.l, n);
+    }
+
+    let len = advice_shares.len() * n;
+    let map: Vec<Option<usize>> = (0..len).map(|i| (i % n < usable_rows).then_some(i)).collect();
+    let cleared = d_route(advice_shares.concat(), &map, false, preprocessing, net, pp)?;
+    let blinds = d_deal(net, pp, || {
+        (0..len)
+            .map(|i| {
+                if i % n >= usable_rows {
+                    C::Scalar::random(&mut rng)
+                } else {
+                    C::Scalar::ZERO
+                }
+            })
+            .collect()
+    })?;
+    let advice_values: Vec<Vec<C::Scalar>> = cleared
+        .iter()
+        .zip(blinds.iter())
+        .map(|(a, b)| *a + b)
+        .collect::<Vec<_>>()
+        .chunks(n / pp.l)
+        .map(|shares| shares.to_vec())
+        .collect();
+    let advice_polys =
+        d_lagrange_to_coeff_batch(advice_values.clone(), false, domain, preprocessing, net, pp)?;
+
+    Ok(ShareBundle {
+        party_id: net.party_id(),
+        advice_values,
+        advice_polys,
+    })
+}
+
+/// Same as d_create_proof but starts from this party's share bundle, the circuit is never
+/// synthesized by the parties. The bundle must have been dealt for the same proving key.
+/// Masks for the partial products, FFTs and degree reductions are taken from preprocessing.
+/// Only single phase circuits are supported.
+pub fn d_create_proof_from_shares<E, Ch, R, T, P, N>(
+    params: &ParamsKZG<E>,
+    pk: &ProvingKey<E::G1Affine>,
+    witness: &ShareBundle<E::Scalar>,
+    instances: &[&[E::Scalar]],
+    mut rng: R,
+    transcript: &mut T,
+    preprocessing: &mut P,
+    net: &N,
+    pp: &PackedSharingParams<E::Scalar>,
+) -> Result<(), Error>
+where
+    E: Engine + Debug,
+    E::Scalar: PrimeField
+        + WithSmallOrderMulGroup<3>
+        + FromUniformBytes<64>
+        + Ord
+        + Serialize
+        + for<'de> Deserialize<'de>,
+    E::G1Affine: CurveAffine<ScalarExt = E::Scalar, CurveExt = E::G1>,
+    Ch: EncodedChallenge<E::G1Affine>,
+    R: RngCore,
+    T: TranscriptWrite<E::G1Affine, Ch>,
+    P: Preprocessing<E::Scalar>,
+    N: MpcNetwork,
+{
+    let vk = pk.get_vk();
+    let cs = vk.cs();
+    let domain = vk.get_domain();
+    let n = 1usize << domain.k();
+    let ext_n = domain.extended_len();
+    let rot_scale = 1 << (domain.extended_k() - domain.k());
+    let blinding_factors = cs.blinding_factors();
+    let usable_rows = n - (blinding_factors + 1);
+    let party_id = net.party_id();
+
+    assert_eq!(cs.num_challenges(), 0, "only single phase circuits are supported");
+    assert!(
+        cs.advice_column_phase().iter().all(|phase| *phase == 0),
+        "only single phase circuits are supported"
+    );
+    assert_eq!(n % pp.l, 0, "domain size must be a multiple of l");
+
+    if instances.len() != cs.num_instance_columns() {
+        return Err(PlonkError::InvalidInstances.into());
+    }
+
+    let ck = PackedCommitKey::<E>::new(params, n, party_id, pp);
+
+    // Hash verification key into transcript
+    vk.hash_into(transcript)?;
+
+    // Instances are public, every party works with them in the clear
+    let instance_values = instances
+        .iter()
+        .map(|values| {
+            if values.len() > usable_rows {
+                return Err(Error::Plonk(PlonkError::InstanceTooLarge));
+            }
+            let mut poly = vec![E::Scalar::ZERO; n];
+            for (cell, value) in poly.iter_mut().zip(values.iter()) {
+                transcript.common_scalar(*value)?;
+                *cell = *value;
+            }
+            Ok(poly)
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let instance_cosets: Vec<Vec<E::Scalar>> = instance_values
+        .iter()
+        .map(|values| {
+            let poly = domain.lagrange_to_coeff(domain.lagrange_from_vec(values.clone()));
+            domain.coeff_to_extended(poly).to_vec()
+        })
+        .collect();
+
+    ////////////////////////////////////////////////////////////////////////////////////////////
+    // Advice
+    if witness.party_id != party_id {
+        return Err(PlonkError::Synthesis.into());
+    }
+    if witness.advice_values.len() != cs.num_advice_columns()
+        || witness.advice_polys.len() != cs.num_advice_columns()
+    {
+        return Err(PlonkError::Synthesis.into());
+    }
+    let advice_shares = &witness.advice_values;
+    let advice_polys = &witness.advice_polys;
+    let polys: Vec<&[E::Scalar]> = advice_polys.iter().map(|p| &p[..]).collect();
+    for commitment in ck.commit_batch(&polys, preprocessing, net, pp)? {
+        transcript.write_point(commitment)?;
+    }
+    debug!("Committed to advice");
+
+    let theta = *transcript.squeeze_challenge_scalar::<()>();
+
+    ////////////////////////////////////////////////////////////////////////////////////////////
+    // Lookups: king sorts the masked compressed expressions into the permuted columns
+    let advice_queries: Vec<(usize, i32)> = cs
+        .advice_queries()
+        .iter()
+        .map(|(column, rotation)| (column.index(), rotation.0))
+        .collect();
+    let advice_rows = d_rotate(advice_shares, &advice_queries, 1, preprocessing, net, pp)?;
+    let lagrange_rows = Rows {
+        rot_scale: 1,
+        fixed: pk.fixed_values().iter().map(|p| &p[..]).collect(),
+        instance: instance_values.iter().map(|p| &p[..]).collect(),
+        advice: &advice_rows,
+    };
+
+    let mut lookups: Vec<Lookup<E::Scalar>> = Vec::new();
+    for lookup in cs.lookups().iter() {
+        let compressed_input =
+            lagrange_rows.compress(lookup.input_expressions(), theta, preprocessing, net, pp)?;
+        let compressed_table =
+            lagrange_rows.compress(lookup.table_expressions(), theta, preprocessing, net, pp)?;
+
+        let input_shares = compressed_input.clone().into_reduced_shares(n, preprocessing, net, pp)?;
+        let table_shares = compressed_table.clone().into_reduced_shares(n, preprocessing, net, pp)?;
+        let permuted = d_permute_lookup(
+            [input_shares, table_shares].concat(),
+            usable_rows,
+            &mut rng,
+            preprocessing,
+            net,
+            pp,
+        )?;
+        let permuted_input = permuted[..n / pp.l].to_vec();
+        let permuted_table = permuted[n / pp.l..].to_vec();
+
+        let polys = vec![permuted_input.clone(), permuted_table.clone()];
+        let mut polys = d_lagrange_to_coeff_batch(polys, false, domain, preprocessing, net, pp)?;
+        let permuted_table_poly = polys.pop().unwrap();
+        let permuted_input_poly = polys.pop().unwrap();
+        let polys = [&permuted_input_poly[..], &permuted_table_poly[..]];
+        for commitment in ck.commit_batch(&polys, preprocessing, net, pp)? {
+            transcript.write_point(commitment)?;
+        }
+
+        lookups.push(Lookup {
+            compressed_input,
+            compressed_table,
+            permuted_input,
+            permuted_table,
+            permuted_input_poly,
+            permuted_table_poly,
+            product_poly: Vec::new(),
+        });
+    }
+
+    let beta = *transcript.squeeze_challenge_scalar::<()>();
+    let gamma = *transcript.squeeze_challenge_scalar::<()>();
+
+    ////////////////////////////////////////////////////////////////////////////////////////////
+    // Permutation: a single d_pp over all sets, unusable rows are masked to one so that the
+    // running product of a set starts where the previous one ended
+    let columns = cs.permutation().get_columns();
+    let chunk_len = cs.degree() - 2;
+    let lagrange_column = |column: &Column<Any>| match column.column_type() {
+        Any::Advice(_) => PackedValue::shared(advice_shares[column.index()].clone()),
+        Any::Fixed => PackedValue::Public(pk.fixed_values()[column.index()].to_vec()),
+        Any::Instance => PackedValue::Public(instance_values[column.index()].clone()),
+    };
+    let mask: Vec<E::Scalar> = (0..n)
+        .map(|i| if i < usable_rows { E::Scalar::ONE } else { E::Scalar::ZERO })
+        .collect();
+    let unmask: Vec<E::Scalar> = mask.iter().map(|m| E::Scalar::ONE - m).collect();
+
+    let mut permutation_polys: Vec<Vec<E::Scalar>> = Vec::new();
+    if !columns.is_empty() {
+        let mut num_all = Vec::new();
+        let mut den_all = Vec::new();
+        let mut delta = E::Scalar::ONE;
+        for (set_columns, sigmas) in columns
+            .chunks(chunk_len)
+            .zip(pk.permutation_values().chunks(chunk_len))
+        {
+            let mut num = PackedValue::Constant(E::Scalar::ONE);
+            let mut den = PackedValue::Constant(E::Scalar::ONE);
+            for (column, sigma) in set_columns.iter().zip(sigmas.iter()) {
+                let values = lagrange_column(column);
+
+                // p(\omega^i) + \beta \sigma(\omega^i) + \gamma
+                let permuted: Vec<E::Scalar> = sigma.iter().map(|s| beta * s + gamma).collect();
+                let permuted = values.clone().add(PackedValue::Public(permuted), party_id, pp);
+                den = den.mul(permuted, preprocessing, net, pp)?;
+
+                // p(\omega^i) + \beta \delta^j \omega^i + \gamma
+                let mut point = delta * beta;
+                let identity: Vec<E::Scalar> = (0..n)
+                    .map(|_| {
+                        let value = point + gamma;
+                        point *= domain.get_omega();
+                        value
+                    })
+                    .collect();
+                let identity = values.add(PackedValue::Public(identity), party_id, pp);
+                num = num.mul(identity, preprocessing, net, pp)?;
+
+                delta *= E::Scalar::DELTA;
+            }
+
+            let num = num
+                .mul(PackedValue::Public(mask.clone()), preprocessing, net, pp)?
+                .add(PackedValue::Public(unmask.clone()), party_id, pp);
+            let den = den
+                .mul(PackedValue::Public(mask.clone()), preprocessing, net, pp)?
+                .add(PackedValue::Public(unmask.clone()), party_id, pp);
+            num_all.extend(num.into_reduced_shares(n, preprocessing, net, pp)?);
+            den_all.extend(den.into_reduced_shares(n, preprocessing, net, pp)?);
+        }
+
+        let masks = preprocessing.dpp_masks(num_all.len() * pp.l, pp)?;
+        let products = d_pp(num_all, den_all, masks, net, pp)?;
+        let z_all =
+            d_grand_product(products, n, usable_rows, &mut rng, preprocessing, net, pp)?;
+
+        for z in z_all.chunks(n / pp.l) {
+            let poly = d_lagrange_to_coeff(z.to_vec(), false, domain, preprocessing, net, pp)?;
+            transcript.write_point(ck.commit(&poly, preprocessing, net, pp)?)?;
+            permutation_polys.push(poly);
+        }
+    }
+    debug!("Committed to permutation products");
+
+    ////////////////////////////////////////////////////////////////////////////////////////////
+    // Lookup and shuffle products
+    for lookup in lookups.iter_mut() {
+        // (A_compressed + \beta)(S_compressed + \gamma) / ((A' + \beta)(S' + \gamma))
+        let num = lookup
+            .compressed_input
+            .clone()
+            .add(PackedValue::Constant(beta), party_id, pp)
+            .mul(
+                lookup
+                    .compressed_table
+                    .clone()
+                    .add(PackedValue::Constant(gamma), party_id, pp),
+                preprocessing,
+                net,
+                pp,
+            )?;
+        let den = PackedValue::shared(lookup.permuted_input.clone())
+            .add(PackedValue::Constant(beta), party_id, pp)
+            .mul(
+                PackedValue::shared(lookup.permuted_table.clone())
+                    .add(PackedValue::Constant(gamma), party_id, pp),
+                preprocessing,
+                net,
+                pp,
+            )?;
+        let products = d_pp(
+            num.into_reduced_shares(n, preprocessing, net, pp)?,
+            den.into_reduced_shares(n, preprocessing, net, pp)?,
+            preprocessing.dpp_masks(n, pp)?,
+            net,
+            pp,
+        )?;
+        let z = d_grand_product(products, n, usable_rows, &mut rng, preprocessing, net, pp)?;
+
+        lookup.product_poly = d_lagrange_to_coeff(z, false, domain, preprocessing, net, pp)?;
+        transcript.write_point(ck.commit(&lookup.product_poly, preprocessing, net, pp)?)?;
+    }
+
+    let mut shuffle_polys: Vec<Vec<E::Scalar>> = Vec::new();
+    for shuffle in cs.shuffles().iter() {
+        // (I_compressed + \gamma) / (S_compressed + \gamma)
+        let num = lagrange_rows
+            .compress(shuffle.input_expressions(), theta, preprocessing, net, pp)?
+            .add(PackedValue::Constant(gamma), party_id, pp);
+        let den = lagrange_rows
+            .compress(shuffle.shuffle_expressions(), theta, preprocessing, net, pp)?
+            .add(PackedValue::Constant(gamma), party_id, pp);
+        let products = d_pp(
+            num.into_reduced_shares(n, preprocessing, net, pp)?,
+            den.into_reduced_shares(n, preprocessing, net, pp)?,
+            preprocessing.dpp_masks(n, pp)?,
+            net,
+            pp,
+        )?;
+        let z = d_grand_product(products, n, usable_rows, &mut rng, preprocessing, net, pp)?;
+
+        let poly = d_lagrange_to_coeff(z, false, domain, preprocessing, net, pp)?;
+        transcript.write_point(ck.commit(&poly, preprocessing, net, pp)?)?;
+        shuffle_polys.push(poly);
+    }
+    debug!("Committed to lookup and shuffle products");
+
+    // Vanishing argument: king deals the random polynomial in coefficient form
+    let random_poly =
+        d_deal(net, pp, || (0..n).map(|_| E::Scalar::random(&mut rng)).collect())?;
+    transcript.write_point(ck.commit(&random_poly, preprocessing, net, pp)?)?;
+
+    let y = *transcript.squeeze_challenge_scalar::<()>();
+
+    ////////////////////////////////////////////////////////////////////////////////////////////
+    // Evaluate h over the extended domain
+    let advice_cosets =
+        d_coeff_to_extended_batch(advice_polys.clone(), domain, preprocessing, net, pp)?;
+    let advice_ext =
+        d_rotate(&advice_cosets, &advice_queries, rot_scale, preprocessing, net, pp)?;
+    let ext_rows = Rows {
+        rot_scale,
+        fixed: pk.fixed_cosets().iter().map(|p| &p[..]).collect(),
+        instance: instance_cosets.iter().map(|p| &p[..]).collect(),
+        advice: &advice_ext,
+    };
+    let ext_column = |column: &Column<Any>| match column.column_type() {
+        Any::Advice(_) => PackedValue::shared(advice_cosets[column.index()].clone()),
+        Any::Fixed => PackedValue::Public(pk.fixed_cosets()[column.index()].to_vec()),
+        Any::Instance => PackedValue::Public(instance_cosets[column.index()].clone()),
+    };
+    let l0 = PackedValue::Public(pk.l0().to_vec());
+    let l_last = PackedValue::Public(pk.l_last().to_vec());
+    let l_active_row = PackedValue::Public(pk.l_active_row().to_vec());
+    let one = PackedValue::Constant(E::Scalar::ONE);
+    let last_rotation = -((blinding_factors + 1) as i32);
+
+    // All the constraints are combined as h = h * y + constraint
+    let mut h = PackedValue::Constant(E::Scalar::ZERO);
+    let push = |h: PackedValue<E::Scalar>, constraint: PackedValue<E::Scalar>| {
+        h.scale(y).add(constraint, party_id, pp)
+    };
+
+    // Custom gates
+    for gate in cs.gates().iter() {
+        for poly in gate.polynomials().iter() {
+            h = push(h, ext_rows.evaluate(poly, preprocessing, net, pp)?);
+        }
+    }
+
+    // Permutations
+    if !permutation_polys.is_empty() {
+        let z_cosets =
+            d_coeff_to_extended_batch(permutation_polys.clone(), domain, preprocessing, net, pp)?;
+        let rotations: Vec<(usize, i32)> = (0..z_cosets.len())
+            .flat_map(|i| [(i, 0), (i, 1), (i, last_rotation)])
+            .collect();
+        let z = d_rotate(&z_cosets, &rotations, rot_scale, preprocessing, net, pp)?;
+        let num_sets = z_cosets.len();
+
+        // l_0(X) * (1 - z_0(X)) = 0
+        let z_first = PackedValue::shared(z[&(0, 0)].clone());
+        let constraint = one
+            .clone()
+            .sub(z_first, party_id, pp)
+            .mul(l0.clone(), preprocessing, net, pp)?;
+        h = push(h, constraint);
+
+        // l_last(X) * (z_l(X)^2 - z_l(X)) = 0
+        let z_last = PackedValue::shared(z[&(num_sets - 1, 0)].clone());
+        let constraint = z_last
+            .clone()
+            .mul(z_last.clone(), preprocessing, net, pp)?
+            .sub(z_last, party_id, pp)
+            .mul(l_last.clone(), preprocessing, net, pp)?;
+        h = push(h, constraint);
+
+        // l_0(X) * (z_i(X) - z_{i-1}(\omega^(last) X)) = 0
+        for set in 1..num_sets {
+            let constraint = PackedValue::shared(z[&(set, 0)].clone())
+                .sub(PackedValue::shared(z[&(set - 1, last_rotation)].clone()), party_id, pp)
+                .mul(l0.clone(), preprocessing, net, pp)?;
+            h = push(h, constraint);
+        }
+
+        // (1 - (l_last(X) + l_blind(X))) * (
+        //   z_i(\omega X) \prod_j (p(X) + \beta s_j(X) + \gamma)
+        // - z_i(X) \prod_j (p(X) + \delta^j \beta X + \gamma)
+        // )
+        let mut current_delta: Vec<E::Scalar> = Vec::with_capacity(ext_n);
+        let mut point = beta * E::Scalar::ZETA;
+        for _ in 0..ext_n {
+            current_delta.push(point);
+            point *= domain.get_extended_omega();
+        }
+        for (set, (set_columns, cosets)) in columns
+            .chunks(chunk_len)
+            .zip(pk.permutation_cosets().chunks(chunk_len))
+            .enumerate()
+        {
+            let mut left = PackedValue::shared(z[&(set, 1)].clone());
+            for (column, coset) in set_columns.iter().zip(cosets.iter()) {
+                let permuted: Vec<E::Scalar> = coset.iter().map(|s| beta * s + gamma).collect();
+                let permuted = ext_column(column).add(PackedValue::Public(permuted), party_id, pp);
+                left = left.mul(permuted, preprocessing, net, pp)?;
+            }
+
+            let mut right = PackedValue::shared(z[&(set, 0)].clone());
+            for column in set_columns.iter() {
+                let identity: Vec<E::Scalar> = current_delta.iter().map(|d| *d + gamma).collect();
+                let identity = ext_column(column).add(PackedValue::Public(identity), party_id, pp);
+                right = right.mul(identity, preprocessing, net, pp)?;
+                current_delta
+                    .iter_mut()
+                    .for_each(|d| *d *= E::Scalar::DELTA);
+            }
+
+            let constraint = left
+                .sub(right, party_id, pp)
+                .mul(l_active_row.clone(), preprocessing, net, pp)?;
+            h = push(h, constraint);
+        }
+    }
+
+    // Lookups
+    for (lookup, argument) in lookups.iter().zip(cs.lookups().iter()) {
+        let polys = vec![
+            lookup.product_poly.clone(),
+            lookup.permuted_input_poly.clone(),
+            lookup.permuted_table_poly.clone(),
+        ];
+        let cosets = d_coeff_to_extended_batch(polys, domain, preprocessing, net, pp)?;
+        let rotations = [(0, 0), (0, 1), (1, 0), (1, -1), (2, 0)];
+        let rotated = d_rotate(&cosets, &rotations, rot_scale, preprocessing, net, pp)?;
+        let product = PackedValue::shared(rotated[&(0, 0)].clone());
+        let product_next = PackedValue::shared(rotated[&(0, 1)].clone());
+        let permuted_input = PackedValue::shared(rotated[&(1, 0)].clone());
+        let permuted_input_prev = PackedValue::shared(rotated[&(1, -1)].clone());
+        let permuted_table = PackedValue::shared(rotated[&(2, 0)].clone());
+
+        let table_value = ext_rows
+            .compress(argument.input_expressions(), theta, preprocessing, net, pp)?
+            .add(PackedValue::Constant(beta), party_id, pp)
+            .mul(
+                ext_rows
+                    .compress(argument.table_expressions(), theta, preprocessing, net, pp)?
+                    .add(PackedValue::Constant(gamma), party_id, pp),
+                preprocessing,
+                net,
+                pp,
+            )?;
+        let a_minus_s = permuted_input.clone().sub(permuted_table.clone(), party_id, pp);
+
+        // l_0(X) * (1 - z(X)) = 0
+        let constraint = one
+            .clone()
+            .sub(product.clone(), party_id, pp)
+            .mul(l0.clone(), preprocessing, net, pp)?;
+        h = push(h, constraint);
+        // l_last(X) * (z(X)^2 - z(X)) = 0
+        let constraint = product
+            .clone()
+            .mul(product.clone(), preprocessing, net, pp)?
+            .sub(product.clone(), party_id, pp)
+            .mul(l_last.clone(), preprocessing, net, pp)?;
+        h = push(h, constraint);
+        // (1 - (l_last(X) + l_blind(X))) * (
+        //   z(\omega X) (a'(X) + \beta) (s'(X) + \gamma)
+        //   - z(X) (\theta^{m-1} a_0(X) + ... + a_{m-1}(X) + \beta)
+        //          (\theta^{m-1} s_0(X) + ... + s_{m-1}(X) + \gamma)
+        // ) = 0
+        let left = product_next
+            .mul(
+                permuted_input.clone().add(PackedValue::Constant(beta), party_id, pp),
+                preprocessing,
+                net,
+                pp,
+            )?
+            .mul(
+                permuted_table.add(PackedValue::Constant(gamma), party_id, pp),
+                preprocessing,
+                net,
+                pp,
+            )?;
+        let right = product.mul(table_value, preprocessing, net, pp)?;
+        let constraint = left
+            .sub(right, party_id, pp)
+            .mul(l_active_row.clone(), preprocessing, net, pp)?;
+        h = push(h, constraint);
+        // l_0(X) * (a'(X) - s'(X)) = 0
+        h = push(h, a_minus_s.clone().mul(l0.clone(), preprocessing, net, pp)?);
+        // (1 - (l_last + l_blind)) * (a′(X) − s′(X))⋅(a′(X) − a′(\omega^{-1} X)) = 0
+        let constraint = a_minus_s
+            .mul(permuted_input.sub(permuted_input_prev, party_id, pp), preprocessing, net, pp)?
+            .mul(l_active_row.clone(), preprocessing, net, pp)?;
+        h = push(h, constraint);
+    }
+
+    // Shuffles
+    for (poly, argument) in shuffle_polys.iter().zip(cs.shuffles().iter()) {
+        let coset = d_coeff_to_extended(poly.clone(), domain, preprocessing, net, pp)?;
+        let rotations = [(0, 0), (0, 1)];
+        let rotated = d_rotate(&[coset], &rotations, rot_scale, preprocessing, net, pp)?;
+        let product = PackedValue::shared(rotated[&(0, 0)].clone());
+        let product_next = PackedValue::shared(rotated[&(0, 1)].clone());
+
+        let input_value = ext_rows
+            .compress(argument.input_expressions(), theta, preprocessing, net, pp)?
+            .add(PackedValue::Constant(gamma), party_id, pp);
+        let shuffle_value = ext_rows
+            .compress(argument.shuffle_expressions(), theta, preprocessing, net, pp)?
+            .add(PackedValue::Constant(gamma), party_id, pp);
+
+        // l_0(X) * (1 - z(X)) = 0
+        let constraint = one
+            .clone()
+            .sub(product.clone(), party_id, pp)
+            .mul(l0.clone(), preprocessing, net, pp)?;
+        h = push(h, constraint);
+        // l_last(X) * (z(X)^2 - z(X)) = 0
+        let constraint = product
+            .clone()
+            .mul(product.clone(), preprocessing, net, pp)?
+            .sub(product.clone(), party_id, pp)
+            .mul(l_last.clone(), preprocessing, net, pp)?;
+        h = push(h, constraint);
+        // (1 - (l_last(X) + l_blind(X))) * (z(\omega X) (s(X) + \gamma) - z(X) (a(X) + \gamma)) = 0
+        let constraint = product_next
+            .mul(shuffle_value, preprocessing, net, pp)?
+            .sub(product.mul(input_value, preprocessing, net, pp)?, party_id, pp)
+            .mul(l_active_row.clone(), preprocessing, net, pp)?;
+        h = push(h, constraint);
+    }
+    debug!("Evaluated h over the extended domain");
+
+    // Divide by the vanishing polynomial and commit to the pieces of h
+    let mut t_inv = domain.empty_extended();
+    t_inv.iter_mut().for_each(|v| *v = E::Scalar::ONE);
+    let t_inv = domain.divide_by_vanishing_poly(t_inv).to_vec();
+    let (h, degree2) = h
+        .mul(PackedValue::Public(t_inv), preprocessing, net, pp)?
+        .into_shares(ext_n, party_id, pp);
+    let h_poly = d_extended_to_coeff(h, degree2, domain, preprocessing, net, pp)?;
+    let h_pieces: Vec<Vec<E::Scalar>> = h_poly.chunks(n / pp.l).map(|p| p.to_vec()).collect();
+    let pieces: Vec<&[E::Scalar]> = h_pieces.iter().map(|p| &p[..]).collect();
+    for commitment in ck.commit_batch(&pieces, preprocessing, net, pp)? {
+        transcript.write_point(commitment)?;
+    }
+
+    let x = *transcript.squeeze_challenge_scalar::<()>();
+    let xn = x.pow_vartime([n as u64]);
+    let x_next = domain.rotate_omega(x, Rotation::next());
+    let x_prev = domain.rotate_omega(x, Rotation::prev());
+    let x_last = domain.rotate_omega(x, Rotation(last_rotation));
+
+    let h_poly = h_pieces
+        .iter()
+        .rev()
+        .fold(vec![E::Scalar::ZERO; n / pp.l], |acc, piece| {
+            acc.iter().zip(piece.iter()).map(|(a, p)| *a * xn + p).collect()
+        });
+
+    ////////////////////////////////////////////////////////////////////////////////////////////
+    // Open all the shared evaluations in one round
+    let mut queries: Vec<(&[E::Scalar], E::Scalar)> = Vec::new();
+    for (column, rotation) in cs.advice_queries().iter() {
+        queries.push((&advice_polys[column.index()], domain.rotate_omega(x, *rotation)));
+    }
+    queries.push((&random_poly, x));
+    for (set, poly) in permutation_polys.iter().enumerate() {
+        queries.push((poly, x));
+        queries.push((poly, x_next));
+        if set + 1 < permutation_polys.len() {
+            queries.push((poly, x_last));
+        }
+    }
+    for lookup in lookups.iter() {
+        queries.push((&lookup.product_poly, x));
+        queries.push((&lookup.product_poly, x_next));
+        queries.push((&lookup.permuted_input_poly, x));
+        queries.push((&lookup.permuted_input_poly, x_prev));
+        queries.push((&lookup.permuted_table_poly, x));
+    }
+    for poly in shuffle_polys.iter() {
+        queries.push((poly, x));
+        queries.push((poly, x_next));
+    }
+    queries.push((&h_poly, x));
+    let evals = d_evaluate(&queries, preprocessing, net, pp)?;
+    let mut evals = evals.into_iter();
+
+    let advice_evals: Vec<E::Scalar> = evals.by_ref().take(cs.advice_queries().len()).collect();
+    let fixed_evals: Vec<E::Scalar> = cs
+        .fixed_queries()
+        .iter()
+        .map(|(column, rotation)| {
+            eval_polynomial(
+                &pk.fixed_polys()[column.index()],
+                domain.rotate_omega(x, *rotation),
+            )
+        })
+        .collect();
+    let random_eval = evals.next().unwrap();
+    let sigma_evals: Vec<E::Scalar> = pk
+        .permutation_polys()
+        .iter()
+        .map(|poly| eval_polynomial(poly, x))
+        .collect();
+    let permutation_evals: Vec<Vec<E::Scalar>> = (0..permutation_polys.len())
+        .map(|set| {
+            let count = if set + 1 < permutation_polys.len() { 3 } else { 2 };
+            evals.by_ref().take(count).collect()
+        })
+        .collect();
+    let lookup_evals: Vec<Vec<E::Scalar>> = (0..lookups.len())
+        .map(|_| evals.by_ref().take(5).collect())
+        .collect();
+    let shuffle_evals: Vec<Vec<E::Scalar>> = (0..shuffle_polys.len())
+        .map(|_| evals.by_ref().take(2).collect())
+        .collect();
+    let h_eval = evals.next().unwrap();
+
+    for eval in advice_evals
+        .iter()
+        .chain(fixed_evals.iter())
+        .chain(iter::once(&random_eval))
+        .chain(sigma_evals.iter())
+        .chain(permutation_evals.iter().flatten())
+        .chain(lookup_evals.iter().flatten())
+        .chain(shuffle_evals.iter().flatten())
+    {
+        transcript.write_scalar(*eval)?;
+    }
+    debug!("Wrote evaluations");
+
+    ////////////////////////////////////////////////////////////////////////////////////////////
+    // Multiopen with GWC, queries in the same order as halo2
+    let mut openings: Vec<(Opening<E::Scalar>, E::Scalar, E::Scalar)> = Vec::new();
+    for ((column, rotation), eval) in cs.advice_queries().iter().zip(advice_evals.iter()) {
+        openings.push((
+            Opening::Shared(&advice_polys[column.index()]),
+            domain.rotate_omega(x, *rotation),
+            *eval,
+        ));
+    }
+    for (poly, evals) in permutation_polys.iter().zip(permutation_evals.iter()) {
+        openings.push((Opening::Shared(poly), x, evals[0]));
+        openings.push((Opening::Shared(poly), x_next, evals[1]));
+    }
+    for (poly, evals) in permutation_polys
+        .iter()
+        .zip(permutation_evals.iter())
+        .rev()
+        .skip(1)
+    {
+        openings.push((Opening::Shared(poly), x_last, evals[2]));
+    }
+    for (lookup, evals) in lookups.iter().zip(lookup_evals.iter()) {
+        openings.push((Opening::Shared(&lookup.product_poly), x, evals[0]));
+        openings.push((Opening::Shared(&lookup.permuted_input_poly), x, evals[2]));
+        openings.push((Opening::Shared(&lookup.permuted_table_poly), x, evals[4]));
+        openings.push((Opening::Shared(&lookup.permuted_input_poly), x_prev, evals[3]));
+        openings.push((Opening::Shared(&lookup.product_poly), x_next, evals[1]));
+    }
+    for (poly, evals) in shuffle_polys.iter().zip(shuffle_evals.iter()) {
+        openings.push((Opening::Shared(poly), x, evals[0]));
+        openings.push((Opening::Shared(poly), x_next, evals[1]));
+    }
+    for ((column, rotation), eval) in cs.fixed_queries().iter().zip(fixed_evals.iter()) {
+        openings.push((
+            Opening::Public(&pk.fixed_polys()[column.index()]),
+            domain.rotate_omega(x, *rotation),
+            *eval,
+        ));
+    }
+    for (poly, eval) in pk.permutation_polys().iter().zip(sigma_evals.iter()) {
+        openings.push((Opening::Public(poly), x, *eval));
+    }
+    openings.push((Opening::Shared(&h_poly), x, h_eval));
+    openings.push((Opening::Shared(&random_poly), x, random_eval));
+
+    let v = *transcript.squeeze_challenge_scalar::<()>();
+
+    // Group the queries by point, keeping the order in which the points first appear
+    let mut points: Vec<E::Scalar> = Vec::new();
+    let mut batches: BTreeMap<usize, (PackedValue<E::Scalar>, E::Scalar, E::Scalar)> =
+        BTreeMap::new();
+    for (poly, point, eval) in openings.into_iter() {
+        let index = match points.iter().position(|p| *p == point) {
+            Some(index) => index,
+            None => {
+                points.push(point);
+                points.len() - 1
+            }
+        };
+        let poly = match poly {
+            Opening::Shared(shares) => PackedValue::shared(shares.to_vec()),
+            Opening::Public(coeffs) => PackedValue::Public(coeffs.to_vec()),
+        };
+        let (poly_batch, eval_batch, power_of_v) = batches.remove(&index).unwrap_or((
+            PackedValue::Constant(E::Scalar::ZERO),
+            E::Scalar::ZERO,
+            E::Scalar::ONE,
+        ));
+        batches.insert(
+            index,
+            (
+                poly_batch.add(poly.scale(power_of_v), party_id, pp),
+                eval_batch + eval * power_of_v,
+                power_of_v * v,
+            ),
+        );
+    }
+
+    let mut witnesses: BTreeMap<usize, E::G1Affine> = BTreeMap::new();
+    let mut shared: Vec<(usize, E::Scalar, Vec<E::Scalar>)> = Vec::new();
+    for (index, (poly_batch, eval_batch, _)) in batches.into_iter() {
+        match poly_batch {
+            PackedValue::Public(mut coeffs) => {
+                coeffs[0] -= eval_batch;
+                let witness = kate_division(&coeffs, points[index]);
+                let witness = best_multiexp(&witness, &params.g()[..witness.len()]).to_affine();
+                witnesses.insert(index, witness);
+            }
+            poly_batch => {
+                let shares = poly_batch.into_reduced_shares(n, preprocessing, net, pp)?;
+                shared.push((index, eval_batch, shares));
+            }
+        }
+    }
+
+    // The shared batches are divided together: (p(X) - p(z)) / (X - z) is evaluated over the
+    // extended coset, where the division by public values is local, and interpolated back.
+    // The quotient has degree below n, so only its first n coefficients can be non-zero.
+    let ext_omega = domain.get_extended_omega();
+    let coset: Vec<E::Scalar> = iter::successors(Some(E::Scalar::ZETA), |x| Some(*x * ext_omega))
+        .take(ext_n)
+        .collect();
+    let polys = shared.iter().map(|(_, _, shares)| shares.clone()).collect();
+    let cosets = d_coeff_to_extended_batch(polys, domain, preprocessing, net, pp)?;
+    let quotients: Vec<Vec<E::Scalar>> = shared
+        .iter()
+        .zip(cosets.into_iter())
+        .map(|((index, eval_batch, _), evals)| {
+            // z hits the coset only with negligible probability
+            let point = points[*index];
+            let inv: Vec<E::Scalar> = coset
+                .iter()
+                .map(|x| (*x - point).invert().unwrap_or(E::Scalar::ZERO))
+                .collect();
+            PackedValue::shared(evals)
+                .sub(PackedValue::Constant(*eval_batch), party_id, pp)
+                .mul(PackedValue::Public(inv), preprocessing, net, pp)
+                .map(|quotient| quotient.into_shares(ext_n, party_id, pp).0)
+        })
+        .collect::<Result<_, _>>()?;
+    let quotients = d_extended_to_coeff_batch(quotients, true, domain, preprocessing, net, pp)?;
+    let quotients: Vec<&[E::Scalar]> = quotients.iter().map(|q| &q[..n / pp.l]).collect();
+    let commitments = ck.commit_batch(&quotients, preprocessing, net, pp)?;
+    for ((index, _, _), witness) in shared.iter().zip(commitments) {
+        witnesses.insert(*index, witness);
+    }
+    for witness in witnesses.into_values() {
+        transcript.write_point(witness)?;
+    }
+    debug!("Created multiopen proof");
+
+    Ok(())
+}
+
+/// Derives the shape of d_create_proof_from_shares for the proving key, to generate the
+/// preprocessing of later proofs. The shape does not depend on the witness, so all the parties
+/// run the prover in this process on an all-zero witness while recording the requests. The
+/// inputs of a lookup then take the values of its disabled rows, which the table must contain.
+pub fn proof_shape<E>(
+    params: &ParamsKZG<E>,
+    pk: &ProvingKey<E::G1Affine>,
+    pp: &PackedSharingParams<E::Scalar>,
+) -> Result<Shape, Error>
+where
+    E: Engine + Debug,
+    E::Scalar: PrimeField
+        + WithSmallOrderMulGroup<3>
+        + FromUniformBytes<64>
+        + Ord
+        + Serialize
+        + for<'de> Deserialize<'de>,
+    E::G1Affine: CurveAffine<ScalarExt = E::Scalar, CurveExt = E::G1>,
+{
+    let cs = pk.get_vk().cs();
+    let n = 1usize << pk.get_vk().get_domain().k();
+    let zeros = vec![vec![E::Scalar::ZERO; n / pp.l]; cs.num_advice_columns()];
+    let instances: Vec<&[E::Scalar]> = vec![&[]; cs.num_instance_columns()];
+
+    let mut shapes = simulate(pp.n, |net| {
+        let witness = ShareBundle {
+            party_id: net.party_id(),
+            advice_values: zeros.clone(),
+            advice_polys: zeros.clone(),
+        };
+        let mut recorder = Recorder::new(KingDealer::new(net));
+        let mut transcript =
+            Blake2bWrite::<_, E::G1Affine, Challenge255<_>>::init(Vec::<u8>::new());
+        d_create_proof_from_shares(
+            params,
+            pk,
+            &witness,
+            &instances,
+            OsRng,
+            &mut transcript,
+            &mut recorder,
+            net,
+            pp,
+        )
+        .map(|()| recorder.into_shape())
+    });
+    shapes.swap_remove(0)
+}
+
+/// Permutes the compressed input A and table S of a lookup, given shares of A followed by S,
+/// into shares of A' followed by S', see permute_expression_pair.
+/// The king has to compare the values, so the parties mask them as a x + b with shares of
+/// random constants a and b and the king sorts the masked values. The king learns which values
+/// are equal, but not the values themselves. Unmasking needs 1 / a, so the king also opens a c
+/// for another random constant c and the parties compute (y - b) c / (a c).
+/// If the input is not contained in the table every party gets ConstraintSystemFailure.
+fn d_permute_lookup<F, R, P, N>(
+    values: Vec<F>,
+    usable_rows: usize,
+    rng: &mut R,
+    preprocessing: &mut P,
+    net: &N,
+    pp: &PackedSharingParams<F>,
+) -> Result<Vec<F>, Error>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Ord + Serialize + for<'de> Deserialize<'de>,
+    R: RngCore,
+    P: Preprocessing<F>,
+    N: MpcNetwork,
+{
+    let n = values.len() * pp.l / 2;
+    let constants = preprocessing.constant_sharings(3, pp)?;
+    let (a, b, c) = (constants[0], constants[1], constants[2]);
+    let zeros = preprocessing.zero_sharings(values.len() + 1, pp)?;
+    let masked: Vec<F> = values
+        .iter()
+        .map(|x| *x * a + b)
+        .chain(iter::once(a * c))
+        .zip(zeros.iter())
+        .map(|(x, z)| x + z)
+        .collect();
+
+    let received = net.send_to_king(&masked)?;
+    let king_answer: Option<Vec<Option<(Vec<F>, F)>>> = received.map(|shares: Vec<Vec<F>>| {
+        let mut secrets: Vec<F> = transpose(shares).iter().flat_map(|x| pp.unpack2(x)).collect();
+        let ac = secrets[2 * n];
+        secrets.truncate(2 * n);
+        let (input, table) = secrets.split_at(n);
+        match permute_expression_pair(input, table, usable_rows, rng) {
+            Ok(permuted) => transpose(pack_vec(&permuted, pp))
+                .into_iter()
+                .map(|shares| Some((shares, ac)))
+                .collect(),
+            Err(_) => vec![None; pp.n],
+        }
+    });
+    let (permuted, ac) = net
+        .recv_from_king(king_answer)?
+        .ok_or(PlonkError::ConstraintSystemFailure)?;
+
+    // a c is non-zero unless one of the constants is, which has negligible probability
+    let scale = ac.invert().unwrap();
+    let unmasked: Vec<F> = permuted.iter().map(|y| (*y - b) * c * scale).collect();
+    let sharings = preprocessing.double_sharings(unmasked.len(), pp)?;
+    Ok(deg_red(unmasked, sharings, net, pp)?)
+}
+
+/// Moves the partial products of sets of n rows one row down into the columns of the grand
+/// products, with d_route so that the king never sees them. Every set starts where the previous
+/// one ended, the first row is one and the unusable rows are blinding factors dealt by the king.
+fn d_grand_product<F, R, P, N>(
+    products: Vec<F>,
+    n: usize,
+    usable_rows: usize,
+    rng: &mut R,
+    preprocessing: &mut P,
+    net: &N,
+    pp: &PackedSharingParams<F>,
+) -> Result<Vec<F>, NetError>
+where
+    F: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    R: RngCore,
+    P: Preprocessing<F>,
+    N: MpcNetwork,
+{
+    let len = products.len() * pp.l;
+    let map: Vec<Option<usize>> = (0..len)
+        .map(|i| (i > 0 && i % n <= usable_rows).then_some(i - 1))
+        .collect();
+    let shifted = d_route(products, &map, false, preprocessing, net, pp)?;
+
+    let blinds = d_deal(net, pp, || {
+        (0..len)
+            .map(|i| if i % n > usable_rows { F::random(&mut *rng) } else { F::ZERO })
+            .collect()
+    })?;
+    let mut first = vec![F::ZERO; len];
+    first[0] = F::ONE;
+    Ok(shifted
+        .iter()
+        .zip(blinds.iter())
+        .zip(pack_public(&first, net.party_id(), pp).iter())
+        .map(|((z, blind), one)| *z + blind + one)
+        .collect())
+}
+
+/// Given a vector of input values A and a vector of table values S,
+/// this method permutes A and S to produce A' and S', such that:
+/// - like values in A' are vertically adjacent to each other; and
+/// - the first row in a sequence of like values in A' is the row
+///   that has the corresponding value in S'.
+/// The unusable rows are filled with random values, returns A' followed by S'.
+fn permute_expression_pair<F: Field + Ord, R: RngCore>(
+    input: &[F],
+    table: &[F],
+    usable_rows: usize,
+    rng: &mut R,
+) -> Result<Vec<F>, PlonkError> {
+    let mut permuted_input: Vec<F> = input[..usable_rows].to_vec();
+    permuted_input.sort();
+
+    // A BTreeMap of each unique element in the table and its count
+    let mut leftover_table_map: BTreeMap<F, u32> =
+        table[..usable_rows]
+            .iter()
+            .fold(BTreeMap::new(), |mut acc, coeff| {
+                *acc.entry(*coeff).or_insert(0) += 1;
+                acc
+            });
+    let mut permuted_table = vec![F::ZERO; usable_rows];
+
+    let mut repeated_input_rows = Vec::new();
+    for (row, input_value) in permuted_input.iter().enumerate() {
+        // If this is the first occurrence of `input_value` in the input
+        if row == 0 || *input_value != permuted_input[row - 1] {
+            permuted_table[row] = *input_value;
+            // Remove one instance of input_value from leftover_table_map
+            match leftover_table_map.get_mut(input_value) {
+                Some(count) => {
+                    assert!(*count > 0);
+                    *count -= 1;
+                }
+                None => return Err(PlonkError::ConstraintSystemFailure),
+            }
+        } else {
+            repeated_input_rows.push(row);
+        }
+    }
+
+    // Populate permuted table at unfilled rows with leftover table elements
+    for (coeff, count) in leftover_table_map.iter() {
+        for _ in 0..*count {
+            permuted_table[repeated_input_rows.pop().unwrap()] = *coeff;
+        }
+    }
+    assert!(repeated_input_rows.is_empty());
+
+    let blinding_rows = input.len() - usable_rows;
+    permuted_input.extend((0..blinding_rows).map(|_| F::random(&mut *rng)));
+    permuted_table.extend((0..blinding_rows).map(|_| F::random(&mut *rng)));
+
+    Ok([permuted_input, permuted_table].concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dhalo2::dealer::deal_witness;
+    use dist_primitives::preprocessing::dealer;
+    use halo2_proofs::{
+        circuit::{AssignedCell, Layouter, SimpleFloorPlanner},
+        halo2curves::bn256::{Bn256, Fr, G1Affine},
+        plonk::{keygen_pk, keygen_vk, verify_proof, Advice, ConstraintSystem, Instance, Selector},
+        poly::{
+            commitment::ParamsProver,
+            kzg::{
+                commitment::KZGCommitmentScheme, multiopen::VerifierGWC, strategy::SingleStrategy,
+            },
+        },
+        transcript::{Blake2bRead, TranscriptReadBuffer},
+    };
+
+    const L: usize = 2;
+    const K: u32 = 4;
+
+    /// c = a + b on every row, where the first a, b and the last c are instances
+    #[derive(Clone, Default)]
+    struct SumCircuit {
+        rows: usize,
+    }
+
+    impl Circuit<Fr> for SumCircuit {
+        type Config = ([Column<Advice>; 3], Column<Instance>, Selector);
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            self.clone()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            let advice = [meta.advice_column(), meta.advice_column(), meta.advice_column()];
+            let instance = meta.instance_column();
+            let selector = meta.selector();
+            meta.enable_equality(instance);
+            advice.iter().for_each(|column| meta.enable_equality(*column));
+            meta.create_gate("add", |meta| {
+                let s = meta.query_selector(selector);
+                let a = meta.query_advice(advice[0], Rotation::cur());
+                let b = meta.query_advice(advice[1], Rotation::cur());
+                let c = meta.query_advice(advice[2], Rotation::cur());
+                vec![s * (a + b - c)]
+            });
+            (advice, instance, selector)
+        }
+
+        fn synthesize(
+            &self,
+            (advice, instance, selector): Self::Config,
+            mut layouter: impl Layouter<Fr>,
+        ) -> Result<(), PlonkError> {
+            let out = layouter.assign_region(
+                || "sums",
+                |mut region| {
+                    let mut a: AssignedCell<Fr, Fr> =
+                        region.assign_advice_from_instance(|| "a", instance, 0, advice[0], 0)?;
+                    let mut b: AssignedCell<Fr, Fr> =
+                        region.assign_advice_from_instance(|| "b", instance, 1, advice[1], 0)?;
+                    for row in 0..self.rows {
+                        selector.enable(&mut region, row)?;
+                        if row > 0 {
+                            a = a.copy_advice(|| "a", &mut region, advice[0], row)?;
+                            b = b.copy_advice(|| "b", &mut region, advice[1], row)?;
+                        }
+                        let c = region.assign_advice(
+                            || "c",
+                            advice[2],
+                            row,
+                            || a.value().copied() + b.value().copied(),
+                        )?;
+                        a = b;
+                        b = c;
+                    }
+                    Ok(b)
+                },
+            )?;
+            layouter.constrain_instance(out.cell(), instance, 2)
+        }
+    }
+
+    #[test]
+    fn d_permute_lookup_test() {
+        let pp = PackedSharingParams::<Fr>::new(L);
+        let n = 1 << K;
+        let usable_rows = n - 3;
+        let table: Vec<Fr> = (0..n as u64).map(Fr::from).collect();
+        let input: Vec<Fr> = (0..n as u64).map(|i| Fr::from(i % 3)).collect();
+        let share = |values: Vec<Fr>| transpose(pack_vec(&values, &pp));
+
+        let shares = share([input.clone(), table.clone()].concat());
+        let permuted = simulate(pp.n, |net| {
+            let values = shares[net.party_id()].clone();
+            let rng = &mut ark_std::test_rng();
+            d_permute_lookup(values, usable_rows, rng, &mut KingDealer::new(net), net, &pp)
+                .unwrap()
+        });
+        let permuted: Vec<Fr> = transpose(permuted).iter().flat_map(|x| pp.unpack(x)).collect();
+        let (permuted_input, permuted_table) = permuted.split_at(n);
+        for row in 0..usable_rows {
+            assert!(
+                permuted_input[row] == permuted_table[row]
+                    || (row > 0 && permuted_input[row] == permuted_input[row - 1])
+            );
+        }
+        let sorted = |values: &[Fr]| {
+            let mut values = values[..usable_rows].to_vec();
+            values.sort();
+            values
+        };
+        assert_eq!(sorted(permuted_input), sorted(&input));
+        assert_eq!(sorted(permuted_table), sorted(&table));
+
+        // An input that is not in the table fails at every party
+        let shares = share([vec![Fr::from(n as u64); n], table].concat());
+        let failed = simulate(pp.n, |net| {
+            let values = shares[net.party_id()].clone();
+            let rng = &mut ark_std::test_rng();
+            let result =
+                d_permute_lookup(values, usable_rows, rng, &mut KingDealer::new(net), net, &pp);
+            matches!(result, Err(Error::Plonk(PlonkError::ConstraintSystemFailure)))
+        });
+        assert!(failed.iter().all(|failed| *failed));
+    }
+
+    #[test]
+    fn d_create_proof_test() {
+        let pp = PackedSharingParams::<Fr>::new(L);
+        let params = ParamsKZG::<Bn256>::setup(K, ark_std::test_rng());
+        let circuit = SumCircuit { rows: 4 };
+        let vk = keygen_vk(&params, &circuit).unwrap();
+        let pk = keygen_pk(&params, vk, &circuit).unwrap();
+
+        let mut out = [Fr::ONE, Fr::ONE];
+        for _ in 0..circuit.rows {
+            out = [out[1], out[0] + out[1]];
+        }
+        let instance = [Fr::ONE, Fr::ONE, out[1]];
+
+        let proofs = simulate(pp.n, |net| {
+            let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
+            d_create_proof(
+                &params,
+                &pk,
+                &circuit,
+                &[&instance],
+                ark_std::test_rng(),
+                &mut transcript,
+                &mut KingDealer::new(net),
+                net,
+                &pp,
+            )
+            .unwrap();
+            transcript.finalize()
+        });
+        assert!(proofs.iter().all(|proof| *proof == proofs[0]));
+
+        let mut transcript = Blake2bRead::<_, G1Affine, Challenge255<_>>::init(&proofs[0][..]);
+        verify_proof::<KZGCommitmentScheme<Bn256>, VerifierGWC<'_, Bn256>, _, _, _>(
+            params.verifier_params(),
+            pk.get_vk(),
+            SingleStrategy::new(&params),
+            &[&[&instance]],
+            &mut transcript,
+        )
+        .expect("proof should verify");
+    }
+
+    #[test]
+    fn d_share_bundle_test() {
+        let pp = PackedSharingParams::<Fr>::new(L);
+        let params = ParamsKZG::<Bn256>::setup(K, ark_std::test_rng());
+        let circuit = SumCircuit { rows: 4 };
+        let vk = keygen_vk(&params, &circuit).unwrap();
+        let pk = keygen_pk(&params, vk, &circuit).unwrap();
+
+        let mut out = [Fr::ONE, Fr::ONE];
+        for _ in 0..circuit.rows {
+            out = [out[1], out[0] + out[1]];
+        }
+        let instance = [Fr::ONE, Fr::ONE, out[1]];
+
+        // Shares of every row of the advice columns, as evaluating the circuit on shares leaves
+        // them, with junk on the unusable rows
+        let cs = pk.get_vk().cs();
+        let n = 1 << K;
+        let usable_rows = n - (cs.blinding_factors() + 1);
+        let advice = synthesize_advice(&circuit, cs, K, &[&instance], usable_rows).unwrap();
+        let advice_shares: Vec<Vec<Vec<Fr>>> = advice
+            .into_iter()
+            .map(|mut values| {
+                values[usable_rows..].iter_mut().for_each(|v| *v = Fr::from(7));
+                transpose(pack_vec(&values, &pp))
+            })
+            .collect();
+
+        let proofs = simulate(pp.n, |net| {
+            let dealer = &mut KingDealer::new(net);
+            let shares = advice_shares.iter().map(|s| s[net.party_id()].clone()).collect();
+            let bundle =
+                d_share_bundle(&pk, shares, ark_std::test_rng(), dealer, net, &pp).unwrap();
+            let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
+            d_create_proof_from_shares(
+                &params,
+                &pk,
+                &bundle,
+                &[&instance],
+                ark_std::test_rng(),
+                &mut transcript,
+                dealer,
+                net,
+                &pp,
+            )
+            .unwrap();
+            transcript.finalize()
+        });
+
+        let mut transcript = Blake2bRead::<_, G1Affine, Challenge255<_>>::init(&proofs[0][..]);
+        verify_proof::<KZGCommitmentScheme<Bn256>, VerifierGWC<'_, Bn256>, _, _, _>(
+            params.verifier_params(),
+            pk.get_vk(),
+            SingleStrategy::new(&params),
+            &[&[&instance]],
+            &mut transcript,
+        )
+        .expect("proof should verify");
+    }
+
+    #[test]
+    fn proof_shape_test() {
+        let pp = PackedSharingParams::<Fr>::new(L);
+        let params = ParamsKZG::<Bn256>::setup(K, ark_std::test_rng());
+        let circuit = SumCircuit { rows: 4 };
+        let vk = keygen_vk(&params, &circuit).unwrap();
+        let pk = keygen_pk(&params, vk, &circuit).unwrap();
+
+        let mut out = [Fr::ONE, Fr::ONE];
+        for _ in 0..circuit.rows {
+            out = [out[1], out[0] + out[1]];
+        }
+        let instance = [Fr::ONE, Fr::ONE, out[1]];
+
+        // The shape is derived without the witness, the stores are dealt from it ahead of time
+        let shape = proof_shape(&params, &pk, &pp).unwrap();
+        let stores = dealer::deal(&shape, &pp, &mut ark_std::test_rng());
+        let bundles = deal_witness(&pk, &circuit, &[&instance], &pp, ark_std::test_rng()).unwrap();
+
+        let proofs = simulate(pp.n, |net| {
+            let mut store = stores[net.party_id()].clone();
+            let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
+            d_create_proof_from_shares(
+                &params,
+                &pk,
+                &bundles[net.party_id()],
+                &[&instance],
+                ark_std::test_rng(),
+                &mut transcript,
+                &mut store,
+                net,
+                &pp,
+            )
+            .unwrap();
+            assert!(store.is_empty());
+            transcript.finalize()
+        });
+
+        let mut transcript = Blake2bRead::<_, G1Affine, Challenge255<_>>::init(&proofs[0][..]);
+        verify_proof::<KZGCommitmentScheme<Bn256>, VerifierGWC<'_, Bn256>, _, _, _>(
+            params.verifier_params(),
+            pk.get_vk(),
+            SingleStrategy::new(&params),
+            &[&[&instance]],
+            &mut transcript,
+        )
+        .expect("proof should verify");
+    }
+}
@@ -3,8 +3,9 @@ use std::fmt::Debug;
 use crate::dpoly_commit::PackPolyCk;
 use crate::{poly_commit::PolyCk, PlonkDomain};
 use ark_std::{end_timer, start_timer, One, Zero};
-use dist_primitives::dfft::dfft::{d_fft, d_ifft};
+use dist_primitives::dfft::dfft::{d_fft, d_fft_batch, d_ifft, d_ifft_batch};
 use dist_primitives::dpp::dpp::d_pp;
+use dist_primitives::preprocessing::{KingDealer, Preprocessing};
 use dist_primitives::utils::deg_red::deg_red;
 use dist_primitives::utils::domain_utils::EvaluationDomainExt;
 use ff::Field;
@@ -15,7 +16,7 @@ use rand::Rng;
 use secret_sharing::pss::PackedSharingParams;
 use serde::{Deserialize, Serialize};
 
-use mpc_net::{MpcMultiNet as Net, MpcNet};
+use mpc_net::{MpcNetwork, NetError};
 
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct PackProvingKey<E>
@@ -84,17 +85,23 @@ where
     dom.rotate_omega(F::ONE, Rotation(i as i32))
 }
 
-pub fn d_plonk_test<E>(pd: &PlonkDomain<E::Scalar>, pp: &PackedSharingParams<E::Scalar>)
+pub fn d_plonk_test<E, N>(
+    pd: &PlonkDomain<E::Scalar>,
+    net: &N,
+    pp: &PackedSharingParams<E::Scalar>,
+) -> Result<(), NetError>
 where
     E: Engine + Debug,
     E::Scalar: PrimeField + WithSmallOrderMulGroup<3> + Serialize + for<'de> Deserialize<'de>,
+    N: MpcNetwork,
 {
+    let mut dealer = KingDealer::new(net);
     let mbyl = pd.n_gates / pp.l;
-    if Net::am_king() {
+    if net.am_king() {
         println!("mbyl: {}", mbyl);
     }
     // Generate CRS ===========================================
-    if Net::am_king() {
+    if net.am_king() {
         println!("Generating CRS===============================");
     }
     let rng = &mut ark_std::test_rng();
@@ -104,7 +111,7 @@ where
     let ck8: PackPolyCk<E> = PackPolyCk::<E>::new(8 * pd.n_gates, rng, pp);
 
     let prover_timer = start_timer!(|| "Prover");
-    if Net::am_king() {
+    if net.am_king() {
         println!("Round 1===============================");
     }
     // Round 1 ================================================
@@ -120,23 +127,28 @@ where
     }
 
     println!("Committing to a, b, c");
-    ck.commit(&aevals, pp);
-    ck.commit(&bevals, pp);
-    ck.commit(&cevals, pp);
+    ck.commit_batch(&[&aevals, &bevals, &cevals], net, pp)?;
     println!("=======================");
 
     println!("Extending domain of a,b,c to 8n");
     // do ifft and fft to get evals of a,b,c on the 8n domain
-    let aevals8 = d_ifft(aevals.clone(), true, 8, false, &pd.gates, pp);
-    let bevals8 = d_ifft(bevals.clone(), true, 8, false, &pd.gates, pp);
-    let cevals8 = d_ifft(cevals.clone(), true, 8, false, &pd.gates, pp);
-
-    let aevals8 = d_fft(aevals8, false, 1, false, &pd.gates8, pp);
-    let bevals8 = d_fft(bevals8, false, 1, false, &pd.gates8, pp);
-    let cevals8 = d_fft(cevals8, false, 1, false, &pd.gates8, pp);
+    // all three go through the king together, one round for the ifft and one for the fft
+    let masks = (0..3)
+        .map(|_| dealer.fft_masks(true, true, 8, false, &pd.gates, pp))
+        .collect::<Result<_, _>>()?;
+    let evals = vec![aevals.clone(), bevals.clone(), cevals.clone()];
+    let coeffs8 = d_ifft_batch(evals, true, 8, false, masks, &pd.gates, net, pp)?;
+
+    let masks = (0..3)
+        .map(|_| dealer.fft_masks(false, false, 1, false, &pd.gates8, pp))
+        .collect::<Result<_, _>>()?;
+    let mut evals8 = d_fft_batch(coeffs8, false, 1, false, masks, &pd.gates8, net, pp)?;
+    let cevals8 = evals8.pop().unwrap();
+    let bevals8 = evals8.pop().unwrap();
+    let aevals8 = evals8.pop().unwrap();
     println!("=======================");
 
-    if Net::am_king() {
+    if net.am_king() {
         println!("Round 2===============================");
     }
     // Round 2 ================================================
@@ -168,14 +180,16 @@ where
     end_timer!(ldpp_timer);
     // todo: benchmark this
     // partial products
-    let zevals = d_pp(num, den, pp);
+    let zevals = d_pp(num, den, dealer.dpp_masks(pd.n_gates, pp)?, net, pp)?;
 
     // extend to zevals8
     let zevals8 = zevals.clone();
-    let zevals8 = d_ifft(zevals8, true, 8, false, &pd.gates, pp);
-    let zevals8 = d_fft(zevals8, false, 1, false, &pd.gates8, pp);
+    let masks = dealer.fft_masks(true, true, 8, false, &pd.gates, pp)?;
+    let zevals8 = d_ifft(zevals8, true, 8, false, masks, &pd.gates, net, pp)?;
+    let masks = dealer.fft_masks(false, false, 1, false, &pd.gates8, pp)?;
+    let zevals8 = d_fft(zevals8, false, 1, false, masks, &pd.gates8, net, pp)?;
 
-    if Net::am_king() {
+    if net.am_king() {
         println!("Round 3===============================");
     }
     // Round 3 ================================================
@@ -228,15 +242,22 @@ where
     end_timer!(t_timer);
 
     // divide by ZH
-    let tcoeffs = d_ifft(tevals8, true, 1, false, &pd.gates8, pp);
-    let mut tevals8 = d_fft(tcoeffs, false, 1, false, &pd.gates8, pp); //king actually needs to truncate
-
-    let toep_mat = E::Scalar::from(123 as u64); // packed shares of toeplitz matrix drop from sky
-    tevals8.iter_mut().for_each(|x| *x *= toep_mat);
-
-    let tevals8 = deg_red(tevals8, pp);
-
-    if Net::am_king() {
+    let masks = dealer.fft_masks(true, true, 1, false, &pd.gates8, pp)?;
+    let tcoeffs = d_ifft(tevals8, true, 1, false, masks, &pd.gates8, net, pp)?;
+    let masks = dealer.fft_masks(false, false, 1, false, &pd.gates8, pp)?;
+    let mut tevals8 = d_fft(tcoeffs, false, 1, false, masks, &pd.gates8, net, pp)?; //king actually needs to truncate
+
+    // toeplitz matrix of 1 / (X^n - 1) only holds ones
+    let toep_mat = dealer.point_powers(E::Scalar::ONE, tevals8.len() * pp.l, pp)?;
+    tevals8
+        .iter_mut()
+        .zip(toep_mat.iter())
+        .for_each(|(x, t)| *x *= t);
+
+    let masks = dealer.double_sharings(tevals8.len(), pp)?;
+    let tevals8 = deg_red(tevals8, masks, net, pp)?;
+
+    if net.am_king() {
         println!("Round 4===============================");
     }
     // Round 4 ================================================
@@ -245,14 +266,14 @@ where
     // commit and open r = (open_a.open_b)qm + (open_a)ql + (open_b)qr + (open_c)qo + qc
 
     println!("Committing to z, t");
-    ck.commit(&zevals, pp);
-    ck8.commit(&tevals8, pp);
+    ck.commit(&zevals, net, pp)?;
+    ck8.commit(&tevals8, net, pp)?;
 
     println!("Opening a, b, c");
     let point = E::Scalar::random(&mut *rng);
-    let open_a = ck.open(&aevals, point, &pd.gates, pp);
-    let open_b = ck.open(&bevals, point, &pd.gates, pp);
-    let open_c = ck.open(&cevals, point, &pd.gates, pp);
+    let open_a = ck.open(&aevals, point, &pd.gates, net, pp)?;
+    let open_b = ck.open(&bevals, point, &pd.gates, net, pp)?;
+    let open_c = ck.open(&cevals, point, &pd.gates, net, pp)?;
 
     println!("Opening s1, s2, s3");
     // extract every 8th element of pk.s1 using iterators
@@ -260,20 +281,23 @@ where
         &pk.s1.iter().step_by(8).copied().collect(),
         point,
         &pd.gates,
+        net,
         pp,
-    );
+    )?;
     ck.open(
         &pk.s2.iter().step_by(8).copied().collect(),
         point,
         &pd.gates,
+        net,
         pp,
-    );
+    )?;
     ck.open(
         &pk.s3.iter().step_by(8).copied().collect(),
         point,
         &pd.gates,
+        net,
         pp,
-    );
+    )?;
 
     println!("Computing r");
     let r_timer = start_timer!(|| "Compute r");
@@ -289,8 +313,9 @@ where
     end_timer!(r_timer);
 
     println!("Committing to r");
-    ck.commit(&revals, pp);
-    ck.open(&revals, point, &pd.gates, pp);
+    ck.commit(&revals, net, pp)?;
+    ck.open(&revals, point, &pd.gates, net, pp)?;
 
     end_timer!(prover_timer);
+    Ok(())
 }
@@ -1,6 +1,7 @@
 use ark_std::{end_timer, start_timer};
 use dist_primitives::dfft::dfft::{d_fft, d_ifft};
-use dist_primitives::dmsm::dmsm::d_msm;
+use dist_primitives::dmsm::dmsm::{d_msm, d_msm_batch};
+use dist_primitives::preprocessing::{KingDealer, Preprocessing};
 use dist_primitives::utils::bn256::random_utils::create_random_group_element;
 use dist_primitives::utils::deg_red::deg_red;
 use dist_primitives::utils::domain_utils::EvaluationDomainExt;
@@ -8,6 +9,7 @@ use dist_primitives::utils::g1_serialization::G1Wrapper;
 use ff::{PrimeField, WithSmallOrderMulGroup};
 use halo2_proofs::halo2curves::pairing::Engine;
 use halo2_proofs::poly::EvaluationDomain;
+use mpc_net::{MpcNetwork, NetError};
 use rand::Rng;
 use secret_sharing::pss::PackedSharingParams;
 use serde::{Deserialize, Serialize};
@@ -42,59 +44,90 @@ where
     }
 
     /// Interactively commits to a polynomial give packed shares of the evals
-    #[allow(unused)]
-    pub fn commit(&self, peval_share: &Vec<E::Scalar>, pp: &PackedSharingParams<E::Scalar>) {
+    pub fn commit<N: MpcNetwork>(
+        &self,
+        peval_share: &Vec<E::Scalar>,
+        net: &N,
+        pp: &PackedSharingParams<E::Scalar>,
+    ) -> Result<E::G1, NetError> {
         let powers_of_tau_g1: Vec<E::G1> = self
             .powers_of_tau
             .iter()
             .map(|wrapper| wrapper.0.clone())
             .collect();
 
-        let commitment = d_msm::<E>(&powers_of_tau_g1, peval_share.as_slice(), pp);
-        // actually getting back shares but king can publish the commitment
+        // King opens the commitment to everyone
+        let dealer = &mut KingDealer::new(net);
+        d_msm::<E, _, _>(&powers_of_tau_g1, peval_share.as_slice(), dealer, net, pp)
+    }
+
+    /// Commits to several polynomials with a single round to and from the king
+    pub fn commit_batch<N: MpcNetwork>(
+        &self,
+        peval_shares: &[&Vec<E::Scalar>],
+        net: &N,
+        pp: &PackedSharingParams<E::Scalar>,
+    ) -> Result<Vec<E::G1>, NetError> {
+        let powers_of_tau_g1: Vec<E::G1> =
+            self.powers_of_tau.iter().map(|wrapper| wrapper.0).collect();
+
+        let bases = vec![&powers_of_tau_g1[..]; peval_shares.len()];
+        let scalars: Vec<&[E::Scalar]> = peval_shares.iter().map(|p| p.as_slice()).collect();
+        let dealer = &mut KingDealer::new(net);
+        d_msm_batch::<E, _, _>(&bases, &scalars, dealer, net, pp)
     }
 
     /// Interactively creates an opening to a polynomial at a chosen point
     #[allow(unused)]
-    pub fn open(
+    pub fn open<N: MpcNetwork>(
         &self,
         peval_share: &Vec<E::Scalar>,
         point: E::Scalar,
         dom: &EvaluationDomain<E::Scalar>,
+        net: &N,
         pp: &PackedSharingParams<E::Scalar>,
-    ) -> E::Scalar {
+    ) -> Result<E::Scalar, NetError> {
         debug_assert_eq!(
             peval_share.len() * pp.l,
             dom.size(),
             "pevals length is not equal to m/l"
         );
+        let mut dealer = KingDealer::new(net);
+
         // Interpolate pevals to get coeffs
-        let pcoeff_share = d_ifft(peval_share.clone(), false, 1, false, dom, pp);
+        let masks = dealer.fft_masks(true, false, 1, false, dom, pp)?;
+        let pcoeff_share = d_ifft(peval_share.clone(), false, 1, false, masks, dom, net, pp)?;
 
         // distributed poly evaluation
-        let powers_of_r_share = E::Scalar::from(123 as u64); // packed shares of r drop from sky
+        // packed shares of powers of r
+        let powers_of_r_share = dealer.point_powers(point, dom.size(), pp)?;
         let point_eval_share = pcoeff_share
             .iter()
-            .map(|&a| a * powers_of_r_share)
+            .zip(powers_of_r_share.iter())
+            .map(|(&a, &b)| a * b)
             .sum::<E::Scalar>();
 
         // do degree reduction and King publishes answer
-        let point_eval_share = deg_red(vec![point_eval_share], pp)[0];
+        let masks = dealer.double_sharings(1, pp)?;
+        let point_eval_share = deg_red(vec![point_eval_share], masks, net, pp)?[0];
 
         // Compute the quotient polynomial
         // During iFFT king sends over the "truncated pcoeff_shares". Do FFT on this
 
-        let ptrunc_evals = d_fft(pcoeff_share, false, 1, false, dom, pp);
-        let toep_mat_share = E::Scalar::from(123 as u64); // packed shares of toeplitz matrix drop from sky
+        let masks = dealer.fft_masks(false, false, 1, false, dom, pp)?;
+        let ptrunc_evals = d_fft(pcoeff_share, false, 1, false, masks, dom, net, pp)?;
+        let toep_mat_share = powers_of_r_share; // toeplitz matrix of 1 / (X - r) is made of powers of r
         let timer_div = start_timer!(|| "Division");
         let q_evals = ptrunc_evals
             .into_iter()
-            .map(|a| a * toep_mat_share)
+            .zip(toep_mat_share.iter())
+            .map(|(a, b)| a * b)
             .collect::<Vec<E::Scalar>>();
         end_timer!(timer_div);
 
-        // don't have to do degree reduction since it's a secret value multiplied by two public values
-        // we could pack two public values together but that would mean two msms instead of one
+        // the toeplitz matrix is shared too, so reduce the degree before the msm
+        let masks = dealer.double_sharings(q_evals.len(), pp)?;
+        let q_evals = deg_red(q_evals, masks, net, pp)?;
 
         let powers_of_tau_g1: Vec<E::G1> = self
             .powers_of_tau
@@ -102,8 +135,8 @@ where
             .map(|wrapper| wrapper.0.clone())
             .collect();
         // Compute the proof pi
-        let pi: E::G1 = d_msm::<E>(&powers_of_tau_g1, &q_evals, pp);
+        let pi: E::G1 = d_msm::<E, _, _>(&powers_of_tau_g1, &q_evals, &mut dealer, net, pp)?;
 
-        point_eval_share
+        Ok(point_eval_share)
     }
 }
@@ -1,6 +1,7 @@
 use ff::{PrimeField, WithSmallOrderMulGroup};
 use halo2_proofs::poly::EvaluationDomain;
 
+pub mod dhalo2;
 pub mod dplonk;
 pub mod dpoly_commit;
 pub mod localplonk;
@@ -1,52 +1,132 @@
+use ark_std::rand::RngCore;
 use ff::{PrimeField, WithSmallOrderMulGroup};
-use halo2_proofs::poly::EvaluationDomain;
+use halo2_proofs::{arithmetic::best_fft, poly::EvaluationDomain};
+use std::iter::Sum;
+use std::ops::Mul;
 
-/// Currently the packed secret sharing is deterministic, but it can easily be extended to add random values when packing
+/// Packed secret sharing with a polynomial of degree d = t + l - 1.
+/// Secrets and randomness sit on the secret points, shares on the share points.
+/// pack hides the secrets from any t parties, pack_from_public is deterministic and only meant for public values
 #[derive(Debug, Clone)]
 pub struct PackedSharingParams<F>
 where
     F: PrimeField,
 {
-    pub t: usize,                     // Corruption threshold
-    pub l: usize,                     // Packing factor
-    pub n: usize,                     // Number of parties
-    pub share: EvaluationDomain<F>,   // Share domain
-    pub secret: EvaluationDomain<F>,  // Secrets domain
-    pub secret2: EvaluationDomain<F>, // Secrets2 domain
+    pub t: usize,               // Corruption threshold
+    pub l: usize,               // Packing factor
+    pub n: usize,               // Number of parties
+    pub share_points: Vec<F>,   // ZETA * omega^i, one per party
+    pub secret_points: Vec<F>,  // omega^i, the first l hold secrets and the next t randomness
+    pack_matrix: Vec<Vec<F>>,   // secret points -> share points
+    unpack_matrix: Vec<Vec<F>>, // first d + 1 share points -> secrets
+    unpack2_matrix: Vec<Vec<F>>, // first 2d + 1 share points -> secrets
 }
 
 impl<F: PrimeField + WithSmallOrderMulGroup<3>> PackedSharingParams<F> {
+    /// n = 4l parties with threshold t = l - 1
     #[allow(unused)]
     pub fn new(l: usize) -> Self {
-        let n = l * 4;
-        let t = l - 1;
-        debug_assert_eq!(n, 2 * (t + l + 1));
+        Self::new_with(4 * l, l - 1, l)
+    }
+
+    /// n parties, corruption threshold t and packing factor l.
+    /// Products of two sharings must still be reconstructible, so n >= 2(t + l - 1) + 1.
+    pub fn new_with(n: usize, t: usize, l: usize) -> Self {
+        assert!(l > 0, "packing factor must be positive");
+        assert!(
+            n >= 2 * (t + l - 1) + 1,
+            "n = {} parties cannot reconstruct degree 2(t + l - 1) = {}",
+            n,
+            2 * (t + l - 1)
+        );
 
-        let share = EvaluationDomain::<F>::new(1, (n as f64).log2() as u32);
-        let secret = EvaluationDomain::<F>::new(1, ((l + t + 1) as f64).log2() as u32);
-        let secret2 =
-            EvaluationDomain::<F>::new(1, ((2 as f64) * (l + t + 1) as f64).log2() as u32);
+        // Shares sit on a coset so that they never meet the secret points
+        let share_domain =
+            EvaluationDomain::<F>::new(1, n.next_power_of_two().trailing_zeros());
+        let share_points = Self::powers(F::ZETA, share_domain.get_omega(), n);
+        let secret_domain =
+            EvaluationDomain::<F>::new(1, (t + l).next_power_of_two().trailing_zeros());
+        let secret_points = Self::powers(F::ONE, secret_domain.get_omega(), t + l);
 
-        debug_assert_eq!(1 << share.k() as usize, n);
-        debug_assert_eq!(1 << secret.k() as usize, l + t + 1);
-        debug_assert_eq!(1 << secret2.k() as usize, 2 * (l + t + 1));
+        let d = t + l - 1;
+        let pack_matrix = Self::lagrange_matrix(&secret_points, &share_points);
+        let unpack_matrix = Self::lagrange_matrix(&share_points[..d + 1], &secret_points[..l]);
+        let unpack2_matrix =
+            Self::lagrange_matrix(&share_points[..2 * d + 1], &secret_points[..l]);
 
         PackedSharingParams {
             t,
             l,
             n,
-            share,
-            secret,
-            secret2,
+            share_points,
+            secret_points,
+            pack_matrix,
+            unpack_matrix,
+            unpack2_matrix,
         }
     }
 
+    /// Degree of a fresh sharing
+    pub fn degree(&self) -> usize {
+        self.t + self.l - 1
+    }
+
+    fn powers(start: F, base: F, len: usize) -> Vec<F> {
+        std::iter::successors(Some(start), |p| Some(*p * base))
+            .take(len)
+            .collect()
+    }
+
+    /// Returns the matrix mapping evaluations over `from` to evaluations over `to`
+    /// of the polynomial of degree < from.len() that interpolates them
+    fn lagrange_matrix(from: &[F], to: &[F]) -> Vec<Vec<F>> {
+        // Barycentric weights 1 / prod_{k != i} (x_i - x_k)
+        let weights: Vec<F> = from
+            .iter()
+            .enumerate()
+            .map(|(i, xi)| {
+                from.iter()
+                    .enumerate()
+                    .filter(|(k, _)| *k != i)
+                    .map(|(_, xk)| *xi - xk)
+                    .product::<F>()
+                    .invert()
+                    .unwrap()
+            })
+            .collect();
+
+        to.iter()
+            .map(|y| match from.iter().position(|x| x == y) {
+                Some(i) => (0..from.len())
+                    .map(|k| if k == i { F::ONE } else { F::ZERO })
+                    .collect(),
+                None => {
+                    let numerator: F = from.iter().map(|x| *y - x).product();
+                    from.iter()
+                        .zip(weights.iter())
+                        .map(|(x, w)| numerator * w * (*y - x).invert().unwrap())
+                        .collect()
+                }
+            })
+            .collect()
+    }
+
+    fn apply<T>(matrix: &[Vec<F>], values: &[T]) -> Vec<T>
+    where
+        T: Copy + Sum + Mul<F, Output = T>,
+    {
+        matrix
+            .iter()
+            .map(|row| row.iter().zip(values.iter()).map(|(c, v)| *v * *c).sum())
+            .collect()
+    }
+
     // Custom FFT function using halo2's EvaluationDomain
     pub fn fft(poly: &mut Vec<F>, domain: &EvaluationDomain<F>) -> Vec<F> {
         poly.resize(1 << domain.k(), F::ZERO);
-        let coeff_poly = domain.coeff_from_vec(poly.clone());
-        let lagrange_poly = domain.coeff_to_extended(coeff_poly);
-        lagrange_poly.iter().cloned().collect()
+        let mut evals = poly.clone();
+        best_fft(&mut evals, domain.get_omega(), domain.k());
+        evals
     }
 
     // Custom IFFT function using halo2's EvaluationDomain
@@ -57,53 +137,237 @@ impl<F: PrimeField + WithSmallOrderMulGroup<3>> PackedSharingParams<F> {
         coeff_poly.iter().cloned().collect()
     }
 
-    #[allow(unused)]
-    pub fn pack_from_public(&self, secrets: &Vec<F>) -> Vec<F> {
+    /// Packs the secrets along with t fresh random values, so that any t shares are uniformly distributed
+    pub fn pack<R: RngCore>(&self, secrets: &Vec<F>, rng: &mut R) -> Vec<F> {
         let mut result = secrets.clone();
-        self.pack_from_public_in_place(&mut result);
+        self.pack_in_place(&mut result, rng);
         result
     }
 
+    pub fn pack_in_place<R: RngCore>(&self, secrets: &mut Vec<F>, rng: &mut R) {
+        debug_assert_eq!(secrets.len(), self.l, "Mismatch of size in pack");
+        secrets.resize_with(self.l + self.t, || F::random(&mut *rng));
+        self.pack_from_public_in_place(secrets);
+    }
+
+    /// Packs the secrets with a random polynomial of degree 2d, the shape of a product of two sharings.
+    /// Any 2d + 1 - l shares are uniformly distributed.
+    pub fn pack2<R: RngCore>(&self, secrets: &Vec<F>, rng: &mut R) -> Vec<F> {
+        let shares = self.pack(secrets, rng);
+
+        // Add a random polynomial of degree 2d that vanishes on the secret points
+        let vanishing = self.secret_points[..self.l]
+            .iter()
+            .fold(vec![F::ONE], |acc, x| poly::mul(&acc, &[-*x, F::ONE]));
+        let random: Vec<F> = (0..2 * self.degree() + 1 - self.l)
+            .map(|_| F::random(&mut *rng))
+            .collect();
+        let zero = poly::mul(&vanishing, &random);
+
+        shares
+            .iter()
+            .zip(self.share_points.iter())
+            .map(|(share, x)| *share + poly::eval(&zero, *x))
+            .collect()
+    }
+
+    #[allow(unused)]
+    pub fn pack_from_public(&self, secrets: &Vec<F>) -> Vec<F> {
+        self.packexp_from_public(secrets)
+    }
+
     #[allow(unused)]
     pub fn pack_from_public_in_place(&self, secrets: &mut Vec<F>) {
-        // interpolating on secrets domain
-        Self::ifft(secrets, &self.secret);
-        // evaluate on share domain
-        Self::fft(secrets, &self.share);
+        *secrets = self.packexp_from_public(secrets);
     }
 
     #[allow(unused)]
     pub fn unpack(&self, shares: &Vec<F>) -> Vec<F> {
-        let mut result = shares.clone();
-        self.unpack_in_place(&mut result);
-        result
+        self.unpackexp(shares, false)
     }
 
     #[allow(unused)]
     pub fn unpack2(&self, shares: &Vec<F>) -> Vec<F> {
-        let mut result = shares.clone();
-        self.unpack2_in_place(&mut result);
-        result
+        self.unpackexp(shares, true)
     }
 
     #[allow(unused)]
     pub fn unpack_in_place(&self, shares: &mut Vec<F>) {
-        // interpolating on share domain
-        Self::ifft(shares, &self.share);
-        // evaluate on secrets domain
-        Self::fft(shares, &self.secret);
-        // truncate to remove the randomness
-        shares.truncate(self.l);
+        *shares = self.unpackexp(shares, false);
     }
 
     #[allow(unused)]
     pub fn unpack2_in_place(&self, shares: &mut Vec<F>) {
-        // interpolating on share domain
-        Self::ifft(shares, &self.share);
-        // evaluate on secrets2 domain
-        Self::fft(shares, &self.secret2);
-        // drop alternate elements from shares array and only iterate till 2l as the rest of it is randomness
-        *shares = shares.iter().filter(|&&x| x != F::ZERO).copied().collect();
+        *shares = self.unpackexp(shares, true);
+    }
+
+    /// Packs values of any type that scalars act on linearly, e.g. group elements.
+    /// Takes up to l + t values, missing randomness is taken to be zero.
+    pub fn packexp_from_public<T>(&self, secrets: &[T]) -> Vec<T>
+    where
+        T: Copy + Sum + Mul<F, Output = T>,
+    {
+        debug_assert!(secrets.len() <= self.l + self.t, "Mismatch of size in pack");
+        Self::apply(&self.pack_matrix, secrets)
+    }
+
+    /// Unpacks values of any type that scalars act on linearly, e.g. group elements.
+    /// degree2 shares come from the product of two sharings.
+    pub fn unpackexp<T>(&self, shares: &[T], degree2: bool) -> Vec<T>
+    where
+        T: Copy + Sum + Mul<F, Output = T>,
+    {
+        debug_assert_eq!(shares.len(), self.n, "Mismatch of size in unpack");
+        if degree2 {
+            Self::apply(&self.unpack2_matrix, shares)
+        } else {
+            Self::apply(&self.unpack_matrix, shares)
+        }
+    }
+
+    /// Checks that the shares lie on a polynomial of the expected degree
+    pub fn is_consistent<T>(&self, shares: &[T], degree2: bool) -> bool
+    where
+        T: Copy + Sum + Mul<F, Output = T> + PartialEq,
+    {
+        let d = if degree2 { 2 * self.degree() } else { self.degree() };
+        let extend = Self::lagrange_matrix(&self.share_points[..d + 1], &self.share_points);
+        Self::apply(&extend, &shares[..d + 1]) == shares
+    }
+
+    /// Recovers the secrets when up to max_errors shares are wrong, using Gao's decoding
+    /// algorithm over the share points. Returns the secrets along with the indices of the
+    /// parties whose shares were wrong, or None if the shares cannot be decoded.
+    pub fn robust_unpack(
+        &self,
+        shares: &[F],
+        degree2: bool,
+        max_errors: usize,
+    ) -> Option<(Vec<F>, Vec<usize>)> {
+        debug_assert_eq!(shares.len(), self.n, "Mismatch of size in unpack");
+        let k = if degree2 { 2 * self.degree() + 1 } else { self.degree() + 1 };
+        assert!(
+            k + 2 * max_errors <= self.n,
+            "cannot correct {} errors with {} shares of degree {}",
+            max_errors,
+            self.n,
+            k - 1
+        );
+
+        // g0 = prod (X - x_i) and g1 interpolates the shares
+        let g0 = self
+            .share_points
+            .iter()
+            .fold(vec![F::ONE], |acc, x| poly::mul(&acc, &[-*x, F::ONE]));
+        let g1 = poly::interpolate(&self.share_points, shares, &g0);
+
+        // Partial extended euclid until the remainder has degree < (n + k) / 2
+        let (mut r0, mut r1) = (g0, g1);
+        let (mut v0, mut v1) = (vec![], vec![F::ONE]);
+        while 2 * r1.len() > self.n + k + 1 {
+            let (q, r) = poly::div_rem(&r0, &r1);
+            let v = poly::sub(&v0, &poly::mul(&q, &v1));
+            (r0, r1) = (r1, r);
+            (v0, v1) = (v1, v);
+        }
+
+        // The message is g / v, v vanishes on the faulty shares
+        let (f, r) = poly::div_rem(&r1, &v1);
+        if !r.is_empty() || f.len() > k {
+            return None;
+        }
+
+        let faulty: Vec<usize> = self
+            .share_points
+            .iter()
+            .zip(shares.iter())
+            .enumerate()
+            .filter(|(_, (x, y))| poly::eval(&f, **x) != **y)
+            .map(|(i, _)| i)
+            .collect();
+        if faulty.len() > max_errors {
+            return None;
+        }
+
+        let secrets = self.secret_points[..self.l]
+            .iter()
+            .map(|x| poly::eval(&f, *x))
+            .collect();
+        Some((secrets, faulty))
+    }
+}
+
+/// Dense polynomials in coefficient form, lowest degree first and without trailing zeros
+mod poly {
+    use ff::Field;
+
+    fn trim<F: Field>(mut p: Vec<F>) -> Vec<F> {
+        while p.last() == Some(&F::ZERO) {
+            p.pop();
+        }
+        p
+    }
+
+    pub fn eval<F: Field>(p: &[F], x: F) -> F {
+        p.iter().rev().fold(F::ZERO, |acc, c| acc * x + c)
+    }
+
+    pub fn mul<F: Field>(a: &[F], b: &[F]) -> Vec<F> {
+        if a.is_empty() || b.is_empty() {
+            return vec![];
+        }
+        let mut out = vec![F::ZERO; a.len() + b.len() - 1];
+        for (i, x) in a.iter().enumerate() {
+            for (j, y) in b.iter().enumerate() {
+                out[i + j] += *x * y;
+            }
+        }
+        trim(out)
+    }
+
+    pub fn sub<F: Field>(a: &[F], b: &[F]) -> Vec<F> {
+        let mut out = vec![F::ZERO; a.len().max(b.len())];
+        out.iter_mut().zip(a.iter()).for_each(|(o, x)| *o += x);
+        out.iter_mut().zip(b.iter()).for_each(|(o, y)| *o -= y);
+        trim(out)
+    }
+
+    /// Long division, b must be non zero
+    pub fn div_rem<F: Field>(a: &[F], b: &[F]) -> (Vec<F>, Vec<F>) {
+        let lead_inv = b.last().unwrap().invert().unwrap();
+        let mut r = a.to_vec();
+        if r.len() < b.len() {
+            return (vec![], r);
+        }
+        let mut q = vec![F::ZERO; r.len() - b.len() + 1];
+        for i in (0..q.len()).rev() {
+            let c = r[i + b.len() - 1] * lead_inv;
+            q[i] = c;
+            for (j, y) in b.iter().enumerate() {
+                r[i + j] -= c * y;
+            }
+        }
+        r.truncate(b.len() - 1);
+        (trim(q), trim(r))
+    }
+
+    /// Interpolates the values over the points, vanishing is prod (X - x_i)
+    pub fn interpolate<F: Field>(points: &[F], values: &[F], vanishing: &[F]) -> Vec<F> {
+        let mut out = vec![F::ZERO; points.len()];
+        for (i, (x, y)) in points.iter().zip(values.iter()).enumerate() {
+            let denominator: F = points
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, xj)| *x - xj)
+                .product();
+            let scale = *y * denominator.invert().unwrap();
+            let (basis, _) = div_rem(vanishing, &[-*x, F::ONE]);
+            out.iter_mut()
+                .zip(basis.iter())
+                .for_each(|(o, b)| *o += scale * b);
+        }
+        trim(out)
     }
 }
 
@@ -111,6 +375,7 @@ impl<F: PrimeField + WithSmallOrderMulGroup<3>> PackedSharingParams<F> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ff::Field;
     use halo2_proofs::halo2curves::bn256::Fr as F;
     use PackedSharingParams;
 
@@ -136,9 +401,95 @@ mod tests {
         assert_eq!(pp.t, L - 1);
         assert_eq!(pp.l, L);
         assert_eq!(pp.n, N);
-        assert_eq!(1 << pp.share.k(), N);
-        assert_eq!(1 << pp.secret.k(), L + T + 1);
-        assert_eq!(1 << pp.secret2.k(), 2 * (L + T + 1));
+        assert_eq!(pp.share_points.len(), N);
+        assert_eq!(pp.secret_points.len(), L + T);
+        for x in pp.share_points.iter() {
+            assert!(!pp.secret_points.contains(x));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_initialize_too_few_parties() {
+        PackedSharingParams::<F>::new_with(2 * (L + T - 1), T, L);
+    }
+
+    #[test]
+    fn test_arbitrary_parameters() {
+        let rng = &mut ark_std::test_rng();
+
+        for (n, t, l) in [(10, 2, 3), (8, 3, 1), (13, 4, 3), (7, 0, 4)] {
+            let pp = PackedSharingParams::<F>::new_with(n, t, l);
+            let secrets: Vec<F> = (0..l).map(|_| random_fr(rng)).collect();
+            let expected: Vec<F> = secrets.iter().map(|x| (*x) * (*x)).collect();
+
+            let shares = pp.pack(&secrets, rng);
+            assert!(pp.is_consistent(&shares, false));
+            assert_eq!(secrets, pp.unpack(&shares));
+
+            let shares: Vec<F> = shares.iter().map(|x| (*x) * (*x)).collect();
+            assert!(pp.is_consistent(&shares, true));
+            assert_eq!(expected, pp.unpack2(&shares));
+        }
+    }
+
+    #[test]
+    fn test_robust_unpack() {
+        let rng = &mut ark_std::test_rng();
+
+        for (n, t, l) in [(N, T, L), (13, 2, 3)] {
+            let pp = PackedSharingParams::<F>::new_with(n, t, l);
+            let secrets: Vec<F> = (0..l).map(|_| random_fr(rng)).collect();
+            let shares = pp.pack(&secrets, rng);
+            let max_errors = (n - pp.degree() - 1) / 2;
+
+            let (unpacked, faulty) = pp.robust_unpack(&shares, false, max_errors).unwrap();
+            assert_eq!(secrets, unpacked);
+            assert!(faulty.is_empty());
+
+            let mut corrupted = shares.clone();
+            let expected_faulty: Vec<usize> = (0..max_errors).map(|i| (5 * i + 2) % n).collect();
+            for i in expected_faulty.iter() {
+                corrupted[*i] += random_fr(rng);
+            }
+            let (unpacked, mut faulty) = pp.robust_unpack(&corrupted, false, max_errors).unwrap();
+            faulty.sort();
+            let mut expected_faulty = expected_faulty;
+            expected_faulty.sort();
+            assert_eq!(secrets, unpacked);
+            assert_eq!(expected_faulty, faulty);
+
+            // One more error than can be corrected
+            corrupted[(5 * max_errors + 2) % n] += random_fr(rng);
+            assert!(pp.robust_unpack(&corrupted, false, max_errors).is_none());
+        }
+    }
+
+    #[test]
+    fn test_robust_unpack2() {
+        let pp = PackedSharingParams::<F>::new_with(20, 2, 3);
+
+        let rng = &mut ark_std::test_rng();
+        let secrets: Vec<F> = (0..3).map(|_| random_fr(rng)).collect();
+        let expected: Vec<F> = secrets.iter().map(|x| (*x) * (*x)).collect();
+        let mut shares: Vec<F> = pp.pack(&secrets, rng).iter().map(|x| (*x) * (*x)).collect();
+        shares[7] += F::ONE;
+
+        let (unpacked, faulty) = pp.robust_unpack(&shares, true, 2).unwrap();
+        assert_eq!(expected, unpacked);
+        assert_eq!(vec![7], faulty);
+    }
+
+    #[test]
+    fn test_inconsistent_shares() {
+        let pp = PackedSharingParams::<F>::new(L);
+
+        let rng = &mut ark_std::test_rng();
+        let secrets: Vec<F> = (0..L).map(|_| random_fr(rng)).collect();
+        let mut shares = pp.pack(&secrets, rng);
+        shares[N - 1] += F::ONE;
+
+        assert!(!pp.is_consistent(&shares, false));
     }
 
     #[test]
@@ -160,6 +511,83 @@ mod tests {
         assert_eq!(expected, secrets);
     }
 
+    #[test]
+    fn test_pack() {
+        let pp = PackedSharingParams::<F>::new(L);
+
+        let rng = &mut ark_std::test_rng();
+        let secrets: Vec<F> = (0..L).map(|_| random_fr(rng)).collect();
+
+        let shares = pp.pack(&secrets, rng);
+        assert_eq!(secrets, pp.unpack(&shares));
+        assert_ne!(shares, pp.pack(&secrets, rng));
+    }
+
+    #[test]
+    fn test_pack2() {
+        let pp = PackedSharingParams::<F>::new(L);
+
+        let rng = &mut ark_std::test_rng();
+        let secrets: Vec<F> = (0..L).map(|_| random_fr(rng)).collect();
+
+        let shares = pp.pack2(&secrets, rng);
+        assert_eq!(secrets, pp.unpack2(&shares));
+        assert!(pp.is_consistent(&shares, true));
+        assert!(!pp.is_consistent(&shares, false));
+    }
+
+    #[test]
+    fn test_pack_hides_secrets() {
+        let pp = PackedSharingParams::<F>::new(L);
+
+        // Shares are a linear function of secrets and randomness. Any T shares are uniformly
+        // distributed when the randomness alone maps onto all of them, i.e. the map has rank T.
+        let randomness_map: Vec<Vec<F>> = (L..L + T)
+            .map(|i| {
+                let mut unit = vec![F::ZERO; L + T];
+                unit[i] = F::ONE;
+                pp.pack_from_public(&unit)
+            })
+            .collect();
+
+        let subsets: Vec<Vec<usize>> = vec![
+            (0..T).collect(),
+            (N - T..N).collect(),
+            (0..N).step_by(N / T).take(T).collect(),
+            (0..T).map(|i| (3 * i + 1) % N).collect(),
+        ];
+        for parties in subsets {
+            let rows: Vec<Vec<F>> = randomness_map
+                .iter()
+                .map(|shares| parties.iter().map(|&p| shares[p]).collect())
+                .collect();
+            assert_eq!(rank(rows), T);
+        }
+    }
+
+    // Rank of a matrix by gaussian elimination
+    fn rank(mut rows: Vec<Vec<F>>) -> usize {
+        let mut rank = 0;
+        for col in 0..rows[0].len() {
+            if let Some(pivot) = (rank..rows.len()).find(|&r| rows[r][col] != F::ZERO) {
+                rows.swap(rank, pivot);
+                let inv = rows[rank][col].invert().unwrap();
+                for r in 0..rows.len() {
+                    if r != rank {
+                        let factor = rows[r][col] * inv;
+                        let pivot_row = rows[rank].clone();
+                        rows[r]
+                            .iter_mut()
+                            .zip(pivot_row.iter())
+                            .for_each(|(x, y)| *x -= factor * y);
+                    }
+                }
+                rank += 1;
+            }
+        }
+        rank
+    }
+
     #[test]
     fn test_multiplication() {
         let pp = PackedSharingParams::<F>::new(L);